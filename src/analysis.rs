@@ -0,0 +1,2061 @@
+//! What can be worked out from the items of a graph: cycles, components,
+//! paths, orders and the edges that matter or don't, along with what the
+//! commands that check, rank and compare graphs report of them.
+//!
+//! None of the analyses recurse, and those that would need memory in the
+//! square of the size of the graph search it instead once it is large, so
+//! they go as deep and as wide as the graph does. Generated graphs often
+//! take the shape of a chain a million items long, or of an item with a
+//! million dependencies, and every analysis is tested on both by a test
+//! left out of the default run; `cargo test --release -- --ignored` runs it.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::time::Instant;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+use crate::csr::Csr;
+use crate::error::DepsError;
+use crate::graph::{Item, Loader, Node, check_deps};
+use crate::parallel;
+use crate::report::Progress;
+use crate::traverse::{Ready, Topological};
+
+/// Finds a cycle of two or more items, self-loops are left to
+/// `find_self_loops`. The cycle is returned as the path of items going
+/// round it, where each item depends on the next and the last depends on
+/// the first. The edges in `undirected` were read from undirected graphs
+/// and have a twin going the other way, so walking straight back along one
+/// to the node we came from is not counted as a cycle. Fails when a
+/// dependency is not the index of one of the items.
+///
+/// The first item is not repeated at the end of the path:
+///
+/// ```
+/// use std::collections::HashSet;
+/// use depsys::{analysis::detect_cycle, csr::Csr, graph::GraphBuilder};
+///
+/// let mut graph = GraphBuilder::new(false);
+/// for (from, to) in [("app", "lib"), ("lib", "util"), ("util", "lib")] {
+///     graph.add_edge_by_name(from, to)?;
+/// }
+/// assert_eq!(detect_cycle(&Csr::forward(&graph.build()), &HashSet::new())?, Some(vec![1, 2]));
+/// # Ok::<(), depsys::error::DepsError>(())
+/// ```
+pub fn detect_cycle(graph: &Csr, undirected: &HashSet<(usize, usize)>) -> Result<Option<Vec<usize>>, DepsError> {
+    graph.check()?;
+    Ok(find_cycle(graph, undirected))
+}
+
+/// Does the work of `detect_cycle` on a graph known to be well formed.
+fn find_cycle(graph: &Csr, undirected: &HashSet<(usize, usize)>) -> Option<Vec<usize>> {
+    let started = Instant::now();
+    let mut progress = Progress::new("Looking for cycles", "nodes", Some(graph.len()));
+    let (found, restarts) = search_cycle(graph, |a, b| undirected.contains(&(a, b)), &mut progress);
+    let outcome = if found.is_some() { "a" } else { "no" };
+    log::debug!(
+        "Found {} cycle in {:.1?}, after {} depth-first searches", outcome, started.elapsed(), restarts,
+    );
+    found.map(|(_, cycle)| cycle)
+}
+
+/// Searches the graph for a cycle depth first, starting again from every
+/// item no search has reached yet. Returns the cycle found along with the
+/// item its search started from, and how many searches there were,
+/// counting the items searched in `progress`. `undirected` tells the edges
+/// read from undirected graphs.
+pub(crate) fn search_cycle(
+    graph: &Csr,
+    undirected: impl Fn(usize, usize) -> bool,
+    progress: &mut Progress,
+) -> (Option<(usize, Vec<usize>)>, usize) {
+    let mut marks = vec![Mark::Unvisited; graph.len()];
+    // The path being searched: each item on it, the next of its edges to
+    // follow and the item it was reached from.
+    let mut stack: Vec<(usize, usize, Option<usize>)> = Vec::new();
+    // How many times the search started again from an unvisited item.
+    let mut restarts = 0;
+
+    for start in 0..graph.len() {
+        if marks[start] != Mark::Unvisited {
+            continue;
+        }
+
+        restarts += 1;
+        marks[start] = Mark::OnPath;
+        progress.add(1);
+        stack.push((start, 0, None));
+
+        while let Some((node, dep_index, parent)) = stack.last_mut() {
+            let Some(&next) = graph.edges(*node).get(*dep_index) else {
+                marks[*node] = Mark::Done;
+                stack.pop();
+                continue;
+            };
+            *dep_index += 1;
+            let (node, next) = (*node, next as usize);
+            let back = *parent == Some(next) && undirected(next, node) && undirected(node, next);
+            if next == node || back {
+                continue;
+            }
+            match marks[next] {
+                Mark::Unvisited => {
+                    marks[next] = Mark::OnPath;
+                    progress.add(1);
+                    stack.push((next, 0, Some(node)));
+                },
+                Mark::OnPath => {
+                    let position = stack.iter().position(|&(index, _, _)| index == next).expect("items on the path are on the stack");
+                    let cycle = stack[position..].iter().map(|&(index, _, _)| index).collect();
+                    return (Some((start, cycle)), restarts);
+                },
+                // Everything reachable from it has been searched and led
+                // to no cycle.
+                Mark::Done => {},
+            }
+        }
+    }
+
+    (None, restarts)
+}
+
+/// How far the search for a cycle has got with an item.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Unvisited,
+    /// On the path being searched.
+    OnPath,
+    /// Left behind, with everything reachable from it.
+    Done,
+}
+
+/// Splits the items into strongly connected components using Tarjan's
+/// algorithm. Components come out dependencies first: no component depends
+/// on one that comes after it.
+pub fn strongly_connected_components(graph: &Csr) -> Vec<Vec<usize>> {
+    tarjan(graph).into_iter().map(|(_, members)| members).collect()
+}
+
+/// The components of `strongly_connected_components`, each with the item
+/// the search that found it started from.
+pub(crate) fn tarjan(graph: &Csr) -> Vec<(usize, Vec<usize>)> {
+    const UNVISITED: usize = usize::MAX;
+    let mut order = vec![UNVISITED; graph.len()];
+    let mut low = vec![0; graph.len()];
+    let mut on_stack = vec![false; graph.len()];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next = 0;
+
+    for root in 0..graph.len() {
+        if order[root] != UNVISITED {
+            continue;
+        }
+
+        let mut work = vec![(root, 0)];
+        while let Some((node, dep_index)) = work.pop() {
+            if dep_index == 0 {
+                order[node] = next;
+                low[node] = next;
+                next += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+
+            let deps = graph.edges(node);
+            if dep_index < deps.len() {
+                work.push((node, dep_index + 1));
+                let dep = deps[dep_index] as usize;
+                if order[dep] == UNVISITED {
+                    work.push((dep, 0));
+                } else if on_stack[dep] {
+                    low[node] = low[node].min(order[dep]);
+                }
+                continue;
+            }
+
+            if low[node] == order[node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.reverse();
+                components.push((root, component));
+            }
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+        }
+    }
+
+    components
+}
+
+/// Lists the elementary cycles of the graph with Johnson's algorithm, each
+/// as the path from its smallest index around to the item that closes it.
+/// Stops after `limit` cycles when there is one, returning whether it did.
+pub fn elementary_cycles(graph: &Csr, limit: Option<usize>) -> (Vec<Vec<usize>>, bool) {
+    let components = strongly_connected_components(graph);
+    let mut component = vec![0; graph.len()];
+    for (number, members) in components.iter().enumerate() {
+        for &member in members {
+            component[member] = number;
+        }
+    }
+
+    let mut cycles = Vec::new();
+    let mut blocked = vec![false; graph.len()];
+    let mut blocked_by = vec![Vec::new(); graph.len()];
+    for start in 0..graph.len() {
+        let members = &components[component[start]];
+        if members.len() == 1 && !graph.targets(start).any(|dep| dep == start) {
+            continue;
+        }
+        // Only look at the rest of the component, the cycles through
+        // smaller indices were all found from those.
+        let allowed = |index: usize| index >= start && component[index] == component[start];
+        for &member in members {
+            blocked[member] = false;
+            blocked_by[member].clear();
+        }
+
+        blocked[start] = true;
+        let mut path = vec![start];
+        let mut stack = vec![(start, 0, false)];
+        while let Some(&mut (node, ref mut dep_index, ref mut found)) = stack.last_mut() {
+            if let Some(&dep) = graph.edges(node).get(*dep_index) {
+                let dep = dep as usize;
+                *dep_index += 1;
+                if !allowed(dep) {
+                    continue;
+                }
+                if dep == start {
+                    *found = true;
+                    cycles.push(path.clone());
+                    if limit.is_some_and(|limit| cycles.len() >= limit) {
+                        return (cycles, true);
+                    }
+                } else if !blocked[dep] {
+                    blocked[dep] = true;
+                    path.push(dep);
+                    stack.push((dep, 0, false));
+                }
+                continue;
+            }
+
+            let found = *found;
+            stack.pop();
+            path.pop();
+            if found {
+                let mut unblock = vec![node];
+                while let Some(index) = unblock.pop() {
+                    if blocked[index] {
+                        blocked[index] = false;
+                        unblock.append(&mut blocked_by[index]);
+                    }
+                }
+            } else {
+                for dep in graph.targets(node) {
+                    if allowed(dep) && !blocked_by[dep].contains(&node) {
+                        blocked_by[dep].push(node);
+                    }
+                }
+            }
+            if let Some(parent) = stack.last_mut() {
+                parent.2 |= found;
+            }
+        }
+    }
+
+    (cycles, false)
+}
+
+/// Returns a shortest path from `from` to `to` following dependencies,
+/// including both ends, that doesn't take the edge `skip`.
+pub fn find_path<T>(items: &[Item<T>], from: usize, to: usize, skip: Option<(usize, usize)>) -> Option<Vec<usize>> {
+    let mut previous = vec![None; items.len()];
+    let mut queue = VecDeque::from([from]);
+    while let Some(index) = queue.pop_front() {
+        if index == to {
+            let mut path = vec![to];
+            while let Some(prev) = previous[*path.last().unwrap()] {
+                path.push(prev);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for &dep in &items[index].deps {
+            if dep != from && previous[dep].is_none() && skip != Some((index, dep)) {
+                previous[dep] = Some(index);
+                queue.push_back(dep);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the paths from `from` to `to` that visit no item twice, up to
+/// `limit` of them, shortest first.
+pub fn simple_paths<T>(items: &[Item<T>], from: usize, to: usize, limit: Option<usize>) -> Vec<Vec<usize>> {
+    let mut paths = Vec::new();
+    let mut path = vec![from];
+    let mut on_path = vec![false; items.len()];
+    on_path[from] = true;
+    let mut stack = vec![0];
+    if from == to {
+        return vec![path];
+    }
+
+    while let Some(dep_index) = stack.last_mut() {
+        let node = *path.last().unwrap();
+        let Some(&dep) = items[node].deps.get(*dep_index) else {
+            stack.pop();
+            on_path[node] = false;
+            path.pop();
+            continue;
+        };
+        *dep_index += 1;
+        if dep == to {
+            let mut found = path.clone();
+            found.push(to);
+            paths.push(found);
+            if limit.is_some_and(|limit| paths.len() >= limit) {
+                break;
+            }
+        } else if !on_path[dep] {
+            on_path[dep] = true;
+            path.push(dep);
+            stack.push(0);
+        }
+    }
+
+    paths.sort_by_key(|path| path.len());
+    paths
+}
+
+/// Returns the items reachable from any of `starts` by following the edges
+/// of `graph`, nearest first and without the starts themselves, going at
+/// most `depth` edges deep. Items reached from more than one start are
+/// listed once.
+pub fn reachable(graph: &Csr, starts: &[usize], depth: Option<usize>) -> Vec<usize> {
+    let mut seen = vec![false; graph.len()];
+    for &start in starts {
+        seen[start] = true;
+    }
+    let mut found = Vec::new();
+    let mut queue: VecDeque<_> = starts.iter().map(|&start| (start, 0)).collect();
+    while let Some((index, distance)) = queue.pop_front() {
+        if depth.is_some_and(|depth| distance >= depth) {
+            continue;
+        }
+        for other in graph.targets(index) {
+            if !std::mem::replace(&mut seen[other], true) {
+                found.push(other);
+                queue.push_back((other, distance + 1));
+            }
+        }
+    }
+    found
+}
+
+/// Splits the items into weakly connected components, where edges connect
+/// items whichever way they go, using union-find. Components come out
+/// largest first with their members in input order.
+pub fn weak_components<T>(items: &[Item<T>]) -> Vec<Vec<usize>> {
+    fn root(parent: &mut [usize], mut index: usize) -> usize {
+        while parent[index] != index {
+            parent[index] = parent[parent[index]];
+            index = parent[index];
+        }
+        index
+    }
+
+    let mut parent: Vec<_> = (0..items.len()).collect();
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            let (a, b) = (root(&mut parent, a), root(&mut parent, b));
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut components: Vec<Vec<usize>> = Vec::new();
+    let mut numbers = HashMap::new();
+    for index in 0..items.len() {
+        let root = root(&mut parent, index);
+        let number = *numbers.entry(root).or_insert_with(|| {
+            components.push(Vec::new());
+            components.len() - 1
+        });
+        components[number].push(index);
+    }
+    components.sort_by_key(|members| Reverse(members.len()));
+    components
+}
+
+/// The articulation points and bridges of the graph taken as undirected,
+/// found with Tarjan's low-link algorithm: the items and the edges whose
+/// removal splits a component. Each comes with how many items that cuts off
+/// from the largest part left of its component. Self-loops are ignored and
+/// edges going both ways count as one.
+pub fn bottlenecks<T>(items: &[Item<T>]) -> Bottlenecks {
+    let mut neighbors = vec![Vec::new(); items.len()];
+    for (a, item) in items.iter().enumerate() {
+        for &b in item.deps.iter().filter(|&&b| b != a) {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+        }
+    }
+    for neighbors in &mut neighbors {
+        neighbors.sort_unstable();
+        neighbors.dedup();
+    }
+
+    const UNVISITED: usize = usize::MAX;
+    let mut order = vec![UNVISITED; items.len()];
+    let mut low = vec![0; items.len()];
+    let mut size = vec![1; items.len()];
+    // The sizes of the subtrees that removing the item would cut off.
+    let mut cut_off = vec![Vec::new(); items.len()];
+    let mut points = Vec::new();
+    let mut bridges = Vec::new();
+    let mut next = 0;
+
+    for root in 0..items.len() {
+        if order[root] != UNVISITED {
+            continue;
+        }
+
+        let mut visited = Vec::new();
+        let mut tree_edges = Vec::new();
+        let mut work = vec![(root, 0)];
+        while let Some((node, neighbor_index)) = work.pop() {
+            if neighbor_index == 0 {
+                order[node] = next;
+                low[node] = next;
+                next += 1;
+                visited.push(node);
+            }
+
+            let parent = work.last().map(|&(parent, _)| parent);
+            if neighbor_index < neighbors[node].len() {
+                work.push((node, neighbor_index + 1));
+                let neighbor = neighbors[node][neighbor_index];
+                if order[neighbor] == UNVISITED {
+                    work.push((neighbor, 0));
+                } else if Some(neighbor) != parent {
+                    low[node] = low[node].min(order[neighbor]);
+                }
+                continue;
+            }
+
+            if let Some(parent) = parent {
+                low[parent] = low[parent].min(low[node]);
+                size[parent] += size[node];
+                if low[node] >= order[parent] {
+                    cut_off[parent].push(size[node]);
+                }
+                if low[node] > order[parent] {
+                    tree_edges.push((parent, node));
+                }
+            }
+        }
+
+        let total = size[root];
+        for node in visited {
+            let mut parts = cut_off[node].clone();
+            if node != root {
+                parts.push(total - 1 - parts.iter().sum::<usize>());
+            }
+            if parts.iter().filter(|&&part| part > 0).count() > 1 {
+                points.push((node, total - 1 - parts.iter().max().unwrap_or(&0)));
+            }
+        }
+        for (parent, node) in tree_edges {
+            bridges.push((parent, node, size[node].min(total - size[node])));
+        }
+    }
+
+    Bottlenecks { points, bridges }
+}
+
+/// What `bottlenecks` finds, each with how many items it cuts off.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Bottlenecks {
+    /// The articulation points.
+    pub points: Vec<(usize, usize)>,
+    /// The bridges, as the two items they join.
+    pub bridges: Vec<(usize, usize, usize)>,
+}
+
+/// Returns every item that lists itself among its own dependencies.
+pub fn find_self_loops<T>(items: &[Item<T>]) -> Vec<usize> {
+    (0..items.len()).filter(|&index| items[index].deps.contains(&index)).collect()
+}
+
+/// Returns the items that depend on each item, the edges turned around.
+pub fn dependents<T>(items: &[Item<T>]) -> Vec<Vec<usize>> {
+    let mut dependents = vec![Vec::new(); items.len()];
+    for (index, item) in items.iter().enumerate() {
+        for &dep_index in &item.deps {
+            dependents[dep_index].push(index);
+        }
+    }
+    dependents
+}
+
+/// The fan-out and fan-in of every item: how many dependencies it has and
+/// how many items depend on it. The loader keeps a single copy of each edge,
+/// so repeated edges in the input are only counted once.
+pub fn degrees<T>(items: &[Item<T>]) -> (Vec<usize>, Vec<usize>) {
+    let fan_out = items.iter().map(|item| item.deps.len()).collect();
+    let mut fan_in = vec![0; items.len()];
+    for item in items {
+        for &dep in &item.deps {
+            fan_in[dep] += 1;
+        }
+    }
+    (fan_out, fan_in)
+}
+
+/// Orders the items so that every item comes after its dependencies,
+/// returning their indices in that order. Alongside each is its level, the
+/// wave of the sort in which it became ready: level 0 items have no
+/// dependencies, and the items of one level only depend on items of lower
+/// levels. With `priorities`, the ready item with the highest priority goes
+/// first, and ties go to the smallest. Fails when a dependency is not the
+/// index of one of the items, or there isn't one priority for every item,
+/// and rather than leave any out when some items could not be placed, being
+/// on or behind a cycle:
+///
+/// ```
+/// use depsys::analysis::sort_items;
+/// use depsys::error::DepsError;
+/// use depsys::graph::GraphBuilder;
+///
+/// let mut graph = GraphBuilder::new(false);
+/// for (from, to) in [("a", "b"), ("b", "c"), ("c", "a"), ("a", "d"), ("e", "d")] {
+///     graph.add_edge_by_name(from, to)?;
+/// }
+/// let items = graph.build();
+/// match sort_items(&items, false, None) {
+///     Err(DepsError::Unsorted { nodes }) => assert_eq!(nodes, ["a", "b", "c"]),
+///     other => panic!("expected the cycle to be reported, got {:?}", other.map(|(sorted, _)| sorted)),
+/// }
+/// # Ok::<(), DepsError>(())
+/// ```
+pub fn sort_items<T: Ord + fmt::Display>(
+    items: &[Item<T>],
+    stable: bool,
+    priorities: Option<&[f64]>,
+) -> Result<(Vec<usize>, Vec<usize>), DepsError> {
+    check_deps(items)?;
+    if let Some(priorities) = priorities.filter(|priorities| priorities.len() != items.len()) {
+        return Err(DepsError::Usage(format!("Got {} priorities for {} items", priorities.len(), items.len())));
+    }
+    let (sorted_indices, levels) = match priorities {
+        Some(priorities) => sort_ready(items, Ready::Highest(ranks(priorities), BinaryHeap::new())),
+        None => sort_order(items, stable),
+    };
+    if sorted_indices.len() != items.len() {
+        let mut placed = vec![false; items.len()];
+        for &index in &sorted_indices {
+            placed[index] = true;
+        }
+        let nodes = (0..items.len()).filter(|&index| !placed[index]).map(|index| items[index].data.to_string()).collect();
+        return Err(DepsError::Unsorted { nodes });
+    }
+    let sorted_levels = sorted_indices.iter().map(|&index| levels[index]).collect();
+    Ok((sorted_indices, sorted_levels))
+}
+
+/// Numbers the values from 0 up in increasing order, equal values getting
+/// the same number, so a heap can order them as integers.
+pub fn ranks(values: &[f64]) -> Vec<usize> {
+    let mut distinct = values.to_vec();
+    distinct.sort_by(f64::total_cmp);
+    distinct.dedup();
+    values.iter().map(|value| distinct.partition_point(|other| other < value)).collect()
+}
+
+/// Does the work of `sort_items`, returning the sorted indices and the
+/// level of every item by its index. Items on a cycle are left out. With
+/// `stable`, ties between items that are ready at the same time are broken
+/// by their order rather than by where they appear in the input.
+pub fn sort_order<T: Ord>(items: &[Item<T>], stable: bool) -> (Vec<usize>, Vec<usize>) {
+    let queue = if stable { Ready::Smallest(BinaryHeap::new()) } else { Ready::InOrder(VecDeque::new()) };
+    sort_ready(items, queue)
+}
+
+/// Sorts the items, taking each next one from `queue` among those whose
+/// dependencies have all been taken.
+fn sort_ready<'a, T: Ord>(items: &'a [Item<T>], queue: Ready<'a, T>) -> (Vec<usize>, Vec<usize>) {
+    let started = Instant::now();
+    let mut order = Topological::new(items, queue, false);
+    let mut progress = Progress::new("Sorting", "nodes", Some(items.len()));
+    let sorted_indices: Vec<_> = order.by_ref().inspect(|_| progress.add(1)).collect();
+    drop(progress);
+
+    log::debug!("Sorted {} of {} nodes in {:.1?}", sorted_indices.len(), items.len(), started.elapsed());
+    log::trace!(
+        "The sort queue held up to {} ready nodes, {} nodes are on or behind a cycle",
+        order.widest(), items.len() - sorted_indices.len(),
+    );
+    (sorted_indices, order.levels().to_vec())
+}
+
+/// For every item, the number of edges in the longest chain of dependencies
+/// starting from it and the dependency that chain goes through next, given
+/// the items sorted dependencies first. Ties go to the earlier dependency.
+pub fn longest_chains<T>(items: &[Item<T>], order: &[usize]) -> Vec<(usize, Option<usize>)> {
+    let mut chains = vec![(0, None); items.len()];
+    for &index in order {
+        for &dep in &items[index].deps {
+            if chains[index].1.is_none() || chains[dep].0 + 1 > chains[index].0 {
+                chains[index] = (chains[dep].0 + 1, Some(dep));
+            }
+        }
+    }
+    chains
+}
+
+/// A node of a simulated schedule, running on `worker` from `start` until
+/// `end`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Slot {
+    pub index: usize,
+    pub worker: usize,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Simulates list scheduling of the items on `workers` workers, given the
+/// items sorted dependencies first. Whenever a worker is free it takes the
+/// ready item with the costliest chain of dependents still to come, ties
+/// going to the smallest name. Returns the slots in the order they
+/// started, along with the longest of those chains, which no number of
+/// workers can beat.
+pub fn simulate_schedule(items: &[Item<Node>], order: &[usize], costs: &[f64], workers: usize) -> (Vec<Slot>, f64) {
+    let dependents = dependents(items);
+    let mut tails = vec![0.0; items.len()];
+    for &index in order.iter().rev() {
+        tails[index] = costs[index] + dependents[index].iter().map(|&dependent| tails[dependent]).fold(0.0, f64::max);
+    }
+    let critical = tails.iter().copied().fold(0.0, f64::max);
+
+    let ranks = ranks(&tails);
+    let mut in_degree: Vec<_> = items.iter().map(|item| item.deps.len()).collect();
+    let mut ready: BinaryHeap<_> = (0..items.len())
+        .filter(|&index| in_degree[index] == 0)
+        .map(|index| (ranks[index], Reverse((&items[index].data, index))))
+        .collect();
+    let mut running: Vec<Option<(f64, usize)>> = vec![None; workers];
+    let mut slots = Vec::new();
+    let mut now = 0.0;
+    loop {
+        for (worker, job) in running.iter_mut().enumerate() {
+            if job.is_some() {
+                continue;
+            }
+            let Some((_, Reverse((_, index)))) = ready.pop() else { break };
+            let end = now + costs[index];
+            *job = Some((end, index));
+            slots.push(Slot { index, worker, start: now, end });
+        }
+        let Some(next) = running.iter().flatten().map(|&(end, _)| end).min_by(f64::total_cmp) else {
+            break;
+        };
+        now = next;
+        for job in &mut running {
+            let Some((end, index)) = *job else { continue };
+            if end > now {
+                continue;
+            }
+            *job = None;
+            for &dependent in &dependents[index] {
+                in_degree[dependent] -= 1;
+                if in_degree[dependent] == 0 {
+                    ready.push((ranks[dependent], Reverse((&items[dependent].data, dependent))));
+                }
+            }
+        }
+    }
+    (slots, critical)
+}
+
+/// Reads the cost of every item from the numeric attribute `attr`, which
+/// is 1 for the items that don't set it.
+pub fn costs(items: &[Item<Node>], attr: &str) -> Result<Vec<f64>, DepsError> {
+    items.iter()
+        .map(|item| match item.attribute(attr) {
+            None => Ok(1.0),
+            Some(value) => value.parse::<f64>().ok().filter(|cost| cost.is_finite() && *cost >= 0.0).ok_or_else(|| {
+                DepsError::Attribute {
+                    node: item.to_string(),
+                    key: attr.to_string(),
+                    value: value.to_string(),
+                    expected: "a number of at least 0",
+                }
+            }),
+        })
+        .collect()
+}
+
+/// Like `longest_chains`, but weighing chains by the total weight of their
+/// items rather than by their number of edges.
+pub fn heaviest_chains<T>(items: &[Item<T>], order: &[usize], weights: &[f64]) -> Vec<(f64, Option<usize>)> {
+    let mut chains = vec![(0.0, None); items.len()];
+    for &index in order {
+        chains[index].0 = weights[index];
+        for &dep in &items[index].deps {
+            if chains[index].1.is_none() || weights[index] + chains[dep].0 > chains[index].0 {
+                chains[index] = (weights[index] + chains[dep].0, Some(dep));
+            }
+        }
+    }
+    chains
+}
+
+/// The chain of dependencies with the highest total weight in a graph
+/// without cycles, from the item that starts it, along with the items
+/// that have no slack, every one of them on a chain as heavy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriticalPath {
+    pub path: Vec<usize>,
+    pub total: f64,
+    /// In input order.
+    pub slack_free: Vec<usize>,
+}
+
+/// Finds the critical path of the items weighed by `weights`, given them
+/// sorted dependencies first, or nothing when there are no items. Of the
+/// heaviest chains, the one starting with the first item in input order is
+/// taken.
+pub fn critical_path<T>(items: &[Item<T>], order: &[usize], weights: &[f64]) -> Option<CriticalPath> {
+    let chains = heaviest_chains(items, order, weights);
+    let start = (0..items.len()).rev().max_by(|&a, &b| chains[a].0.total_cmp(&chains[b].0))?;
+    let total = chains[start].0;
+    let mut path = vec![start];
+    while let Some(next) = chains[*path.last().unwrap()].1 {
+        path.push(next);
+    }
+
+    // An item has no slack when the heaviest chain through it is as
+    // heavy as the critical path, adding the heaviest chain of
+    // dependents leading to it to the one leaving it.
+    let dependents = dependents(items);
+    let mut arriving = vec![0.0; items.len()];
+    for &index in order.iter().rev() {
+        arriving[index] = weights[index]
+            + dependents[index].iter().map(|&dependent| arriving[dependent]).fold(0.0, f64::max);
+    }
+    let tolerance = 1e-9 * total.max(1.0);
+    let slack_free = (0..items.len())
+        .filter(|&index| arriving[index] + chains[index].0 - weights[index] >= total - tolerance)
+        .collect();
+    Some(CriticalPath { path, total, slack_free })
+}
+
+/// Finds the edges of a graph without cycles that another path already
+/// implies, like `a -> c` next to `a -> b -> c`, given the items sorted
+/// dependencies first. Removing them all gives the transitive reduction.
+pub fn redundant_edges<T>(items: &[Item<T>], order: &[usize]) -> Vec<(usize, usize)> {
+    if !Closure::fits(items.len()) {
+        let mut redundant = reached_through_others(items.len(), |index| &items[index].deps);
+        redundant.sort_unstable();
+        return redundant;
+    }
+    let words = items.len().div_ceil(64);
+    // The items reachable from each item, as bitsets.
+    let mut reachable = vec![vec![0u64; words]; items.len()];
+    let mut redundant = Vec::new();
+    for &index in order {
+        let mut through_deps = vec![0u64; words];
+        for &dep in &items[index].deps {
+            for (word, dep_word) in through_deps.iter_mut().zip(&reachable[dep]) {
+                *word |= dep_word;
+            }
+        }
+        for &dep in &items[index].deps {
+            if through_deps[dep / 64] & (1 << (dep % 64)) != 0 {
+                redundant.push((index, dep));
+            }
+            through_deps[dep / 64] |= 1 << (dep % 64);
+        }
+        reachable[index] = through_deps;
+    }
+    redundant.sort_unstable();
+    redundant
+}
+
+/// Picks edges whose removal leaves the graph without cycles, with the
+/// heuristic of Eades, Lin and Smyth. Items are laid out by repeatedly
+/// moving items without dependencies to the back, items without dependents
+/// to the front, or else the item with the most dependencies over dependents
+/// to the front, and the edges pointing backwards are cut. Cut edges that
+/// no longer close a cycle are put back afterwards. Self-loops are always
+/// cut.
+pub fn feedback_edges<T>(items: &[Item<T>]) -> Vec<(usize, usize)> {
+    let dependents = dependents(items);
+    let mut out_degree: Vec<_> = items.iter().enumerate()
+        .map(|(index, item)| item.deps.iter().filter(|&&dep| dep != index).count())
+        .collect();
+    let mut in_degree: Vec<_> = dependents.iter().enumerate()
+        .map(|(index, dependents)| dependents.iter().filter(|&&dependent| dependent != index).count())
+        .collect();
+    let mut placed = vec![false; items.len()];
+    let mut sinks: Vec<_> = (0..items.len()).filter(|&index| out_degree[index] == 0).collect();
+    let mut sources: Vec<_> = (0..items.len()).filter(|&index| in_degree[index] == 0).collect();
+    let (mut front, mut back) = (Vec::new(), Vec::new());
+
+    while front.len() + back.len() < items.len() {
+        let node = if let Some(node) = sinks.pop() {
+            if placed[node] {
+                continue;
+            }
+            back.push(node);
+            node
+        } else if let Some(node) = sources.pop() {
+            if placed[node] {
+                continue;
+            }
+            front.push(node);
+            node
+        } else {
+            let node = (0..items.len())
+                .filter(|&index| !placed[index])
+                .max_by_key(|&index| (out_degree[index] as isize - in_degree[index] as isize, Reverse(index)))
+                .unwrap();
+            front.push(node);
+            node
+        };
+        placed[node] = true;
+        for &dep in items[node].deps.iter().filter(|&&dep| !placed[dep]) {
+            in_degree[dep] -= 1;
+            if in_degree[dep] == 0 {
+                sources.push(dep);
+            }
+        }
+        for &dependent in dependents[node].iter().filter(|&&dependent| !placed[dependent]) {
+            out_degree[dependent] -= 1;
+            if out_degree[dependent] == 0 {
+                sinks.push(dependent);
+            }
+        }
+    }
+
+    let mut position = vec![0; items.len()];
+    for (at, &index) in front.iter().chain(back.iter().rev()).enumerate() {
+        position[index] = at;
+    }
+    let mut cut = Vec::new();
+    let mut kept: Vec<_> = items.iter().map(|item| Item::new((), item.deps.clone())).collect();
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            if position[a] >= position[b] {
+                cut.push((a, b));
+                kept[a].deps.retain(|&dep| dep != b);
+            }
+        }
+    }
+    cut.retain(|&(a, b)| {
+        if a == b || find_path(&kept, b, a, None).is_some() {
+            return true;
+        }
+        kept[a].deps.push(b);
+        false
+    });
+    cut
+}
+
+/// Finds the edges of any graph that another path already implies, each
+/// with a shortest such path. Edges within a cycle are always implied by
+/// going around it, so they are returned without a path instead.
+pub fn implied_edges<T>(items: &[Item<T>], graph: &Csr) -> Vec<(usize, usize, Option<Vec<usize>>)> {
+    let fits = Closure::fits(items.len());
+    let (components, component, reachable) = if fits {
+        let closure = Closure::new(graph);
+        (closure.components, closure.component, closure.reachable)
+    } else {
+        let components = strongly_connected_components(graph);
+        let mut component = vec![0; items.len()];
+        for (number, members) in components.iter().enumerate() {
+            for &member in members {
+                component[member] = number;
+            }
+        }
+        (components, component, Vec::new())
+    };
+
+    // The edges leaving each component, by the component they go to.
+    let mut leaving: Vec<BTreeMap<usize, usize>> = vec![BTreeMap::new(); components.len()];
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            if component[a] != component[b] {
+                *leaving[component[a]].entry(component[b]).or_default() += 1;
+            }
+        }
+    }
+    // Without the closure, the components each one reaches through
+    // another it has an edge to are searched for instead.
+    let through_others: HashSet<_> = if fits {
+        HashSet::new()
+    } else {
+        let targets: Vec<Vec<usize>> = leaving.iter().map(|targets| targets.keys().copied().collect()).collect();
+        reached_through_others(components.len(), |number| &targets[number]).into_iter().collect()
+    };
+    let reaches = |from: usize, to: usize| reachable[from][to / 64] & (1 << (to % 64)) != 0;
+
+    let mut implied = Vec::new();
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            let (from, to) = (component[a], component[b]);
+            if from == to {
+                implied.push((a, b, None));
+                continue;
+            }
+            // Another edge into the same component, or an edge into one that
+            // reaches it, makes a second path.
+            let targets = &leaving[from];
+            let other_path = targets[&to] > 1 || if fits {
+                targets.keys().any(|&other| other != to && reaches(other, to))
+            } else {
+                through_others.contains(&(from, to))
+            };
+            if other_path {
+                implied.push((a, b, find_path(items, a, b, Some((a, b)))));
+            }
+        }
+    }
+    implied.sort_unstable_by_key(|&(a, b, _)| (a, b));
+    implied
+}
+
+/// Finds the edges of a graph without cycles, out of `len` items whose
+/// edges go to `targets`, that go to an item another of the edges out of
+/// the same item also leads to. It searches the graph from every item with
+/// more than one edge, so it only takes memory in proportion to the graph,
+/// where bitsets of what every item reaches take memory in the square of
+/// its size.
+fn reached_through_others<'a>(len: usize, targets: impl Fn(usize) -> &'a [usize]) -> Vec<(usize, usize)> {
+    // The item whose search last came upon each item.
+    let mut reached_by = vec![usize::MAX; len];
+    let mut stack = Vec::new();
+    let mut found = Vec::new();
+    for from in 0..len {
+        let direct = targets(from);
+        if direct.len() < 2 {
+            continue;
+        }
+        stack.extend(direct.iter().flat_map(|&to| targets(to)));
+        while let Some(index) = stack.pop() {
+            if std::mem::replace(&mut reached_by[index], from) != from {
+                stack.extend(targets(index));
+            }
+        }
+        found.extend(direct.iter().filter(|&&to| reached_by[to] == from).map(|&to| (from, to)));
+    }
+    found
+}
+
+/// Every item each of `sources` reaches, the source itself among them when
+/// it is on a cycle, handed to `found` sorted and one source at a time.
+/// This is what `Closure::reachable_from` gives, without keeping the
+/// closure of the whole graph, so it also works on graphs too large for
+/// `Closure::fits`.
+pub fn each_reachable<E>(
+    graph: &Csr,
+    sources: &[usize],
+    mut found: impl FnMut(usize, &[usize]) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut reached_by = vec![usize::MAX; graph.len()];
+    let mut stack = Vec::new();
+    let mut reached = Vec::new();
+    for &source in sources {
+        reached.clear();
+        stack.extend(graph.targets(source));
+        while let Some(index) = stack.pop() {
+            if std::mem::replace(&mut reached_by[index], source) != source {
+                reached.push(index);
+                stack.extend(graph.targets(index));
+            }
+        }
+        reached.sort_unstable();
+        found(source, &reached)?;
+    }
+    Ok(())
+}
+
+/// The most memory the bitsets of a `Closure` may take, 1 GiB. The
+/// analyses that would need more search the graph instead.
+pub const CLOSURE_BUDGET: usize = 1 << 30;
+
+/// The transitive closure of a graph, as the strongly connected components
+/// each item can reach. The items of a component on a cycle reach each
+/// other and themselves.
+pub struct Closure {
+    components: Vec<Vec<usize>>,
+    component: Vec<usize>,
+    /// For each component, a bitset of the components it reaches.
+    reachable: Vec<Vec<u64>>,
+}
+
+impl Closure {
+    /// Whether the closure of a graph of `len` items is sure to fit in
+    /// `CLOSURE_BUDGET`: a bitset of `len` bits for each of them.
+    pub fn fits(len: usize) -> bool {
+        len.saturating_mul(len.div_ceil(64)).saturating_mul(8) <= CLOSURE_BUDGET
+    }
+
+    /// Works out what every item of `graph` reaches.
+    pub fn new(graph: &Csr) -> Self {
+        // Tarjan's algorithm hands out the components dependencies first,
+        // so the components each one depends on are already done.
+        let mut components = strongly_connected_components(graph);
+        for members in &mut components {
+            members.sort_unstable();
+        }
+        let mut component = vec![0; graph.len()];
+        for (number, members) in components.iter().enumerate() {
+            for &member in members {
+                component[member] = number;
+            }
+        }
+
+        let words = components.len().div_ceil(64);
+        let mut reachable = vec![vec![0u64; words]; components.len()];
+        for (number, members) in components.iter().enumerate() {
+            let mut reached = vec![0u64; words];
+            for &member in members {
+                for dep in graph.targets(member) {
+                    let other = component[dep];
+                    reached[other / 64] |= 1 << (other % 64);
+                    if other != number {
+                        for (word, other_word) in reached.iter_mut().zip(&reachable[other]) {
+                            *word |= other_word;
+                        }
+                    }
+                }
+            }
+            reachable[number] = reached;
+        }
+
+        Closure { components, component, reachable }
+    }
+
+    /// Every item that `index` reaches, sorted.
+    pub fn reachable_from(&self, index: usize) -> Vec<usize> {
+        let reached = &self.reachable[self.component[index]];
+        let mut items: Vec<_> = (0..self.components.len())
+            .filter(|&other| reached[other / 64] & (1 << (other % 64)) != 0)
+            .flat_map(|other| self.components[other].iter().copied())
+            .collect();
+        items.sort_unstable();
+        items
+    }
+
+    /// How many other items each item reaches, and how many other items
+    /// reach it.
+    pub fn counts(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut reaches = vec![0; self.components.len()];
+        let mut reached_by = vec![0; self.components.len()];
+        for (number, reached) in self.reachable.iter().enumerate() {
+            for (word_index, &word) in reached.iter().enumerate() {
+                let mut word = word;
+                while word != 0 {
+                    let other = word_index * 64 + word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    reaches[number] += self.components[other].len();
+                    reached_by[other] += self.components[number].len();
+                }
+            }
+        }
+
+        let on_cycle = |number: usize| self.reachable[number][number / 64] & (1 << (number % 64)) != 0;
+        let per_item = |counts: &[usize]| -> Vec<usize> {
+            self.component.iter()
+                .map(|&number| counts[number] - usize::from(on_cycle(number)))
+                .collect()
+        };
+        (per_item(&reaches), per_item(&reached_by))
+    }
+}
+
+/// Keeps only the items that `keep` is set for and the edges between them.
+pub fn induced_subgraph<T: Clone>(items: &[Item<T>], keep: &[bool]) -> Vec<Item<T>> {
+    let mut new_index = vec![None; items.len()];
+    let mut next = 0;
+    for (index, &kept) in keep.iter().enumerate() {
+        if kept {
+            new_index[index] = Some(next);
+            next += 1;
+        }
+    }
+    items.iter()
+        .zip(keep)
+        .filter(|(_, &kept)| kept)
+        .map(|(item, _)| Item::new(item.data.clone(), item.deps.iter().filter_map(|&dep| new_index[dep]).collect()))
+        .collect()
+}
+
+/// Fails with the self-loops or the first cycle found, for the commands
+/// that need an order. `graph` holds the edges of `items`.
+pub fn ensure_acyclic(items: &[Item<Node>], graph: &Csr) -> Result<(), DepsError> {
+    let self_loops = find_self_loops(items);
+    if !self_loops.is_empty() {
+        let names = self_loops.iter().map(|&index| items[index].to_string()).collect();
+        return Err(DepsError::SelfLoops(names));
+    }
+    if let Some(cycle) = detect_cycle(graph, &HashSet::new())? {
+        return Err(DepsError::CycleDetected { path: cycle.iter().map(|&index| items[index].to_string()).collect() });
+    }
+    Ok(())
+}
+
+/// Writes a cycle as `a -> b -> c -> a`, coming back round to the start.
+pub fn cycle_chain(names: &[impl fmt::Display]) -> String {
+    let mut chain: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+    if let Some(first) = chain.first().cloned() {
+        chain.push(first);
+    }
+    chain.join(" -> ")
+}
+
+/// Lists every problem `check --strict` rejects, each prefixed with its
+/// category so they are easy to grep for.
+pub fn strict_violations(loader: &Loader, threads: usize) -> Result<Vec<String>, DepsError> {
+    let items = &loader.items;
+    let mut violations = Vec::new();
+
+    for index in find_self_loops(items) {
+        violations.push(format!("self-loop: {} -> {}", *items[index], *items[index]));
+    }
+    for &(a, b) in &loader.duplicates {
+        violations.push(format!("duplicate-edge: {} -> {}", *items[a], *items[b]));
+    }
+
+    if let Some(cycle) = parallel::detect_cycle(items, loader.csr(), &loader.undirected, threads)? {
+        violations.push(format!("cycle: {}", cycle_chain(&cycle.iter().map(|&index| &*items[index]).collect::<Vec<_>>())));
+    }
+
+    Ok(violations)
+}
+
+/// Collapses every strongly connected component of more than one item into
+/// a single `scc_N` item labelled with its members, leaving a graph without
+/// cycles. Edges within a component, self-loops included, are dropped.
+/// `graph` holds the edges of `items`.
+pub fn condense(items: &[Item<Node>], graph: &Csr) -> Vec<Item<Node>> {
+    let mut components = strongly_connected_components(graph);
+    for members in &mut components {
+        members.sort_unstable();
+    }
+    // Keep the components in the order their first member was read.
+    components.sort_by_key(|members| members[0]);
+
+    let names: HashSet<_> = items.iter().map(|item| item.name.as_str()).collect();
+    let mut component = vec![0; items.len()];
+    let mut nodes = Vec::new();
+    let mut next = 1;
+    for (number, members) in components.iter().enumerate() {
+        for &member in members {
+            component[member] = number;
+        }
+        if let [member] = members[..] {
+            nodes.push(items[member].data.clone());
+            continue;
+        }
+        while names.contains(format!("scc_{}", next).as_str()) {
+            next += 1;
+        }
+        let mut node = Node::new(format!("scc_{}", next));
+        next += 1;
+        let labels: Vec<_> = members.iter().map(|&member| items[member].to_string()).collect();
+        node.label = Some(labels.join("+"));
+        nodes.push(node);
+    }
+
+    // The members are in input order, so the edges of each component are
+    // taken in the order they were read. `added` holds the component each
+    // other component was last added to the dependencies of.
+    let mut deps = vec![Vec::new(); components.len()];
+    let mut added = vec![usize::MAX; components.len()];
+    for (from, members) in components.iter().enumerate() {
+        for &a in members {
+            for &b in &items[a].deps {
+                let to = component[b];
+                if from != to && added[to] != from {
+                    added[to] = from;
+                    deps[from].push(to);
+                }
+            }
+        }
+    }
+
+    nodes.into_iter().zip(deps).map(|(node, deps)| Item::new(node, deps)).collect()
+}
+
+
+/// Sorts `indices` the way the commands list items, by how they are shown.
+fn by_shown_name(items: &[Item<Node>], indices: &mut [usize]) {
+    indices.sort_by_cached_key(|&index| items[index].to_string());
+}
+
+/// What `coverage` finds.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Coverage {
+    /// Each entry point with the items it reaches, itself included, sorted
+    /// by name.
+    pub roots: Vec<(usize, Vec<usize>)>,
+    /// The items none of the entry points reach, sorted as they are shown.
+    pub unreachable: Vec<usize>,
+}
+
+/// Works out what each of `starts` reaches by the edges of `graph`, those
+/// of `items`, and what none of them do. An item reached from several
+/// entry points counts for each of them.
+pub fn coverage(items: &[Item<Node>], graph: &Csr, starts: &[usize]) -> Coverage {
+    let mut reached = vec![false; items.len()];
+    let mut roots = Vec::new();
+    for &start in starts {
+        let mut covered: Vec<_> = [start].into_iter()
+            .chain(reachable(graph, &[start], None))
+            .collect();
+        for &index in &covered {
+            reached[index] = true;
+        }
+        covered.sort_by(|&a, &b| items[a].name.cmp(&items[b].name));
+        roots.push((start, covered));
+    }
+    let mut unreachable: Vec<_> = (0..items.len()).filter(|&index| !reached[index]).collect();
+    by_shown_name(items, &mut unreachable);
+    Coverage { roots, unreachable }
+}
+
+/// Returns the items that depend on any of `changed`, however indirectly,
+/// sorted as they are shown. The changed items are only listed themselves
+/// with `include_changed`, and with `roots_only` only the items nothing
+/// depends on are.
+pub fn impact(items: &[Item<Node>], changed: &[usize], include_changed: bool, roots_only: bool) -> Vec<usize> {
+    let dependents = Csr::reverse(items);
+    let mut impacted = reachable(&dependents, changed, None);
+    if include_changed {
+        impacted.extend(changed);
+    } else {
+        impacted.retain(|index| !changed.contains(index));
+    }
+    if roots_only {
+        impacted.retain(|&index| dependents.edges(index).is_empty());
+    }
+    by_shown_name(items, &mut impacted);
+    impacted
+}
+
+/// How a graph changes when items and edges are taken out of it, each
+/// count before and after.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WhatIf {
+    /// The graph without the edges taken out or those of the items taken
+    /// out, which keeps every item so the indices are the same as before.
+    pub after: Vec<Item<Node>>,
+    /// Whether each item is left.
+    pub keep: Vec<bool>,
+    pub nodes: (usize, usize),
+    pub edges: (usize, usize),
+    /// The strongly connected components that hold a cycle, self-loops
+    /// included.
+    pub cycles: (usize, usize),
+    /// The items left that a root reached and none of the roots left does,
+    /// sorted as they are shown, as are the next two.
+    pub unreachable: Vec<usize>,
+    /// The items left with edges that have none any more.
+    pub orphans: Vec<usize>,
+    /// The items left that were on a cycle and no longer are.
+    pub no_longer_cyclic: Vec<usize>,
+}
+
+/// Works out what taking `remove_nodes` and `remove_edges` out of the graph
+/// would change, without touching it. `graph` holds the edges of `items`.
+pub fn what_if(
+    items: &[Item<Node>],
+    graph: &Csr,
+    remove_nodes: &[usize],
+    remove_edges: &HashSet<(usize, usize)>,
+) -> WhatIf {
+    let mut keep = vec![true; items.len()];
+    for &index in remove_nodes {
+        keep[index] = false;
+    }
+    // Keep the indices the same so the two graphs compare item by item.
+    let mut after = items.to_vec();
+    for (index, item) in after.iter_mut().enumerate() {
+        item.deps.retain(|&dep| keep[index] && keep[dep] && !remove_edges.contains(&(index, dep)));
+    }
+
+    let graph_after = Csr::forward(&after);
+    let reached_from = |graph: &Csr, roots: &[usize]| {
+        let mut reached = vec![false; graph.len()];
+        for index in roots.iter().copied().chain(reachable(graph, roots, None)) {
+            reached[index] = true;
+        }
+        reached
+    };
+    let cyclic = |graph: &Csr| -> Vec<Vec<usize>> {
+        strongly_connected_components(graph).into_iter()
+            .filter(|members| members.len() > 1 || graph.targets(members[0]).any(|dep| dep == members[0]))
+            .collect()
+    };
+    let on_cycle = |cycles: &[Vec<usize>]| {
+        let mut on_cycle = vec![false; items.len()];
+        for &member in cycles.iter().flatten() {
+            on_cycle[member] = true;
+        }
+        on_cycle
+    };
+
+    let (fan_out, fan_in) = degrees(items);
+    let (fan_out_after, fan_in_after) = degrees(&after);
+    let roots: Vec<_> = (0..items.len()).filter(|&index| fan_in[index] == 0).collect();
+    let surviving_roots: Vec<_> = roots.iter().copied().filter(|&index| keep[index]).collect();
+    let (reached_before, reached_after) = (reached_from(graph, &roots), reached_from(&graph_after, &surviving_roots));
+    let (cycles_before, cycles_after) = (cyclic(graph), cyclic(&graph_after));
+    let (cyclic_before, cyclic_after) = (on_cycle(&cycles_before), on_cycle(&cycles_after));
+    let pick = |pick: &dyn Fn(usize) -> bool| {
+        let mut picked: Vec<_> = (0..items.len()).filter(|&index| keep[index] && pick(index)).collect();
+        by_shown_name(items, &mut picked);
+        picked
+    };
+
+    WhatIf {
+        unreachable: pick(&|index| reached_before[index] && !reached_after[index]),
+        orphans: pick(&|index| fan_out_after[index] + fan_in_after[index] == 0 && fan_out[index] + fan_in[index] > 0),
+        no_longer_cyclic: pick(&|index| cyclic_before[index] && !cyclic_after[index]),
+        nodes: (items.len(), keep.iter().filter(|&&kept| kept).count()),
+        edges: (fan_out.iter().sum(), fan_out_after.iter().sum()),
+        cycles: (cycles_before.len(), cycles_after.len()),
+        after,
+        keep,
+    }
+}
+
+/// Which way the edges of an item are counted, its dependencies or its
+/// dependents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Fan {
+    Out,
+    In,
+}
+
+/// Returns the `top` items with the most edges in `degrees`, one of the
+/// counts from `degrees`, most first and then by name. Items without any
+/// are left out.
+pub fn most_connected(items: &[Item<Node>], degrees: &[usize], top: usize) -> Vec<usize> {
+    let mut most: Vec<_> = (0..items.len()).filter(|&index| degrees[index] > 0).collect();
+    most.sort_by(|&a, &b| degrees[b].cmp(&degrees[a]).then_with(|| items[a].name.cmp(&items[b].name)));
+    most.truncate(top);
+    most
+}
+
+/// An item with more edges than it may have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Excess {
+    pub node: usize,
+    pub fan: Fan,
+    pub count: usize,
+    pub max: usize,
+}
+
+impl Excess {
+    /// How many edges too many the item has.
+    pub fn over(&self) -> usize {
+        self.count - self.max
+    }
+}
+
+/// Returns the items with more than `max_out` dependencies or more than
+/// `max_in` dependents, furthest over first, then by name and with the
+/// dependencies before the dependents.
+pub fn fan_limits(items: &[Item<Node>], max_out: Option<usize>, max_in: Option<usize>) -> Vec<Excess> {
+    let (fan_out, fan_in) = degrees(items);
+    let mut excesses = Vec::new();
+    for (fan, degrees, max) in [(Fan::Out, &fan_out, max_out), (Fan::In, &fan_in, max_in)] {
+        let Some(max) = max else { continue };
+        for node in (0..items.len()).filter(|&index| degrees[index] > max) {
+            excesses.push(Excess { node, fan, count: degrees[node], max });
+        }
+    }
+    excesses.sort_by(|a, b| {
+        b.over().cmp(&a.over()).then_with(|| items[a.node].name.cmp(&items[b.node].name)).then(a.fan.cmp(&b.fan))
+    });
+    excesses
+}
+
+/// How a graph differs from an older one, by name since the indices of the
+/// two have nothing to do with each other. Everything is sorted by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diff {
+    pub added_nodes: Vec<String>,
+    pub removed_nodes: Vec<String>,
+    pub added_edges: Vec<(String, String)>,
+    pub removed_edges: Vec<(String, String)>,
+    /// The nodes in both graphs whose dependencies changed.
+    pub changed: Vec<Changed>,
+}
+
+/// The dependencies a node in both graphs gained and lost.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Changed {
+    pub node: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Compares the graph `new` with the graph `old`.
+pub fn diff(old: &[Item<Node>], new: &[Item<Node>]) -> Diff {
+    fn graph(items: &[Item<Node>]) -> BTreeMap<&str, BTreeSet<&str>> {
+        items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    let (old, new) = (graph(old), graph(new));
+    let owned = |names: Vec<&&str>| -> Vec<String> { names.into_iter().map(|name| name.to_string()).collect() };
+    let empty = BTreeSet::new();
+
+    let mut diff = Diff {
+        added_nodes: owned(new.keys().filter(|name| !old.contains_key(*name)).collect()),
+        removed_nodes: owned(old.keys().filter(|name| !new.contains_key(*name)).collect()),
+        ..Diff::default()
+    };
+    let names: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+    for name in names {
+        let before = old.get(name).unwrap_or(&empty);
+        let after = new.get(name).unwrap_or(&empty);
+        let added = owned(after.difference(before).collect());
+        let removed = owned(before.difference(after).collect());
+        diff.added_edges.extend(added.iter().map(|dep| (name.to_string(), dep.clone())));
+        diff.removed_edges.extend(removed.iter().map(|dep| (name.to_string(), dep.clone())));
+        if old.contains_key(name) && new.contains_key(name) && (!added.is_empty() || !removed.is_empty()) {
+            diff.changed.push(Changed { node: name.to_string(), added, removed });
+        }
+    }
+    diff
+}
+
+/// Returns the items without any edges, sorted as they are shown.
+pub fn orphans(items: &[Item<Node>]) -> Vec<usize> {
+    let (fan_out, fan_in) = degrees(items);
+    let mut orphans: Vec<_> = (0..items.len()).filter(|&index| fan_out[index] + fan_in[index] == 0).collect();
+    by_shown_name(items, &mut orphans);
+    orphans
+}
+
+/// Items that depend on the very same items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SameDeps<'a> {
+    /// The names of the items, sorted.
+    pub members: Vec<&'a str>,
+    /// The names of what they depend on, sorted.
+    pub deps: Vec<&'a str>,
+}
+
+/// Groups the items with at least `min_deps` dependencies that have the
+/// same dependencies as another, the largest groups first and then by
+/// their members.
+pub fn same_deps(items: &[Item<Node>], min_deps: usize) -> Vec<SameDeps<'_>> {
+    let mut groups: BTreeMap<Vec<&str>, Vec<&str>> = BTreeMap::new();
+    for item in items {
+        let deps: BTreeSet<_> = item.deps.iter().map(|&dep| items[dep].name.as_str()).collect();
+        if deps.len() >= min_deps {
+            groups.entry(deps.into_iter().collect()).or_default().push(&item.name);
+        }
+    }
+    let mut groups: Vec<_> = groups.into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(deps, mut members)| {
+            members.sort_unstable();
+            SameDeps { members, deps }
+        })
+        .collect();
+    groups.sort_by(|a, b| b.members.len().cmp(&a.members.len()).then_with(|| a.members.cmp(&b.members)));
+    groups
+}
+
+/// Orders the items by their `scores`, highest first and then by name,
+/// keeping the first `top` of them.
+pub fn ranked(items: &[Item<Node>], scores: &[usize], top: Option<usize>) -> Vec<usize> {
+    let mut ranked: Vec<_> = (0..items.len()).collect();
+    ranked.sort_by(|&a, &b| scores[b].cmp(&scores[a]).then_with(|| items[a].name.cmp(&items[b].name)));
+    ranked.truncate(top.unwrap_or(usize::MAX));
+    ranked
+}
+
+/// Returns the edges whose dependency is not listed before its dependent in
+/// an order that puts item `i` on line `line[i]`, self-loops included. Items
+/// the order leaves out are not checked.
+pub fn order_violations<T>(items: &[Item<T>], line: &[Option<usize>]) -> Vec<(usize, usize)> {
+    let mut violations = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let Some(at) = line[index] else { continue };
+        for &dep in &item.deps {
+            if line[dep].is_some_and(|dep_at| dep == index || dep_at > at) {
+                violations.push((index, dep));
+            }
+        }
+    }
+    violations
+}
+
+/// Returns the edges that go up from the layer `layer` puts their dependent
+/// in, or stay in it unless `allow_same_layer`. A lower layer may not
+/// depend on a higher one, and items in no layer are not checked.
+pub fn layer_violations<T>(items: &[Item<T>], layer: &[Option<i64>], allow_same_layer: bool) -> Vec<(usize, usize)> {
+    let mut violations = Vec::new();
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            let (Some(from), Some(to)) = (layer[a], layer[b]) else { continue };
+            if from < to || (from == to && !allow_same_layer) {
+                violations.push((a, b));
+            }
+        }
+    }
+    violations
+}
+
+/// Merges the items into one item for each name `group` gives them, with
+/// an edge between two groups whenever there is one between their members
+/// and the attributes of the first of those. Edges within a group are only
+/// kept, as a self-loop, with `keep_self`.
+pub fn collapse(items: &[Item<Node>], group: impl Fn(&Node) -> String, keep_self: bool) -> Vec<Item<Node>> {
+    let mut collapsed = Loader::new(false);
+    let groups: Vec<_> = items.iter().map(|item| collapsed.index_of_name(&group(item))).collect();
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            if groups[a] != groups[b] || keep_self {
+                collapsed.add_edge(groups[a], groups[b]);
+                if let Some(attrs) = item.edge(&items[b].name) {
+                    collapsed.add_edge_attrs(groups[a], groups[b], attrs);
+                }
+            }
+        }
+    }
+    collapsed.graph.items
+}
+
+/// What `rename` makes of a graph.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Renamed {
+    pub items: Vec<Item<Node>>,
+    /// The edges between two items given the same name, which made it
+    /// depend on itself.
+    pub merged_loops: Vec<(usize, usize)>,
+}
+
+/// Gives the items the names in `renames`, from the old names to the new
+/// ones, merging the items that end up with the same name. A merged item
+/// keeps the label, cluster and attributes of the first of them to have
+/// each, and an edge the attributes of the first edge it was made from.
+pub fn rename(items: &[Item<Node>], renames: &HashMap<String, String>) -> Renamed {
+    let new_name = |index: usize| renames.get(&items[index].name).unwrap_or(&items[index].name);
+    let mut renamed = Loader::new(false);
+    for (index, item) in items.iter().enumerate() {
+        let new = renamed.index_of_name(new_name(index));
+        let node = &mut renamed.items[new].data;
+        if node.label.is_none() {
+            node.label = item.label.clone();
+        }
+        if node.cluster.is_none() {
+            node.cluster = item.cluster.clone();
+        }
+        for (key, value) in &item.attributes {
+            if node.attribute(key).is_none() {
+                node.attributes.set(key.clone(), value.clone());
+            }
+        }
+    }
+    let mut merged_loops = Vec::new();
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            let (new_a, new_b) = (renamed.indices[new_name(a)], renamed.indices[new_name(b)]);
+            if a != b && new_a == new_b {
+                merged_loops.push((a, b));
+            }
+            renamed.add_edge(new_a, new_b);
+            if let Some(attrs) = item.edge(&items[b].name) {
+                renamed.add_edge_attrs(new_a, new_b, attrs);
+            }
+        }
+    }
+    Renamed { items: renamed.graph.items, merged_loops }
+}
+
+/// Finds a cycle of `graph`, the merge of graphs whose edges are `inputs`,
+/// that none of them has all the edges of. Only the first `limit` cycles
+/// are looked at, and the second value is whether there were more.
+pub fn new_cycle(graph: &Csr, inputs: &[HashSet<(usize, usize)>], limit: usize) -> (Option<Vec<usize>>, bool) {
+    let (cycles, truncated) = elementary_cycles(graph, Some(limit));
+    let new_cycle = cycles.into_iter().find(|cycle| {
+        let cycle_edges: Vec<_> = cycle.iter().zip(cycle.iter().cycle().skip(1)).map(|(&a, &b)| (a, b)).collect();
+        !inputs.iter().any(|input| cycle_edges.iter().all(|edge| input.contains(edge)))
+    });
+    (new_cycle, truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::GraphBuilder;
+    use crate::testing::Random;
+    use crate::traverse::{Edges, TreeWalk};
+    use std::time::Duration;
+
+    fn graph(edges: &[(&str, &str)]) -> Vec<Item<Node>> {
+        let mut graph = GraphBuilder::new(false);
+        for &(from, to) in edges {
+            graph.add_edge_by_name(from, to).unwrap();
+        }
+        graph.items
+    }
+
+    #[test]
+    fn a_self_loop_alone_is_not_a_cycle() {
+        let items = graph(&[("a", "a"), ("a", "b")]);
+        assert_eq!(find_self_loops(&items), [0]);
+        assert_eq!(detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap(), None);
+        assert!(matches!(ensure_acyclic(&items, &Csr::forward(&items)), Err(DepsError::SelfLoops(names)) if names == ["a"]));
+    }
+
+    #[test]
+    fn self_loops_are_all_reported_next_to_cycles() {
+        let items = graph(&[("a", "a"), ("x", "y"), ("y", "z"), ("z", "x"), ("z", "z")]);
+        assert_eq!(find_self_loops(&items), [0, 3]);
+        assert_eq!(detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap(), Some(vec![1, 2, 3]));
+        assert!(matches!(ensure_acyclic(&items, &Csr::forward(&items)), Err(DepsError::SelfLoops(names)) if names == ["a", "z"]));
+    }
+
+    #[test]
+    fn a_cycle_is_refused_with_its_path() {
+        let items = graph(&[("app", "lib"), ("lib", "util"), ("util", "lib")]);
+        assert!(matches!(ensure_acyclic(&items, &Csr::forward(&items)), Err(DepsError::CycleDetected { path }) if path == ["lib", "util"]));
+    }
+
+    #[test]
+    fn a_clean_graph_has_neither() {
+        let items = graph(&[("a", "b"), ("b", "c"), ("a", "c")]);
+        assert!(find_self_loops(&items).is_empty());
+        assert_eq!(detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap(), None);
+        assert!(ensure_acyclic(&items, &Csr::forward(&items)).is_ok());
+    }
+
+    #[test]
+    fn a_dependency_past_the_end_is_refused() {
+        let items = vec![Item::new(0, vec![1]), Item::new(1, vec![2])];
+        let found = detect_cycle(&Csr::forward(&items), &HashSet::new());
+        assert!(matches!(found, Err(DepsError::InvalidDependency { item: 1, dep: 2, len: 2 })));
+    }
+
+    fn names(items: &[Item<Node>], indices: &[usize]) -> Vec<String> {
+        indices.iter().map(|&index| items[index].name.clone()).collect()
+    }
+
+    fn cycle(edges: &[(&str, &str)]) -> Option<Vec<String>> {
+        let items = graph(edges);
+        let cycle = detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap()?;
+        Some(names(&items, &cycle))
+    }
+
+    #[test]
+    fn a_two_cycle_is_both_its_items() {
+        assert_eq!(cycle(&[("a", "b"), ("b", "a")]).unwrap(), ["a", "b"]);
+        assert_eq!(cycle(&[("a", "b"), ("b", "c")]), None);
+    }
+
+    #[test]
+    fn a_cycle_deep_in_the_search_is_only_its_items() {
+        // A chain of 30 items whose last goes back to the 20th.
+        let mut chain: Vec<_> =
+            (0..29).map(|index| (index.to_string(), (index + 1).to_string())).collect();
+        chain.push(("29".to_string(), "20".to_string()));
+        let edges: Vec<_> = chain.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect();
+        let expected: Vec<_> = (20..30).map(|index| index.to_string()).collect();
+        assert_eq!(cycle(&edges), Some(expected));
+    }
+
+    #[test]
+    fn a_cycle_away_from_the_first_root_is_found() {
+        // The search from a finds nothing and has to start again.
+        let found = cycle(&[("a", "b"), ("b", "c"), ("d", "e"), ("e", "f"), ("f", "d")]);
+        assert_eq!(found.unwrap(), ["d", "e", "f"]);
+    }
+
+    /// Checks that each item of `cycle` has an edge to the next, and the
+    /// last to the first.
+    fn assert_is_cycle<T: fmt::Debug>(items: &[Item<T>], cycle: &[usize]) {
+        assert!(cycle.len() >= 2, "{:?} is too short", cycle);
+        for (position, &from) in cycle.iter().enumerate() {
+            let to = cycle[(position + 1) % cycle.len()];
+            assert!(items[from].deps.contains(&to), "{:?} -> {:?} is not an edge", items[from].data, items[to].data);
+        }
+    }
+
+    #[test]
+    fn the_cycle_path_follows_the_edges_wherever_it_is_entered() {
+        for len in [4, 6] {
+            for entry in 0..len {
+                // A root searched first that leads into the cycle at `entry`,
+                // with a shortcut across it and a tail hanging off it.
+                let mut graph = GraphBuilder::new(false);
+                graph.add_edge_by_name("root", &format!("n{}", entry)).unwrap();
+                for index in 0..len {
+                    graph.add_edge_by_name(&format!("n{}", index), &format!("n{}", (index + 1) % len)).unwrap();
+                }
+                graph.add_edge_by_name("n0", &format!("n{}", len / 2)).unwrap();
+                graph.add_edge_by_name(&format!("n{}", len - 1), "tail").unwrap();
+                let items = graph.build();
+
+                let cycle = detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap().expect("a cycle");
+                assert!(!cycle.contains(&0), "the root is not on the cycle: {:?}", cycle);
+                assert_is_cycle(&items, &cycle);
+            }
+        }
+    }
+
+    /// A graph of `len` items named by their index with the given edges.
+    fn numbered(len: usize, edges: &[(usize, usize)]) -> Vec<Item<String>> {
+        let mut graph = GraphBuilder::new(false);
+        for index in 0..len {
+            graph.add_node(&index.to_string());
+        }
+        for &(from, to) in edges {
+            graph.add_edge(from, to).unwrap();
+        }
+        graph.build()
+    }
+
+    #[test]
+    fn a_graph_without_a_cycle_has_none_and_an_added_one_is_found() {
+        let mut random = Random::new(11);
+        for _ in 0..300 {
+            // Edges only go down a random ranking of the items, so there is no
+            // cycle, but there are plenty of diamonds.
+            let len = 2 + random.below(40);
+            let mut rank: Vec<usize> = (0..len).collect();
+            for index in (1..len).rev() {
+                rank.swap(index, random.below(index + 1));
+            }
+            let mut edges = Vec::new();
+            for _ in 0..random.below(len * 3) {
+                let (a, b) = (random.below(len), random.below(len));
+                if rank[a] > rank[b] {
+                    edges.push((a, b));
+                }
+            }
+            let items = numbered(len, &edges);
+            assert_eq!(detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap(), None, "{:?}", edges);
+
+            // Walk down from an item with dependencies and add an edge back.
+            let Some(top) = (0..len).find(|&index| !items[index].deps.is_empty()) else { continue };
+            let mut bottom = top;
+            for step in 0..1 + random.below(len) {
+                let deps = &items[bottom].deps;
+                if deps.is_empty() || (step > 0 && random.below(3) == 0) {
+                    break;
+                }
+                bottom = deps[random.below(deps.len())];
+            }
+            edges.push((bottom, top));
+            let items = numbered(len, &edges);
+            let cycle = detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap();
+            assert_is_cycle(&items, &cycle.unwrap_or_else(|| panic!("no cycle found in {:?}", edges)));
+        }
+    }
+
+    #[test]
+    fn a_cycle_below_many_paths_is_found_without_walking_them_all() {
+        // A ladder of 60 diamonds, with 2^60 paths down it, then a cycle.
+        let mut edges = Vec::new();
+        for rung in 0..60 {
+            let top = rung * 3;
+            edges.extend([(top, top + 1), (top, top + 2), (top + 1, top + 3), (top + 2, top + 3)]);
+        }
+        edges.extend([(180, 181), (181, 182), (182, 181)]);
+        let items = numbered(183, &edges);
+        assert_eq!(detect_cycle(&Csr::forward(&items), &HashSet::new()).unwrap(), Some(vec![181, 182]));
+    }
+
+    #[test]
+    #[ignore = "a million items, run it with --release"]
+    fn a_million_items_deep_or_wide_are_analysed() {
+        const LEN: usize = 1_000_000;
+        let chain: Vec<_> = (0..LEN).map(|index| Item::new(index, (index + 1..LEN.min(index + 2)).collect())).collect();
+        let star: Vec<_> =
+            (0..LEN).map(|index| Item::new(index, if index == 0 { (1..LEN).collect() } else { Vec::new() })).collect();
+        assert!(!Closure::fits(LEN));
+
+        for (items, longest) in [(&chain, LEN - 1), (&star, 1)] {
+            let started = Instant::now();
+            let graph = Csr::forward(items);
+            assert_eq!(detect_cycle(&graph, &HashSet::new()).unwrap(), None);
+            assert_eq!(strongly_connected_components(&graph).len(), LEN);
+            let (order, _) = sort_items(items, false, None).unwrap();
+            assert_eq!(order.len(), LEN);
+            assert_eq!(longest_chains(items, &order)[0].0, longest);
+            assert!(redundant_edges(items, &order).is_empty());
+            assert!(implied_edges(items, &graph).is_empty());
+            assert_eq!(bottlenecks(items).bridges.len(), LEN - 1);
+            each_reachable(&graph, &[0], |_, reached| {
+                assert_eq!(reached.len(), LEN - 1);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+            let walk = TreeWalk::new(Edges::packed(items, &graph), 0, Some(100));
+            assert_eq!(walk.count(), if longest == 1 { LEN } else { 101 });
+            assert!(started.elapsed() < Duration::from_secs(300), "took {:?}", started.elapsed());
+        }
+    }
+
+    #[test]
+    fn cycles_are_written_back_round_to_their_start() {
+        assert_eq!(cycle_chain(&["a", "b", "c"]), "a -> b -> c -> a");
+        assert_eq!(cycle_chain(&["a"]), "a -> a");
+        assert_eq!(cycle_chain(&[] as &[&str]), "");
+    }
+
+    /// Loads the DOT `text` as the command line does.
+    fn loaded(text: &str) -> Loader {
+        let mut loader = Loader::new(false);
+        crate::graph::from_graphviz(&mut loader, &graphviz_rust::parse(text).unwrap(), crate::graph::Undirected::Error).unwrap();
+        loader
+    }
+
+    #[test]
+    fn strict_checks_list_every_kind_of_problem_once() {
+        let loader = loaded("digraph { a -> a; a -> b; a -> b; b -> c; c -> b }");
+        for threads in [1, 4] {
+            assert_eq!(
+                strict_violations(&loader, threads).unwrap(),
+                ["self-loop: a -> a", "duplicate-edge: a -> b", "cycle: b -> c -> b"],
+            );
+        }
+        assert!(strict_violations(&loaded("digraph { a -> b }"), 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_critical_path_and_the_items_without_slack() {
+        // a -> b -> d weighs 0.1 + 0.2, which as a float is a little more
+        // than the 0.3 of a -> c, but c has no slack either. e -> d has.
+        let items = graph(&[("a", "b"), ("b", "d"), ("a", "c"), ("e", "d")]);
+        let (order, _) = sort_order(&items, false);
+        let critical = critical_path(&items, &order, &[0.0, 0.1, 0.2, 0.3, 0.05]).unwrap();
+        assert_eq!(critical.path, [0, 1, 2]);
+        assert_eq!(critical.total, 0.1 + 0.2);
+        assert_eq!(critical.slack_free, [0, 1, 2, 3]);
+
+        let critical = critical_path(&items, &order, &[1.0; 5]).unwrap();
+        assert_eq!((critical.path, critical.total, critical.slack_free), (vec![0, 1, 2], 3.0, vec![0, 1, 2]));
+        assert_eq!(critical_path::<Node>(&[], &[], &[]), None);
+    }
+
+    #[test]
+    fn a_stable_sort_does_not_depend_on_the_input_order() {
+        let edges = [("app", "web"), ("app", "api"), ("web", "ui"), ("api", "core"), ("ui", "core"), ("cli", "core")];
+        let mut reordered = edges;
+        reordered.reverse();
+        let sorted = |edges: &[(&str, &str)]| {
+            let items = graph(edges);
+            names(&items, &sort_order(&items, true).0)
+        };
+        assert_eq!(sorted(&edges), ["core", "api", "cli", "ui", "web", "app"]);
+        assert_eq!(sorted(&reordered), sorted(&edges));
+    }
+
+    #[test]
+    fn every_elementary_cycle_is_listed() {
+        let cycles = |edges: &[(&str, &str)], limit| {
+            let items = graph(edges);
+            let (cycles, truncated) = elementary_cycles(&Csr::forward(&items), limit);
+            (cycles.iter().map(|cycle| names(&items, cycle).join(" ")).collect::<Vec<_>>(), truncated)
+        };
+        let disjoint = [("a", "b"), ("b", "a"), ("x", "y"), ("y", "z"), ("z", "x")];
+        assert_eq!(cycles(&disjoint, None), (vec!["a b".to_string(), "x y z".to_string()], false));
+        // Both cycles go through the edge a -> b.
+        let overlapping = [("a", "b"), ("b", "c"), ("c", "a"), ("b", "d"), ("d", "a")];
+        assert_eq!(cycles(&overlapping, None), (vec!["a b c".to_string(), "a b d".to_string()], false));
+        assert_eq!(cycles(&overlapping, Some(1)), (vec!["a b c".to_string()], true));
+        assert!(!cycles(&overlapping, Some(3)).1);
+    }
+
+    #[test]
+    fn weak_components_ignore_the_direction_of_edges() {
+        // c only reaches the others against the direction of its edge.
+        let items = graph(&[("x", "y"), ("a", "b"), ("c", "b"), ("lone", "lone"), ("b", "d")]);
+        let components: Vec<_> = weak_components(&items).iter().map(|members| names(&items, members).join(" ")).collect();
+        assert_eq!(components, ["a b c d", "x y", "lone"]);
+        assert!(weak_components::<Node>(&[]).is_empty());
+    }
+
+    #[test]
+    fn coverage_counts_a_node_for_every_root_reaching_it() {
+        let items = graph(&[("a", "c"), ("b", "c"), ("c", "d"), ("e", "f")]);
+        let Coverage { roots, unreachable } = coverage(&items, &Csr::forward(&items), &[0, 1]);
+        assert_eq!(roots.iter().map(|(root, covered)| (*root, names(&items, covered))).collect::<Vec<_>>(), [
+            (0, vec!["a".to_string(), "c".to_string(), "d".to_string()]),
+            (1, vec!["c".to_string(), "d".to_string()]),
+        ]);
+        assert_eq!(names(&items, &unreachable), ["b", "e", "f"]);
+    }
+
+    #[test]
+    fn impact_follows_the_dependents() {
+        let items = graph(&[("app", "lib"), ("lib", "log"), ("cli", "log"), ("tool", "cli")]);
+        let log = 2;
+        assert_eq!(names(&items, &impact(&items, &[log], false, false)), ["app", "cli", "lib", "tool"]);
+        assert_eq!(names(&items, &impact(&items, &[log], true, true)), ["app", "tool"]);
+        assert_eq!(names(&items, &impact(&items, &[log], true, false)), ["app", "cli", "lib", "log", "tool"]);
+    }
+
+    #[test]
+    fn what_if_compares_the_graph_before_and_after() {
+        let items = graph(&[("a", "b"), ("b", "c"), ("c", "b"), ("c", "d"), ("e", "d")]);
+        let what_if = what_if(&items, &Csr::forward(&items), &[4], &HashSet::from([(2, 1)]));
+        assert_eq!((what_if.nodes, what_if.edges, what_if.cycles), ((5, 4), (5, 3), (1, 0)));
+        assert!(what_if.unreachable.is_empty());
+        assert!(what_if.orphans.is_empty());
+        assert_eq!(names(&items, &what_if.no_longer_cyclic), ["b", "c"]);
+        assert!(what_if.after[3].deps.is_empty() && what_if.after[4].deps.is_empty());
+
+        let what_if = super::what_if(&items, &Csr::forward(&items), &[0], &HashSet::new());
+        assert_eq!(names(&items, &what_if.unreachable), ["b", "c"]);
+        assert!(what_if.no_longer_cyclic.is_empty());
+    }
+
+    #[test]
+    fn fan_limits_put_the_furthest_over_first() {
+        let items = graph(&[("a", "x"), ("a", "y"), ("a", "z"), ("b", "x"), ("b", "y"), ("c", "x")]);
+        let (fan_out, fan_in) = degrees(&items);
+        assert_eq!(names(&items, &most_connected(&items, &fan_out, 2)), ["a", "b"]);
+        assert_eq!(names(&items, &most_connected(&items, &fan_in, 10)), ["x", "y", "z"]);
+
+        let excesses = fan_limits(&items, Some(1), Some(2));
+        let excesses: Vec<_> = excesses.iter().map(|excess| (items[excess.node].name.as_str(), excess.fan, excess.over())).collect();
+        assert_eq!(excesses, [("a", Fan::Out, 2), ("b", Fan::Out, 1), ("x", Fan::In, 1)]);
+        assert!(fan_limits(&items, None, None).is_empty());
+    }
+
+    #[test]
+    fn a_diff_goes_by_name() {
+        let old = graph(&[("a", "b"), ("b", "c"), ("x", "c")]);
+        let new = graph(&[("b", "c"), ("a", "c"), ("a", "n")]);
+        let diff = diff(&old, &new);
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(diff.added_nodes, ["n"]);
+        assert_eq!(diff.removed_nodes, ["x"]);
+        assert_eq!(diff.added_edges, [pair("a", "c"), pair("a", "n")]);
+        assert_eq!(diff.removed_edges, [pair("a", "b"), pair("x", "c")]);
+        assert_eq!(diff.changed, [Changed { node: "a".to_string(), added: vec!["c".into(), "n".into()], removed: vec!["b".into()] }]);
+        assert_eq!(super::diff(&new, &new), Diff::default());
+    }
+
+    #[test]
+    fn orphans_have_no_edges_either_way() {
+        let mut builder = GraphBuilder::new(false);
+        builder.add_edge_by_name("a", "b").unwrap();
+        builder.add_node("z");
+        builder.add_node("c");
+        assert_eq!(names(&builder.items, &orphans(&builder.items)), ["c", "z"]);
+    }
+
+    #[test]
+    fn items_with_the_same_deps_are_grouped() {
+        let items = graph(&[("a", "x"), ("a", "y"), ("b", "y"), ("b", "x"), ("c", "x"), ("d", "x")]);
+        let groups = same_deps(&items, 1);
+        assert_eq!(groups, [
+            SameDeps { members: vec!["a", "b"], deps: vec!["x", "y"] },
+            SameDeps { members: vec!["c", "d"], deps: vec!["x"] },
+        ]);
+        assert_eq!(same_deps(&items, 2).len(), 1);
+        assert!(same_deps(&items, 0).iter().any(|group| group.deps.is_empty() && group.members == ["x", "y"]));
+    }
+
+    #[test]
+    fn reach_counts_leave_out_the_item_itself() {
+        let items = graph(&[
+            ("app", "api"), ("app", "ui"), ("api", "core"), ("ui", "core"), ("core", "util"),
+            ("x", "y"), ("y", "x"), ("y", "util"),
+        ]);
+        let (reach, rdeps) = Closure::new(&Csr::forward(&items)).counts();
+        // app, api, ui, core, util, x, y. core is reached two ways and
+        // counted once, and x and y reach each other but not themselves.
+        assert_eq!(reach, [4, 2, 2, 1, 0, 2, 2]);
+        assert_eq!(rdeps, [0, 1, 1, 3, 6, 1, 1]);
+    }
+
+    #[test]
+    fn ranked_breaks_ties_by_name() {
+        let items = graph(&[("b", "a"), ("c", "a"), ("c", "b")]);
+        assert_eq!(names(&items, &ranked(&items, &[1, 2, 2], None)), ["a", "c", "b"]);
+        assert_eq!(names(&items, &ranked(&items, &[1, 2, 2], Some(1))), ["a"]);
+    }
+
+    #[test]
+    fn order_violations_are_dependencies_listed_later() {
+        let items = graph(&[("a", "b"), ("b", "c"), ("c", "c"), ("d", "a")]);
+        assert_eq!(order_violations(&items, &[Some(1), Some(2), Some(3), None]), [(0, 1), (1, 2), (2, 2)]);
+        assert_eq!(order_violations(&items, &[Some(3), Some(2), None, Some(4)]), []);
+    }
+
+    #[test]
+    fn layer_violations_are_edges_going_up() {
+        let items = graph(&[("ui", "core"), ("core", "db"), ("db", "core"), ("core", "util"), ("util", "x")]);
+        let layer = [Some(3), Some(2), Some(1), Some(2), None];
+        assert_eq!(layer_violations(&items, &layer, false), [(1, 3), (2, 1)]);
+        assert_eq!(layer_violations(&items, &layer, true), [(2, 1)]);
+    }
+
+    #[test]
+    fn collapse_merges_edges_between_groups() {
+        let items = graph(&[("a/x", "a/y"), ("a/x", "b/x"), ("a/y", "b/y"), ("b/y", "a/x")]);
+        let group = |node: &Node| node.name.split('/').next().unwrap().to_string();
+        let collapsed = collapse(&items, group, false);
+        assert_eq!(names(&collapsed, &[0, 1]), ["a", "b"]);
+        assert_eq!(collapsed.iter().map(|item| item.deps.clone()).collect::<Vec<_>>(), [vec![1], vec![0]]);
+        let collapsed = collapse(&items, group, true);
+        assert_eq!(collapsed.iter().map(|item| item.deps.clone()).collect::<Vec<_>>(), [vec![0, 1], vec![0]]);
+    }
+
+    #[test]
+    fn renaming_onto_another_node_merges_them() {
+        let mut items = graph(&[("a", "b"), ("b", "c"), ("old", "c")]);
+        items[3].data.label = Some("Old".to_string());
+        let renames = HashMap::from([("old".to_string(), "new".to_string()), ("b".to_string(), "a".to_string())]);
+        let Renamed { items: renamed, merged_loops } = rename(&items, &renames);
+        assert_eq!(names(&renamed, &[0, 1, 2]), ["a", "c", "new"]);
+        assert_eq!(renamed[2].label.as_deref(), Some("Old"));
+        assert_eq!(renamed.iter().map(|item| item.deps.clone()).collect::<Vec<_>>(), [vec![0, 1], vec![], vec![1]]);
+        assert_eq!(merged_loops, [(0, 1)]);
+    }
+
+    #[test]
+    fn a_new_cycle_has_edges_from_several_inputs() {
+        let items = graph(&[("a", "b"), ("b", "a"), ("b", "c"), ("c", "b")]);
+        let first = HashSet::from([(0, 1), (1, 0), (1, 2)]);
+        let second = HashSet::from([(2, 1)]);
+        let (cycle, truncated) = new_cycle(&Csr::forward(&items), &[first.clone(), second], 10);
+        assert_eq!(cycle.map(|mut cycle| { cycle.sort(); cycle }), Some(vec![1, 2]));
+        assert!(!truncated);
+        let both: HashSet<_> = first.into_iter().chain([(2, 1)]).collect();
+        assert_eq!(new_cycle(&Csr::forward(&items), &[both], 10).0, None);
+    }
+}
@@ -0,0 +1,355 @@
+//! Snapshots of a loaded input, so a big graph queried again and again is
+//! only parsed the first time. A snapshot holds the names and data of the
+//! nodes, the edges in compressed sparse row form and what else the loader
+//! found, along with a key made from the contents it was loaded from and
+//! the release of the program that loaded them. It starts with magic bytes
+//! and a format version and ends with a checksum, so a snapshot that is
+//! damaged, cut short or written by another version is told apart and
+//! loaded from the input again.
+//!
+//! ```
+//! use depsys::cache;
+//! use depsys::graph::{Loader, Undirected, from_graphviz};
+//!
+//! let text = "digraph { a -> b; b -> c }";
+//! let mut loaded = Loader::new(false);
+//! from_graphviz(&mut loaded, &graphviz_rust::parse(text).unwrap(), Undirected::Error)?;
+//! let snapshot = cache::encode(&loaded, cache::key(text, "dot"));
+//! assert_eq!(cache::decode(&snapshot, cache::key(text, "dot"), false).unwrap().items.len(), 3);
+//! # Ok::<(), depsys::error::DepsError>(())
+//! ```
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::error::DepsError;
+use crate::graph::{Attrs, Loader};
+
+/// What every snapshot starts with.
+const MAGIC: &[u8; 8] = b"DEPSYS\0C";
+
+/// The version of the format, raised whenever it changes.
+const VERSION: u32 = 2;
+
+/// Where the snapshot of the input at `input` is kept: next to it, as
+/// `INPUT.depsys-cache`.
+pub fn path_for(input: &str) -> PathBuf {
+    PathBuf::from(format!("{}.depsys-cache", input))
+}
+
+/// The key of a snapshot of `contents` loaded with `options`, the settings
+/// that change what loading it gives, by this version of the program. A
+/// release can read the same input differently without the format
+/// changing, so its snapshots are only used by the same release.
+pub fn key(contents: &str, options: &str) -> u64 {
+    let hash = fnv1a(FNV_OFFSET, env!("CARGO_PKG_VERSION").as_bytes());
+    let hash = fnv1a(fnv1a(hash, &[0]), options.as_bytes());
+    fnv1a(fnv1a(hash, &[0]), contents.as_bytes())
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// Folds `bytes` into the 64-bit FNV-1a hash `hash`.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Writes a snapshot of everything `loader` holds, keyed with `key`.
+pub fn encode(loader: &Loader, key: u64) -> Vec<u8> {
+    let mut out = Encoder(Vec::new());
+    out.0.extend_from_slice(MAGIC);
+    out.u32(VERSION);
+    out.u64(key);
+    out.0.push(u8::from(loader.any_both_ways));
+
+    let items = &loader.items;
+    out.len(items.len());
+    for item in items {
+        out.str(&item.name);
+        out.opt_str(item.label.as_deref());
+        out.opt_str(item.cluster.as_deref());
+        out.0.push(u8::from(item.show_name));
+        out.attrs(&item.attributes);
+    }
+    let mut offset = 0;
+    out.u32(0);
+    for item in items {
+        offset += item.deps.len();
+        out.len(offset);
+    }
+    for item in items {
+        for &dep in &item.deps {
+            out.len(dep);
+        }
+    }
+    for item in items {
+        out.len(item.edges.len());
+        for (to, attrs) in &item.edges {
+            out.len(loader.indices[to]);
+            out.attrs(attrs);
+        }
+    }
+    out.len(loader.duplicates.len());
+    for &(a, b) in &loader.duplicates {
+        out.len(a);
+        out.len(b);
+    }
+    // Sorted, so the same graph always gives the same snapshot.
+    let mut undirected: Vec<_> = loader.undirected.iter().copied().collect();
+    undirected.sort_unstable();
+    out.len(undirected.len());
+    for (a, b) in undirected {
+        out.len(a);
+        out.len(b);
+    }
+
+    let checksum = fnv1a(FNV_OFFSET, &out.0);
+    out.u64(checksum);
+    out.0
+}
+
+/// Reads a snapshot written by `encode`, failing with the reason it can't
+/// be used when it was keyed with another key than `key`, or is from
+/// another version or damaged.
+pub fn decode(bytes: &[u8], key: u64, verbose: bool) -> Result<Loader, &'static str> {
+    let (body, checksum) = bytes.split_at_checked(bytes.len().wrapping_sub(8)).ok_or("it is cut short")?;
+    if !body.starts_with(MAGIC) {
+        return Err("it is not a cache");
+    }
+    let mut input = Decoder { bytes: body, at: MAGIC.len() };
+    if input.u32()? != VERSION {
+        return Err("it was written by another version");
+    }
+    if u64::from_le_bytes(checksum.try_into().unwrap()) != fnv1a(FNV_OFFSET, body) {
+        return Err("it is damaged");
+    }
+    if input.u64()? != key {
+        return Err("the input changed since");
+    }
+
+    let mut loader = Loader::new(verbose);
+    loader.any_both_ways = input.u8()? != 0;
+    let len = input.len()?;
+    for index in 0..len {
+        let name = input.str()?;
+        if loader.graph.add_node(name) != index {
+            return Err("it names a node twice");
+        }
+        let node = &mut loader.graph.items[index].data;
+        node.label = input.opt_str()?.map(str::to_string);
+        node.cluster = input.opt_str()?.map(str::to_string);
+        node.show_name = input.u8()? != 0;
+        node.attributes = input.attrs()?;
+    }
+    let offsets = (0..=len).map(|_| input.len()).collect::<Result<Vec<_>, _>>()?;
+    for from in 0..len {
+        let count = offsets[from + 1].checked_sub(offsets[from]).ok_or("its edges are out of order")?;
+        for _ in 0..count {
+            let to = input.len()?;
+            loader.graph.add_edge(from, to).map_err(|_| "it has an edge to a node it doesn't have")?;
+        }
+    }
+    for from in 0..len {
+        for _ in 0..input.len()? {
+            let to = input.len()?;
+            let attrs = input.attrs()?;
+            let to = loader.graph.items.get(to).ok_or("it has an edge to a node it doesn't have")?.name.clone();
+            loader.graph.items[from].data.edges.insert(to, attrs);
+        }
+    }
+    for _ in 0..input.len()? {
+        let edge = (input.len()?, input.len()?);
+        if edge.0 >= len || edge.1 >= len {
+            return Err("it has an edge to a node it doesn't have");
+        }
+        loader.duplicates.push(edge);
+    }
+    for _ in 0..input.len()? {
+        let edge = (input.len()?, input.len()?);
+        if edge.0 >= len || edge.1 >= len {
+            return Err("it has an edge to a node it doesn't have");
+        }
+        loader.undirected.insert(edge);
+    }
+    if input.at != body.len() {
+        return Err("it has bytes left over");
+    }
+    Ok(loader)
+}
+
+/// Loads the snapshot at `path` keyed with `key`, or nothing when there is
+/// none or it can't be used.
+pub fn load(path: &Path, key: u64, verbose: bool) -> Option<Loader> {
+    let bytes = fs::read(path).ok()?;
+    match decode(&bytes, key, verbose) {
+        Ok(loader) => Some(loader),
+        Err(reason) => {
+            log::debug!("Not using the cache {}, {}", path.display(), reason);
+            None
+        },
+    }
+}
+
+/// Writes a snapshot of `loader` keyed with `key` to `path`, replacing
+/// what was there only once it is complete.
+pub fn save(path: &Path, loader: &Loader, key: u64) -> Result<(), DepsError> {
+    let error = |source| DepsError::Write { path: path.display().to_string(), source };
+    let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut file = tempfile::NamedTempFile::new_in(directory).map_err(error)?;
+    file.write_all(&encode(loader, key)).map_err(error)?;
+    file.persist(path).map_err(|err| error(err.error))?;
+    Ok(())
+}
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, value: usize) {
+        self.u32(u32::try_from(value).expect("graphs have fewer than 2^32 items and edges"));
+    }
+
+    fn str(&mut self, text: &str) {
+        self.len(text.len());
+        self.0.extend_from_slice(text.as_bytes());
+    }
+
+    fn opt_str(&mut self, text: Option<&str>) {
+        match text {
+            Some(text) => {
+                self.0.push(1);
+                self.str(text);
+            },
+            None => self.0.push(0),
+        }
+    }
+
+    fn attrs(&mut self, attrs: &Attrs) {
+        self.len(attrs.len());
+        for (key, value) in attrs {
+            self.str(key);
+            self.str(value);
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], &'static str> {
+        let bytes = self.bytes.get(self.at..).and_then(|rest| rest.get(..count)).ok_or("it is cut short")?;
+        self.at += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, &'static str> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, &'static str> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn str(&mut self) -> Result<&'a str, &'static str> {
+        let len = self.len()?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| "it has a name that isn't UTF-8")
+    }
+
+    fn opt_str(&mut self) -> Result<Option<&'a str>, &'static str> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => self.str().map(Some),
+        }
+    }
+
+    fn attrs(&mut self) -> Result<Attrs, &'static str> {
+        (0..self.len()?).map(|_| Ok((self.str()?.to_string(), self.str()?.to_string()))).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Undirected, from_graphviz};
+
+    const TEXT: &str = r#"digraph {
+        subgraph cluster_core { a [label="A", shape=box] }
+        a -> b [style=dashed]; b -> c; b -> c; c -> a
+    }"#;
+
+    fn loaded() -> Loader {
+        let mut loader = Loader::new(false);
+        from_graphviz(&mut loader, &graphviz_rust::parse(TEXT).unwrap(), Undirected::Error).unwrap();
+        loader
+    }
+
+    #[test]
+    fn the_same_contents_get_the_same_graph_back() {
+        let loaded = loaded();
+        let current = key(TEXT, "dot");
+        let cached = decode(&encode(&loaded, current), current, false).unwrap();
+        let items = |loader: &Loader| loader.items.iter().map(|item| (item.data.clone(), item.deps.clone())).collect::<Vec<_>>();
+        assert_eq!(items(&cached), items(&loaded));
+        assert_eq!(cached.duplicates, loaded.duplicates);
+        assert_eq!(cached.undirected, loaded.undirected);
+    }
+
+    #[test]
+    fn a_snapshot_of_other_contents_or_options_is_stale() {
+        let current = key(TEXT, "dot");
+        let snapshot = encode(&loaded(), current);
+        assert!(decode(&snapshot, key(&TEXT.replace("c -> a", "c -> d"), "dot"), false).is_err());
+        assert!(decode(&snapshot, key(TEXT, "dot --qualify-clusters"), false).is_err());
+    }
+
+    #[test]
+    fn a_damaged_or_foreign_snapshot_is_not_used() {
+        let current = key(TEXT, "dot");
+        let snapshot = encode(&loaded(), current);
+        let mut damaged = snapshot.clone();
+        damaged[snapshot.len() / 2] ^= 1;
+        assert!(decode(&damaged, current, false).is_err());
+        assert!(decode(&snapshot[..snapshot.len() - 1], current, false).is_err());
+        assert!(decode(b"not a cache", current, false).is_err());
+
+        // Nor is one written by another version of the format.
+        let mut other_version = snapshot.clone();
+        other_version[8] ^= 0xff;
+        assert!(decode(&other_version, current, false).is_err());
+    }
+
+    #[test]
+    fn a_saved_snapshot_is_found_again_next_to_its_input() {
+        let loaded = loaded();
+        let current = key(TEXT, "dot");
+        let directory = tempfile::tempdir().unwrap();
+        let input = directory.path().join("graph.dot").display().to_string();
+        assert!(load(&path_for(&input), current, false).is_none());
+        save(&path_for(&input), &loaded, current).unwrap();
+        assert!(load(&path_for(&input), current, false).is_some());
+        assert!(load(&path_for(&input), current.wrapping_add(1), false).is_none());
+    }
+}
@@ -0,0 +1,252 @@
+//! Completion scripts for the shells, generated by `clap_complete` from the
+//! definition of the command line so they can't fall behind it. The bash,
+//! zsh and fish scripts complete node names as well, with a few lines added
+//! after the generated ones that call the hidden `__complete-nodes` with the
+//! first input file on the line. PowerShell and Elvish only get what
+//! `clap_complete` writes.
+
+use std::fmt::Write;
+use clap::Arg;
+pub use clap_complete::Shell;
+
+/// What the added lines need to know about the command line.
+struct Nodes {
+    /// Every visible subcommand, to find the one on the line.
+    commands: Vec<String>,
+    /// The subcommands with a positional argument that is a node.
+    positional: Vec<String>,
+    /// Each subcommand with an option taking a node, and that option as
+    /// typed.
+    options: Vec<(String, String)>,
+    /// Every option taking a value, as typed, after which a word is that
+    /// value rather than a node.
+    valued: Vec<String>,
+}
+
+/// Whether the values of `arg` are nodes, going by what they are called.
+fn is_node(arg: &Arg) -> bool {
+    let names: Vec<String> = match arg.get_value_names() {
+        Some(names) => names.iter().map(ToString::to_string).collect(),
+        None => vec![arg.get_id().as_str().to_uppercase()],
+    };
+    // Positional nodes may be named after the end of the edge they are.
+    let nodes: &[&str] = if arg.is_positional() { &["NODE", "FROM", "TO"] } else { &["NODE"] };
+    names.iter().all(|name| nodes.contains(&name.as_str()))
+}
+
+/// The long and short form of an option, as typed.
+fn flags(arg: &Arg) -> impl Iterator<Item = String> {
+    let long = arg.get_long().map(|long| format!("--{}", long));
+    let short = arg.get_short().map(|short| format!("-{}", short));
+    long.into_iter().chain(short)
+}
+
+fn nodes(command: &clap::Command) -> Nodes {
+    let mut nodes = Nodes { commands: Vec::new(), positional: Vec::new(), options: Vec::new(), valued: Vec::new() };
+    let global = command.get_arguments().map(|arg| (command, arg));
+    let subcommands = command.get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .flat_map(|subcommand| subcommand.get_arguments().map(move |arg| (subcommand, arg)));
+    for (owner, arg) in global.chain(subcommands) {
+        let name = owner.get_name().to_string();
+        if !std::ptr::eq(owner, command) && !nodes.commands.contains(&name) {
+            nodes.commands.push(name.clone());
+        }
+        if arg.is_positional() {
+            if is_node(arg) && !nodes.positional.contains(&name) {
+                nodes.positional.push(name);
+            }
+            continue;
+        }
+        if !arg.get_action().takes_values() {
+            continue;
+        }
+        for flag in flags(arg) {
+            if is_node(arg) {
+                nodes.options.push((name.clone(), flag.clone()));
+            }
+            if !nodes.valued.contains(&flag) {
+                nodes.valued.push(flag);
+            }
+        }
+    }
+    nodes
+}
+
+/// Writes the completion script of `shell` for `command`, the whole
+/// command line of the program.
+pub fn script(shell: Shell, command: &mut clap::Command) -> String {
+    let program = command.get_name().to_string();
+    // clap_complete names the bash functions of the subcommands one way
+    // where they are called and another where they are defined once the
+    // program has a dash in its name, so they are generated without one.
+    let generated_name = match shell {
+        Shell::Bash => program.replace('-', "_"),
+        _ => program.clone(),
+    };
+    // The subcommands take their names in the script from this when built.
+    command.set_bin_name(&generated_name);
+    command.build();
+    let nodes = nodes(command);
+    let mut generated = Vec::new();
+    clap_complete::generate(shell, command, &generated_name, &mut generated);
+    let generated = String::from_utf8(generated).expect("clap_complete writes UTF-8");
+    match shell {
+        Shell::Bash => bash(&program, &generated_name, &generated, &nodes),
+        Shell::Zsh => zsh(&program, &generated, &nodes),
+        Shell::Fish => fish(&program, &generated, &nodes),
+        _ => generated,
+    }
+}
+
+/// Turns `program` into something that can be part of a function name.
+fn function_name(program: &str) -> String {
+    program.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+/// Quotes `text` in single quotes for a POSIX shell or fish.
+fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// The `command option` pairs of the node options, as `case` patterns.
+fn option_patterns(nodes: &Nodes) -> String {
+    let patterns: Vec<_> = nodes.options.iter().map(|(command, option)| single_quote(&format!("{} {}", command, option))).collect();
+    patterns.join("|")
+}
+
+fn bash(program: &str, generated_name: &str, generated: &str, nodes: &Nodes) -> String {
+    let function = function_name(program);
+    let mut text = String::new();
+    // Register the function below rather than the generated one, for the
+    // program rather than the name it was generated for.
+    for line in generated.lines() {
+        match line.trim_start().strip_prefix("complete -F ") {
+            Some(rest) => {
+                let rest = rest.strip_suffix(generated_name).unwrap_or(rest);
+                let rest = rest.replacen(&format!("_{} ", function), &format!("_{}_nodes ", function), 1);
+                let indent = &line[..line.len() - line.trim_start().len()];
+                let _ = writeln!(text, "{}complete -F {}{}", indent, rest, program);
+            },
+            None => {
+                let _ = writeln!(text, "{}", line);
+            },
+        }
+    }
+    let _ = writeln!(text);
+    let _ = writeln!(text, "_{}_nodes() {{", function);
+    let _ = writeln!(text, "    _{} \"$@\"", function);
+    let _ = writeln!(text, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+    let _ = writeln!(text, "    local word command=\"\" previous=\"\" input=\"\" format=()");
+    let _ = writeln!(text, "    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do");
+    let _ = writeln!(text, "        if [ -z \"$command\" ]; then");
+    let _ = writeln!(text, "            case \"$word\" in {}) command=\"$word\" ;; esac", nodes.commands.join("|"));
+    let _ = writeln!(text, "        elif [ \"$previous\" = --format ]; then");
+    let _ = writeln!(text, "            format=(--format \"$word\")");
+    let _ = writeln!(text, "        elif [ -z \"$input\" ] && [ -f \"$word\" ]; then");
+    let _ = writeln!(text, "            input=\"$word\"");
+    let _ = writeln!(text, "        fi");
+    let _ = writeln!(text, "        previous=\"$word\"");
+    let _ = writeln!(text, "    done");
+    let _ = writeln!(text, "    [ -n \"$input\" ] || return 0");
+    let _ = writeln!(text, "    case \"$command $prev\" in");
+    let _ = writeln!(text, "        {}) COMPREPLY=() ;;", option_patterns(nodes));
+    let _ = writeln!(text, "        *)");
+    let _ = writeln!(text, "            case \"$prev\" in {}) return 0 ;; esac", nodes.valued.join("|"));
+    let _ = writeln!(text, "            case \"$command\" in {}) ;; *) return 0 ;; esac", nodes.positional.join("|"));
+    let _ = writeln!(text, "            [[ \"$cur\" == -* ]] && return 0");
+    let _ = writeln!(text, "            ;;");
+    let _ = writeln!(text, "    esac");
+    let _ = writeln!(text, "    local IFS=$'\\n'");
+    let _ = writeln!(
+        text,
+        "    COMPREPLY+=($({} __complete-nodes \"${{format[@]}}\" --prefix=\"$cur\" \"$input\" 2>/dev/null))",
+        program,
+    );
+    let _ = writeln!(text, "}}");
+    text
+}
+
+fn zsh(program: &str, generated: &str, nodes: &Nodes) -> String {
+    // The generated function is renamed so the one below, which calls it,
+    // is the one the #compdef line and the end of the script run.
+    let generated = generated.replacen(&format!("\n_{}() {{\n", program), &format!("\n_{}_arguments() {{\n", program), 1);
+    let (generated, dispatch) = match generated.rfind("\nif [ \"$funcstack[1]\"") {
+        Some(at) => (&generated[..=at], &generated[at + 1..]),
+        None => (&generated[..], ""),
+    };
+    let mut text = generated.to_string();
+    let _ = writeln!(text, "_{}() {{", program);
+    // The generated function shifts the words as it goes into subcommands.
+    let _ = writeln!(text, "    local -a typed=($words) format nodes");
+    let _ = writeln!(text, "    local current=$CURRENT prefix=$PREFIX word command previous input");
+    let _ = writeln!(text, "    _{}_arguments \"$@\"", program);
+    let _ = writeln!(text, "    for word in $typed[2,current-1]; do");
+    let _ = writeln!(text, "        if [[ -z $command ]]; then");
+    let _ = writeln!(text, "            case $word in ({}) command=$word ;; esac", nodes.commands.join("|"));
+    let _ = writeln!(text, "        elif [[ $previous == --format ]]; then");
+    let _ = writeln!(text, "            format=(--format $word)");
+    let _ = writeln!(text, "        elif [[ -z $input && -f $word ]]; then");
+    let _ = writeln!(text, "            input=$word");
+    let _ = writeln!(text, "        fi");
+    let _ = writeln!(text, "        previous=$word");
+    let _ = writeln!(text, "    done");
+    let _ = writeln!(text, "    [[ -n $input ]] || return 0");
+    let _ = writeln!(text, "    case \"$command $typed[current-1]\" in");
+    let _ = writeln!(text, "        ({}) ;;", option_patterns(nodes));
+    let _ = writeln!(text, "        (*)");
+    let _ = writeln!(text, "            case $typed[current-1] in ({}) return 0 ;; esac", nodes.valued.join("|"));
+    let _ = writeln!(text, "            case $command in ({}) ;; (*) return 0 ;; esac", nodes.positional.join("|"));
+    let _ = writeln!(text, "            [[ $prefix == -* ]] && return 0");
+    let _ = writeln!(text, "            ;;");
+    let _ = writeln!(text, "    esac");
+    let _ = writeln!(
+        text,
+        "    nodes=(${{(f)\"$({} __complete-nodes $format --prefix=\"$prefix\" \"$input\" 2>/dev/null)\"}})",
+        program,
+    );
+    let _ = writeln!(text, "    compadd -a nodes");
+    let _ = writeln!(text, "}}");
+    let _ = writeln!(text);
+    text.push_str(dispatch);
+    text
+}
+
+fn fish(program: &str, generated: &str, nodes: &Nodes) -> String {
+    let function = function_name(program);
+    let mut text = generated.to_string();
+    let _ = writeln!(text);
+    let _ = writeln!(text, "function __{}_nodes", function);
+    let _ = writeln!(text, "    set -l format");
+    let _ = writeln!(text, "    set -l input");
+    let _ = writeln!(text, "    set -l previous");
+    let _ = writeln!(text, "    set -l command");
+    let _ = writeln!(text, "    for word in (commandline -opc)[2..-1]");
+    let _ = writeln!(text, "        if test -z \"$command\"");
+    let _ = writeln!(text, "            contains -- $word {}; and set command $word", nodes.commands.join(" "));
+    let _ = writeln!(text, "        else if test \"$previous\" = --format");
+    let _ = writeln!(text, "            set format --format $word");
+    let _ = writeln!(text, "        else if test -z \"$input\"; and test -f \"$word\"");
+    let _ = writeln!(text, "            set input $word");
+    let _ = writeln!(text, "        end");
+    let _ = writeln!(text, "        set previous $word");
+    let _ = writeln!(text, "    end");
+    let _ = writeln!(text, "    if test -n \"$input\"");
+    let _ = writeln!(
+        text,
+        "        {} __complete-nodes $format --prefix=(commandline -ct) $input 2>/dev/null",
+        program,
+    );
+    let _ = writeln!(text, "    end");
+    let _ = writeln!(text, "end");
+    let using = |commands: &str| format!("\"__fish_{}_using_subcommand {}\"", function, commands);
+    let _ = writeln!(text, "complete -c {} -n {} -a '(__{}_nodes)'", program, using(&nodes.positional.join(" ")), function);
+    for (command, option) in &nodes.options {
+        let flag = match option.strip_prefix("--") {
+            Some(long) => format!("-l {}", long),
+            None => format!("-s {}", &option[1..]),
+        };
+        let _ = writeln!(text, "complete -c {} -n {} {} -x -a '(__{}_nodes)'", program, using(command), flag, function);
+    }
+    text
+}
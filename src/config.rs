@@ -0,0 +1,162 @@
+//! Default options read from a `dep-sys.toml`. Its `[global]` table holds
+//! defaults for every command that has the option, and a table named after
+//! a command holds defaults for that command alone, winning over `[global]`.
+//! Options given on the command line always win over both.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::ArgAction;
+use clap::parser::ValueSource;
+use toml::Spanned;
+use toml::de::{DeTable, DeValue};
+use depsys::error::DepsError;
+
+/// The name of the file looked for in the current directory and above.
+pub const FILE_NAME: &str = "dep-sys.toml";
+
+/// The options that choose the config file, which it can't set itself.
+const OWN_OPTIONS: &[&str] = &["config", "no-config", "help", "version"];
+
+/// Finds the closest `dep-sys.toml`, walking up from the current directory.
+pub fn discover() -> Option<PathBuf> {
+    let directory = env::current_dir().ok()?;
+    directory.ancestors().map(|directory| directory.join(FILE_NAME)).find(|path| path.is_file())
+}
+
+/// Returns `arguments`, a command line of `command`, with the options of
+/// the config file at `path` that it doesn't give added, and a warning for
+/// every table and key of the file that no command reads.
+pub fn with_defaults(
+    path: &Path,
+    arguments: &[OsString],
+    mut command: clap::Command,
+) -> Result<(Vec<OsString>, Vec<String>), DepsError> {
+    let name = path.display().to_string();
+    let contents = fs::read_to_string(path).map_err(|source| DepsError::Io { path: name.clone(), source })?;
+    let position = |offset: usize| {
+        let before = &contents[..offset.min(contents.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        (line, column)
+    };
+    let error_at = |offset: usize, message: &str| {
+        let (line, column) = position(offset);
+        DepsError::at(&name, line, column, message, &contents)
+    };
+    let table = DeTable::parse(&contents).map_err(|err| {
+        error_at(err.span().map_or(0, |span| span.start), err.message())
+    })?;
+
+    let matches = command.try_get_matches_from_mut(arguments).map_err(|err| DepsError::Usage(err.to_string()))?;
+    let Some((subcommand_name, subcommand_matches)) = matches.subcommand() else {
+        return Ok((arguments.to_vec(), Vec::new()));
+    };
+    let subcommand = command.find_subcommand(subcommand_name).expect("the matches name a subcommand");
+    let has_option = |command: &clap::Command, long: &str| {
+        !OWN_OPTIONS.contains(&long) && command.get_arguments().any(|arg| arg.get_long() == Some(long))
+    };
+
+    let mut warnings = Vec::new();
+    for (section, value) in table.get_ref() {
+        let (line, _) = position(section.span().start);
+        let Some(options) = value.get_ref().as_table() else {
+            warnings.push((line, format!("{}:{}: unknown key {}", name, line, section.get_ref())));
+            continue;
+        };
+        let commands: Vec<_> = if section.get_ref() == "global" {
+            command.get_subcommands().collect()
+        } else {
+            match command.find_subcommand(&**section.get_ref()) {
+                Some(command) => vec![command],
+                None => {
+                    warnings.push((line, format!("{}:{}: unknown table [{}]", name, line, section.get_ref())));
+                    continue;
+                },
+            }
+        };
+        for key in options.keys() {
+            let long = key.get_ref().replace('_', "-");
+            if !commands.iter().any(|command| has_option(command, &long)) {
+                let (line, _) = position(key.span().start);
+                warnings.push((line, format!("{}:{}: unknown key {} in [{}]", name, line, key.get_ref(), section.get_ref())));
+            }
+        }
+    }
+
+    // The defaults of the command, by option, those of its own table
+    // replacing those of `[global]`.
+    let mut defaults: Vec<(&clap::Arg, &Spanned<DeValue>)> = Vec::new();
+    for section in ["global", subcommand_name] {
+        let Some(options) = table.get_ref().get(section).and_then(|value| value.get_ref().as_table()) else {
+            continue;
+        };
+        for (key, value) in options {
+            let long = key.get_ref().replace('_', "-");
+            if !has_option(subcommand, &long) {
+                continue;
+            }
+            let arg = subcommand.get_arguments().find(|arg| arg.get_long() == Some(long.as_str())).expect("the option exists");
+            defaults.retain(|(other, _)| other.get_id() != arg.get_id());
+            defaults.push((arg, value));
+        }
+    }
+
+    let mut added = Vec::new();
+    for (arg, value) in defaults {
+        let id = arg.get_id().as_str();
+        let given = |matches: &clap::ArgMatches| matches.value_source(id) == Some(ValueSource::CommandLine);
+        if given(subcommand_matches) || (arg.is_global_set() && given(&matches)) {
+            continue;
+        }
+        let long = arg.get_long().expect("the option has a long name");
+        let values = option_values(arg, value.get_ref()).map_err(|message| error_at(value.span().start, &message))?;
+        added.extend(values.into_iter().map(|value| match value {
+            Some(value) => OsString::from(format!("--{}={}", long, value)),
+            None => OsString::from(format!("--{}", long)),
+        }));
+    }
+
+    // The options go before a `--`, after which everything is positional.
+    let mut arguments = arguments.to_vec();
+    let end = arguments.iter().position(|argument| argument == "--").unwrap_or(arguments.len());
+    arguments.splice(end..end, added);
+    warnings.sort();
+    Ok((arguments, warnings.into_iter().map(|(_, warning)| warning).collect()))
+}
+
+/// The values `value` gives the option `arg`, each written as `--option` for
+/// `None` or `--option=value`.
+fn option_values(arg: &clap::Arg, value: &DeValue) -> Result<Vec<Option<String>>, String> {
+    match arg.get_action() {
+        ArgAction::SetTrue => match value.as_bool() {
+            Some(true) => Ok(vec![None]),
+            Some(false) => Ok(Vec::new()),
+            None => Err("expected true or false".to_string()),
+        },
+        ArgAction::Count => match (value.as_bool(), value.as_integer().and_then(|number| number.as_str().parse().ok())) {
+            (Some(given), _) => Ok(if given { vec![None] } else { Vec::new() }),
+            (_, Some(count)) => Ok(vec![None; count]),
+            _ => Err("expected true, false or how many times to pass it".to_string()),
+        },
+        ArgAction::Append => match value.as_array() {
+            Some(values) => values.iter().map(|value| scalar(value.get_ref()).map(Some)).collect(),
+            None => Ok(vec![Some(scalar(value)?)]),
+        },
+        _ if value.is_array() => Err("expected a single value, the option can't be repeated".to_string()),
+        _ => Ok(vec![Some(scalar(value)?)]),
+    }
+}
+
+fn scalar(value: &DeValue) -> Result<String, String> {
+    match value {
+        DeValue::String(text) => Ok(text.to_string()),
+        DeValue::Integer(number) => i128::from_str_radix(number.as_str(), number.radix())
+            .map(|number| number.to_string())
+            .map_err(|_| format!("{} is too large", number)),
+        DeValue::Float(number) => Ok(number.to_string()),
+        DeValue::Boolean(value) => Ok(value.to_string()),
+        _ => Err(format!("expected a string, number or boolean, found {}", value.type_str())),
+    }
+}
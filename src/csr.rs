@@ -0,0 +1,103 @@
+//! The edges of a graph packed for the analyses that walk all of them. The
+//! `deps` of every item are vectors of their own, scattered over the heap,
+//! which on graphs of hundreds of thousands of edges means an allocation
+//! per item and a cache miss per step. A `Csr` holds them one after the
+//! other in a single vector instead.
+
+use crate::error::DepsError;
+use crate::graph::Item;
+use crate::timings;
+
+/// The edges of a graph in compressed sparse row form: the targets of the
+/// edges out of every item one after the other, and where those of each item
+/// start. `forward` follows the edges from an item to its dependencies,
+/// `reverse` from an item to the items depending on it.
+#[derive(Debug, Clone, Default)]
+pub struct Csr {
+    /// The edges out of item `i` are `targets[offsets[i]..offsets[i + 1]]`.
+    offsets: Vec<u32>,
+    targets: Vec<u32>,
+}
+
+impl Csr {
+    /// The dependencies of every item, in the order of its `deps`.
+    pub fn forward<T>(items: &[Item<T>]) -> Self {
+        let mut offsets = Vec::with_capacity(items.len() + 1);
+        let mut targets = Vec::with_capacity(items.iter().map(|item| item.deps.len()).sum());
+        offsets.push(0);
+        for item in items {
+            targets.extend(item.deps.iter().map(|&dep| narrow(dep)));
+            offsets.push(narrow(targets.len()));
+        }
+        Csr { offsets, targets }.noted()
+    }
+
+    /// The items depending on every item, in input order as `dependents`
+    /// lists them.
+    pub fn reverse<T>(items: &[Item<T>]) -> Self {
+        let mut offsets = vec![0u32; items.len() + 1];
+        for &dep in items.iter().flat_map(|item| &item.deps) {
+            offsets[dep + 1] += 1;
+        }
+        for index in 0..items.len() {
+            offsets[index + 1] += offsets[index];
+        }
+        // Fill each item's slice from its start, moving the start along.
+        let mut next: Vec<_> = offsets[..items.len()].to_vec();
+        let mut targets = vec![0u32; offsets[items.len()] as usize];
+        for (index, item) in items.iter().enumerate() {
+            for &dep in &item.deps {
+                targets[next[dep] as usize] = narrow(index);
+                next[dep] += 1;
+            }
+        }
+        Csr { offsets, targets }.noted()
+    }
+
+    /// Notes the size of the graph for `--timings`.
+    fn noted(self) -> Self {
+        timings::note_size(self.len(), self.edge_count(), self.offsets.capacity() + self.targets.capacity());
+        self
+    }
+
+    /// How many items there are.
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many edges there are.
+    pub fn edge_count(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// The targets of the edges out of `index`.
+    pub fn edges(&self, index: usize) -> &[u32] {
+        &self.targets[self.offsets[index] as usize..self.offsets[index + 1] as usize]
+    }
+
+    /// Fails when an edge goes to an item there isn't, which the analyses
+    /// rely on not happening.
+    pub fn check(&self) -> Result<(), DepsError> {
+        for index in 0..self.len() {
+            if let Some(dep) = self.targets(index).find(|&dep| dep >= self.len()) {
+                return Err(DepsError::InvalidDependency { item: index, dep, len: self.len() });
+            }
+        }
+        Ok(())
+    }
+
+    /// The targets of the edges out of `index`, as indices.
+    pub fn targets(&self, index: usize) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator + '_ {
+        self.edges(index).iter().map(|&target| target as usize)
+    }
+}
+
+/// Item indices and edge counts are kept as `u32`, which halves the memory
+/// the edges take and is plenty for any dependency graph.
+fn narrow(value: usize) -> u32 {
+    u32::try_from(value).expect("graphs have fewer than 2^32 items and edges")
+}
@@ -0,0 +1,126 @@
+use std::io;
+use thiserror::Error;
+
+/// Everything that can go wrong reading, checking or writing a graph.
+#[derive(Debug, Error)]
+pub enum DepsError {
+    #[error("Could not read {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("Could not write {path}: {source}")]
+    Write { path: String, source: io::Error },
+    #[error("Could not write the output: {0}")]
+    Output(#[from] io::Error),
+    #[error("{0}")]
+    Usage(String),
+    #[error("{path} is empty, expected a graph")]
+    EmptyInput { path: String },
+    #[error("Could not parse {path}: {message}\n{snippet}")]
+    Parse { path: String, line: usize, column: usize, message: String, snippet: String },
+    #[error("Could not load {path}: {message}")]
+    Invalid { path: String, message: String },
+    #[error("{0} are not supported")]
+    Unsupported(String),
+    #[error("Undirected graphs without --treat-undirected-as are not supported")]
+    UndirectedGraph,
+    /// The nodes of the cycle, each depending on the next and the last on
+    /// the first.
+    #[error("Circular dependency detected: {} -> {}", .path.join(" -> "), .path[0])]
+    CycleDetected { path: Vec<String> },
+    #[error("Circular dependency detected: {} -> {}, introduced by {}", .cycle.join(" -> "), .cycle[0], .constraints.join(", "))]
+    ConstraintCycle { cycle: Vec<String>, constraints: Vec<String> },
+    /// The nodes a sort could not place, in input order.
+    #[error("Could not place {} in the order, they are on or behind a cycle", .nodes.join(", "))]
+    Unsorted { nodes: Vec<String> },
+    #[error("Cannot sort nodes that depend on themselves: {}", .0.join(", "))]
+    SelfLoops(Vec<String>),
+    #[error("Node {node} cannot depend on itself")]
+    SelfLoop { node: String },
+    #[error("Node {node} has {key}={value}, expected {expected}")]
+    Attribute { node: String, key: String, value: String, expected: &'static str },
+    #[error("There is no node named {name}{}", did_you_mean(.suggestions))]
+    UnknownNode { name: String, suggestions: Vec<String> },
+    #[error("Item {item} depends on item {dep}, but the items only go up to {}", .len - 1)]
+    InvalidDependency { item: usize, dep: usize, len: usize },
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(", did you mean {}?", suggestions.join(", "))
+    }
+}
+
+impl DepsError {
+    /// Builds a parse error from the report graphviz_rust produces, which
+    /// looks like ` --> 2:1 | ... = expected stmt` spread over several lines,
+    /// pointing into `contents`.
+    pub fn parse(path: &str, report: &str, contents: &str) -> Self {
+        let position = report.lines()
+            .find_map(|line| line.trim().strip_prefix("--> "))
+            .and_then(|position| position.split_once(':'));
+        let (line, column): (usize, usize) = match position {
+            Some((line, column)) => (line.parse().unwrap_or(0), column.parse().unwrap_or(0)),
+            None => (0, 0),
+        };
+        let message = report.lines()
+            .find_map(|line| line.trim().strip_prefix("= "))
+            .unwrap_or(report);
+
+        DepsError::at(path, line, column, message, contents)
+    }
+
+    /// Builds a parse error for the 1-based `line` and `column` of `contents`.
+    pub fn at(path: &str, line: usize, column: usize, message: &str, contents: &str) -> Self {
+        let message = message.to_string();
+        let source_line = contents.lines().nth(line.saturating_sub(1)).unwrap_or("");
+        let snippet = render_snippet(path, line, column, source_line);
+
+        DepsError::Parse { path: path.to_string(), line, column, message, snippet }
+    }
+}
+
+/// The widest source line shown in a diagnostic before it gets truncated.
+const SNIPPET_WIDTH: usize = 80;
+
+/// Renders `source_line` rustc-style with a caret under `column`.
+fn render_snippet(path: &str, line: usize, column: usize, source_line: &str) -> String {
+    // Expand tabs so the caret lines up no matter the terminal's tab width.
+    let mut chars = Vec::new();
+    let mut caret = 0;
+    for (i, c) in source_line.chars().enumerate() {
+        if i + 1 == column {
+            caret = chars.len();
+        }
+        if c == '\t' {
+            chars.extend([' '; 4]);
+        } else {
+            chars.push(c);
+        }
+    }
+    if column > source_line.chars().count() {
+        caret = chars.len();
+    }
+
+    let mut start = 0;
+    let mut end = chars.len();
+    if end > SNIPPET_WIDTH {
+        start = caret.saturating_sub(SNIPPET_WIDTH / 2).min(end - SNIPPET_WIDTH);
+        end = start + SNIPPET_WIDTH;
+    }
+    let mut text: String = chars[start..end].iter().collect();
+    let mut caret = caret - start;
+    if start > 0 {
+        text.insert(0, '…');
+        caret += 1;
+    }
+    if end < chars.len() {
+        text.push('…');
+    }
+
+    let gutter = " ".repeat(line.to_string().len());
+    format!(
+        "{gutter}--> {path}:{line}:{column}\n{gutter} |\n{line} | {text}\n{gutter} | {}^",
+        " ".repeat(caret),
+    )
+}
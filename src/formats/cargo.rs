@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+use serde_json::Value;
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Which crates and dependency kinds become part of the graph.
+pub struct Options {
+    pub include_external: bool,
+    pub include_dev: bool,
+}
+
+/// Runs `cargo metadata` for the workspace containing `manifest_path`.
+pub fn metadata(manifest_path: &str) -> Result<String, DepsError> {
+    let command = format!("cargo metadata --manifest-path {}", manifest_path);
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--manifest-path", manifest_path])
+        .output()
+        .map_err(|source| DepsError::Io { path: command.clone(), source })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().find(|line| !line.trim().is_empty()).unwrap_or("failed").trim();
+        return Err(DepsError::Invalid { path: command, message: message.to_string() });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Loads the crate dependency graph from the `resolve.nodes` section of
+/// `cargo metadata --format-version 1` output.
+pub fn load(loader: &mut Loader, path: &str, contents: &str, options: &Options) -> Result<(), DepsError> {
+    let metadata: Value = serde_json::from_str(contents)
+        .map_err(|err| DepsError::at(path, err.line(), err.column(), "not valid cargo metadata JSON", contents))?;
+    let invalid = |message: &str| DepsError::Invalid { path: path.to_string(), message: message.to_string() };
+
+    let packages = metadata["packages"].as_array().ok_or_else(|| invalid("missing \"packages\""))?;
+    let nodes = metadata["resolve"]["nodes"].as_array()
+        .ok_or_else(|| invalid("missing \"resolve.nodes\", was it generated with --no-deps?"))?;
+    let members: HashSet<&str> = metadata["workspace_members"].as_array()
+        .ok_or_else(|| invalid("missing \"workspace_members\""))?
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+
+    // Crates are named by package name, with the version added only when
+    // several versions of the same crate are in the graph.
+    let mut versions: HashMap<&str, usize> = HashMap::new();
+    for package in packages {
+        *versions.entry(package["name"].as_str().unwrap_or_default()).or_default() += 1;
+    }
+    let mut names = HashMap::new();
+    for package in packages {
+        let (Some(id), Some(name)) = (package["id"].as_str(), package["name"].as_str()) else {
+            return Err(invalid("packages need an \"id\" and a \"name\""));
+        };
+        let name = match versions[name] {
+            1 => name.to_string(),
+            _ => format!("{}@{}", name, package["version"].as_str().unwrap_or_default()),
+        };
+        names.insert(id, name);
+    }
+
+    let included = |id: &str| options.include_external || members.contains(id);
+    let name_of = |id: &str| names.get(id).cloned().ok_or_else(|| invalid(&format!("unknown package {}", id)));
+
+    for node in nodes {
+        let id = node["id"].as_str().ok_or_else(|| invalid("resolve nodes need an \"id\""))?;
+        if !included(id) {
+            continue;
+        }
+        let a = loader.index_of_name(&name_of(id)?);
+
+        for dep in node["deps"].as_array().into_iter().flatten() {
+            let Some(pkg) = dep["pkg"].as_str() else {
+                continue;
+            };
+            if !included(pkg) || (!options.include_dev && is_dev_only(dep)) {
+                continue;
+            }
+            let b = loader.index_of_name(&name_of(pkg)?);
+            loader.add_edge(a, b);
+        }
+    }
+
+    Ok(())
+}
+
+/// Dev-dependencies legitimately form cycles, e.g. a crate testing itself
+/// through a helper crate that depends on it.
+fn is_dev_only(dep: &Value) -> bool {
+    match dep["dep_kinds"].as_array() {
+        Some(kinds) if !kinds.is_empty() => kinds.iter().all(|kind| kind["kind"] == "dev"),
+        _ => false,
+    }
+}
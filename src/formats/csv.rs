@@ -0,0 +1,109 @@
+use csv::{ReaderBuilder, StringRecord};
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Which CSV columns hold the two ends of each edge.
+pub struct Columns<'a> {
+    /// A header name, or a 1-based column number.
+    pub from: &'a str,
+    pub to: &'a str,
+    pub has_header: bool,
+}
+
+/// Loads one `from,to` edge per row. Any other columns are ignored, and a
+/// row with an empty `to` field declares an isolated node.
+pub fn load(loader: &mut Loader, path: &str, contents: &str, columns: &Columns) -> Result<(), DepsError> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(columns.has_header)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let invalid = |message: String| DepsError::Invalid { path: path.to_string(), message };
+
+    let headers = if columns.has_header {
+        reader.headers().map_err(|err| invalid(err.to_string()))?.clone()
+    } else {
+        StringRecord::new()
+    };
+    let from = column_index(columns.from, &headers).map_err(invalid)?;
+    let to = column_index(columns.to, &headers).map_err(invalid)?;
+
+    for record in reader.records() {
+        let record = record.map_err(|err| match err.position() {
+            Some(position) => DepsError::at(path, position.line() as usize, 1, &err.to_string(), contents),
+            None => invalid(err.to_string()),
+        })?;
+        let line = record.position().map_or(0, |position| position.line() as usize);
+        let field = |index: usize, name: &str| {
+            record.get(index).ok_or_else(|| {
+                DepsError::at(path, line, 1, &format!("this row has no column \"{}\"", name), contents)
+            })
+        };
+
+        let a = field(from, columns.from)?;
+        let b = field(to, columns.to)?;
+        let a = loader.index_of_name(a);
+        if !b.is_empty() {
+            let b = loader.index_of_name(b);
+            loader.add_edge(a, b);
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves a column given by header name or 1-based number to an index.
+fn column_index(column: &str, headers: &StringRecord) -> Result<usize, String> {
+    if let Some(index) = headers.iter().position(|header| header == column) {
+        return Ok(index);
+    }
+    match column.parse::<usize>() {
+        Ok(0) => Err("column numbers start at 1".to_string()),
+        Ok(number) => Ok(number - 1),
+        Err(_) if headers.is_empty() => {
+            Err(format!("column \"{}\" can only be found by name in a file with a header", column))
+        },
+        Err(_) => Err(format!("there is no column \"{}\" in the header", column)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_str(contents: &str, from: &str, to: &str, has_header: bool) -> Result<Loader, DepsError> {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "edges.csv", contents, &Columns { from, to, has_header })?;
+        Ok(loader)
+    }
+
+    /// Every item with the names of its dependencies, in input order.
+    fn graph(loader: &Loader) -> Vec<(&str, Vec<&str>)> {
+        loader.items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| loader.items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn columns_are_found_by_header() {
+        let contents = "kind,to,from\nbuild,b,a\nrun,\"c, d\",b\ntest,,lone\n";
+        let loader = load_str(contents, "from", "to", true).unwrap();
+        assert_eq!(graph(&loader), [("a", vec!["b"]), ("b", vec!["c, d"]), ("c, d", vec![]), ("lone", vec![])]);
+        assert_eq!(graph(&load_str(contents, "3", "2", true).unwrap()), graph(&loader));
+        let missing = load_str(contents, "source", "to", true).err().unwrap();
+        assert_eq!(missing.to_string(), "Could not load edges.csv: there is no column \"source\" in the header");
+    }
+
+    #[test]
+    fn without_a_header_the_first_row_is_an_edge() {
+        let loader = load_str("a,b,extra\nb,c\n", "1", "2", false).unwrap();
+        assert_eq!(graph(&loader), [("a", vec!["b"]), ("b", vec!["c"]), ("c", vec![])]);
+        assert!(load_str("a,b\n", "from", "2", false).is_err());
+    }
+
+    #[test]
+    fn a_short_row_is_refused_at_its_line() {
+        let error = load_str("from,to\na,b\nc\n", "from", "to", true).err().unwrap();
+        assert!(matches!(&error, DepsError::Parse { line: 3, .. }), "{}", error);
+        assert!(error.to_string().contains("this row has no column \"to\""), "{}", error);
+    }
+}
@@ -0,0 +1,191 @@
+use dot_structures::{Attribute, Edge, EdgeTy, Graph, Id, Node as DotNode, NodeId, Stmt, Vertex};
+use graphviz_rust::printer::{DotPrinter, PrinterContext};
+use crate::analysis::find_self_loops;
+use crate::csr::Csr;
+use crate::graph::{Attrs, Item, Node};
+use crate::parallel;
+
+/// Rebuilds a digraph from `items`, declaring every node with the
+/// attributes it was read with so isolated ones are kept, and writing every
+/// edge with its own. `node_attrs` and `edge_attrs` give extra attributes
+/// for the node at an index and for the edge between two indices,
+/// replacing any the node or edge already had.
+pub fn to_graphviz(
+    items: &[Item<Node>],
+    node_attrs: impl Fn(usize) -> Attrs,
+    edge_attrs: impl Fn(usize, usize) -> Attrs,
+) -> Graph {
+    let mut stmts = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let mut attrs = Vec::new();
+        if let Some(label) = &item.label {
+            attrs.push(attribute("label", label));
+        }
+        attrs.extend(merged(&item.attributes, &node_attrs(index)));
+        stmts.push(Stmt::Node(DotNode::new(node_id(&item.name), attrs)));
+    }
+    let none = Attrs::new();
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            let attrs = merged(item.edge(&items[b].name).unwrap_or(&none), &edge_attrs(a, b));
+            let ty = EdgeTy::Pair(Vertex::N(node_id(&items[a].name)), Vertex::N(node_id(&items[b].name)));
+            stmts.push(Stmt::Edge(Edge { ty, attributes: attrs }));
+        }
+    }
+
+    Graph::DiGraph { id: Id::Anonymous(String::new()), strict: false, stmts }
+}
+
+/// Renders the graph as DOT with the nodes and edges of every cycle
+/// highlighted, leaving the rest of the graph as it was.
+pub fn annotate_cycles(items: &[Item<Node>], graph: &Csr, threads: usize) -> String {
+    let mut component = vec![0; items.len()];
+    let mut on_cycle = vec![false; items.len()];
+    for (number, members) in parallel::strongly_connected_components(items, graph, threads).into_iter().enumerate() {
+        for &member in &members {
+            component[member] = number;
+            on_cycle[member] = members.len() > 1;
+        }
+    }
+    for index in find_self_loops(items) {
+        on_cycle[index] = true;
+    }
+
+    let attrs = |pairs: &[(&str, &str)]| pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+    let graph = to_graphviz(
+        items,
+        |index| if on_cycle[index] {
+            attrs(&[("style", "filled"), ("fillcolor", "mistyrose")])
+        } else {
+            Attrs::new()
+        },
+        |a, b| if on_cycle[a] && component[a] == component[b] {
+            attrs(&[("color", "red"), ("penwidth", "2")])
+        } else {
+            Attrs::new()
+        },
+    );
+    print(&graph)
+}
+
+/// Prints `graph` as DOT text, ending with a newline.
+pub fn print(graph: &Graph) -> String {
+    let mut text = graph.print(&mut PrinterContext::default());
+    text.push('\n');
+    text
+}
+
+/// Writes `text` as a DOT identifier, quoting it when needed.
+pub fn quote(text: &str) -> String {
+    match id(text) {
+        Id::Plain(text) | Id::Escaped(text) | Id::Html(text) | Id::Anonymous(text) => text,
+    }
+}
+
+/// The attributes of `own` that `extra` doesn't replace, followed by
+/// `extra`.
+fn merged(own: &Attrs, extra: &Attrs) -> Vec<Attribute> {
+    own.iter()
+        .filter(|(key, _)| extra.get(key).is_none())
+        .chain(extra)
+        .map(|(key, value)| attribute(key, value))
+        .collect()
+}
+
+fn node_id(name: &str) -> NodeId {
+    NodeId(id(name), None)
+}
+
+fn attribute(key: &str, value: &str) -> Attribute {
+    Attribute(id(key), id(value))
+}
+
+/// The words DOT keeps for itself, in any case.
+const KEYWORDS: [&str; 6] = ["graph", "node", "edge", "digraph", "subgraph", "strict"];
+
+/// Quotes `text` unless it is a plain DOT identifier or number. Keywords
+/// are quoted too, or they would be read as keywords.
+fn id(text: &str) -> Id {
+    let is_identifier = text.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.iter().any(|keyword| keyword.eq_ignore_ascii_case(text));
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let is_number = digits.chars().any(|c| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && digits.matches('.').count() <= 1;
+    if is_identifier || is_number {
+        Id::Plain(text.to_string())
+    } else {
+        Id::Escaped(format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Loader, Undirected, from_graphviz};
+
+    /// Writes a graph with these nodes and edges and reads it back.
+    fn round_trip(edges: &[(&str, &str)]) -> Vec<(String, Vec<String>)> {
+        let mut loader = Loader::new(false);
+        for &(from, to) in edges {
+            let (a, b) = (loader.index_of_name(from), loader.index_of_name(to));
+            loader.add_edge(a, b);
+        }
+        let text = print(&to_graphviz(&loader.items, |_| Attrs::new(), |_, _| Attrs::new()));
+        let mut read = Loader::new(false);
+        let graph = graphviz_rust::parse(&text).unwrap_or_else(|err| panic!("{}\n{}", err, text));
+        from_graphviz(&mut read, &graph, Undirected::Error).unwrap();
+        read.items.iter()
+            .map(|item| (item.name.clone(), item.deps.iter().map(|&dep| read.items[dep].name.clone()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn keywords_are_quoted_in_any_case() {
+        for keyword in KEYWORDS {
+            for name in [keyword.to_string(), keyword.to_uppercase(), keyword[..1].to_uppercase() + &keyword[1..]] {
+                assert_eq!(quote(&name), format!("\"{}\"", name));
+            }
+        }
+        assert_eq!(quote("nodes"), "nodes");
+        assert_eq!(quote("graph_1"), "graph_1");
+    }
+
+    #[test]
+    fn names_read_back_the_same() {
+        let edges = [
+            ("node", "edge"), ("Graph", "DIGRAPH"), ("subgraph", "Strict"), ("strict", "node"),
+            ("plain", "with space"), ("quote\"d", "back\\slash"), ("-1.5", "42"), ("nodes", "graph_1"),
+        ];
+        let read = round_trip(&edges);
+        let mut expected: Vec<(String, Vec<String>)> = Vec::new();
+        for (from, to) in edges {
+            for name in [from, to] {
+                if !expected.iter().any(|(other, _)| other == name) {
+                    expected.push((name.to_string(), Vec::new()));
+                }
+            }
+            expected.iter_mut().find(|(name, _)| name == from).unwrap().1.push(to.to_string());
+        }
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn annotated_cycles_are_highlighted_and_the_rest_left_alone() {
+        let mut loader = Loader::new(false);
+        let text = "digraph { a -> b; b -> a; b -> c [color=blue]; c -> c; d -> a }";
+        from_graphviz(&mut loader, &graphviz_rust::parse(text).unwrap(), Undirected::Error).unwrap();
+        for threads in [1, 4] {
+            let annotated = annotate_cycles(&loader.items, loader.csr(), threads);
+            let mut read = Loader::new(false);
+            from_graphviz(&mut read, &graphviz_rust::parse(&annotated).unwrap(), Undirected::Error).unwrap();
+            let filled: Vec<_> = read.items.iter().map(|item| item.attribute("fillcolor").is_some()).collect();
+            assert_eq!(filled, [true, true, true, false]);
+            let color = |from: usize, to: &str| read.items[from].edge(to).and_then(|attrs| attrs.get("color"));
+            assert_eq!([color(0, "b"), color(1, "a"), color(2, "c")], [Some("red"); 3]);
+            // Edges between components keep what they had.
+            assert_eq!([color(1, "c"), color(3, "a")], [Some("blue"), None]);
+        }
+    }
+}
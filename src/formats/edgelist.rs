@@ -0,0 +1,138 @@
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Loads one edge per line, written `a b` or `a -> b`. A line with a
+/// single name declares an isolated node, names containing spaces can be
+/// double quoted, and everything after a `#` is a comment.
+pub fn load(loader: &mut Loader, path: &str, contents: &str) -> Result<(), DepsError> {
+
+    for (number, line) in contents.lines().enumerate() {
+        let number = number + 1;
+        let names = tokenize(line).map_err(|(column, message)| {
+            DepsError::at(path, number, column, message, contents)
+        })?;
+        match names.as_slice() {
+            [] => {},
+            [name] => {
+                loader.index_of_name(name);
+            },
+            [a, b] => {
+                let a = loader.index_of_name(a);
+                let b = loader.index_of_name(b);
+                loader.add_edge(a, b);
+            },
+            _ => return Err(DepsError::at(path, number, 1, "expected at most two names", contents)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits a line into names, or returns the 1-based column and reason it
+/// could not be split. Other files of names, like the changed nodes of
+/// `impact --changed-file` or the renames of `rename --map`, are read with
+/// it too.
+pub fn tokenize(line: &str) -> Result<Vec<String>, (usize, &'static str)> {
+    let chars: Vec<char> = line.chars().collect();
+    let is_arrow = |i: usize| chars[i] == '-' && chars.get(i + 1) == Some(&'>');
+    let mut names = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            break;
+        } else if is_arrow(i) {
+            i += 2;
+        } else if c == '"' {
+            let start = i;
+            let mut name = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some('"') => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        name.push(chars[i + 1]);
+                        i += 2;
+                    },
+                    Some(&c) => {
+                        name.push(c);
+                        i += 1;
+                    },
+                    None => return Err((start + 1, "unterminated quoted name")),
+                }
+            }
+            names.push(name);
+            i += 1;
+        } else {
+            let mut name = String::new();
+            while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '#' | '"') && !is_arrow(i) {
+                name.push(chars[i]);
+                i += 1;
+            }
+            names.push(name);
+        }
+    }
+
+    Ok(names)
+}
+
+/// Writes `name` so that `tokenize` reads it back as one name, quoting it
+/// when it is empty or has spaces, quotes, comments or arrows in it.
+pub fn quote(name: &str) -> String {
+    let plain = !name.is_empty()
+        && !name.contains("->")
+        && !name.chars().any(|c| c.is_whitespace() || matches!(c, '#' | '"'));
+    if plain {
+        return name.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in name.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_str(contents: &str) -> Result<Loader, DepsError> {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "edges.txt", contents)?;
+        Ok(loader)
+    }
+
+    /// Every item with the names of its dependencies, in input order.
+    fn graph(loader: &Loader) -> Vec<(&str, Vec<&str>)> {
+        loader.items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| loader.items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let loader = load_str("# the services\n\na b\n  b -> c  # trailing\n#a c\n").unwrap();
+        assert_eq!(graph(&loader), [("a", vec!["b"]), ("b", vec!["c"]), ("c", vec![])]);
+    }
+
+    #[test]
+    fn quoted_names_keep_their_spaces() {
+        let loader = load_str("\"web app\" -> \"a \\\"b\\\"\"\n\"x#y\" z\n").unwrap();
+        assert_eq!(graph(&loader), [("web app", vec!["a \"b\""]), ("a \"b\"", vec![]), ("x#y", vec!["z"]), ("z", vec![])]);
+        assert!(matches!(load_str("a \"b\n"), Err(DepsError::Parse { line: 1, column: 3, .. })));
+    }
+
+    #[test]
+    fn a_single_name_is_an_isolated_node() {
+        let loader = load_str("lone\na b\nb\n").unwrap();
+        assert_eq!(graph(&loader), [("lone", vec![]), ("a", vec!["b"]), ("b", vec![])]);
+        assert!(matches!(load_str("a b\na b c\n"), Err(DepsError::Parse { line: 2, .. })));
+    }
+}
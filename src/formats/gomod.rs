@@ -0,0 +1,32 @@
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Loads the output of `go mod graph`, one `module@version dependency@version`
+/// edge per line. The main module is written without a version. With
+/// `collapse_versions` every version of a module becomes the same node.
+pub fn load(loader: &mut Loader, path: &str, contents: &str, collapse_versions: bool) -> Result<(), DepsError> {
+
+    for (number, line) in contents.lines().enumerate() {
+        let mut modules = line.split_whitespace();
+        let (a, b) = match (modules.next(), modules.next(), modules.next()) {
+            (None, _, _) => continue,
+            (Some(a), Some(b), None) => (a, b),
+            _ => return Err(DepsError::at(path, number + 1, 1, "expected a module and a dependency", contents)),
+        };
+        let (a, b) = if collapse_versions {
+            (strip_version(a), strip_version(b))
+        } else {
+            (a, b)
+        };
+
+        let a = loader.index_of_name(a);
+        let b = loader.index_of_name(b);
+        loader.add_edge(a, b);
+    }
+
+    Ok(())
+}
+
+fn strip_version(module: &str) -> &str {
+    module.rsplit_once('@').map_or(module, |(module, _)| module)
+}
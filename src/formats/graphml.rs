@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use roxmltree::{Document, Node};
+use crate::graph::{Attrs, Item, Loader, Undirected};
+use crate::error::DepsError;
+
+/// Loads the `<node>` and `<edge>` elements of every `<graph>` in a GraphML
+/// document. Nodes are named by their `label` data when a key for it is
+/// declared, and by their `id` otherwise. Their other data, and that of
+/// the edges, become attributes named by the `attr.name` of their key.
+pub fn load(loader: &mut Loader, path: &str, contents: &str, undirected: Undirected) -> Result<(), DepsError> {
+    let document = Document::parse(contents).map_err(|err| {
+        let position = err.pos();
+        DepsError::at(path, position.row as usize, position.col as usize, &error_message(&err), contents)
+    })?;
+    let root = document.root_element();
+    if root.tag_name().name() != "graphml" {
+        return Err(DepsError::Invalid { path: path.to_string(), message: "expected a <graphml> document".to_string() });
+    }
+
+    let label_key = root.children()
+        .filter(|key| is(key, "key"))
+        .find(|key| {
+            key.attribute("attr.name") == Some("label")
+                && matches!(key.attribute("for"), None | Some("node") | Some("all"))
+        })
+        .and_then(|key| key.attribute("id"));
+    // The names of the keys declared for nodes and for edges, by id.
+    let names = |of: &str| -> HashMap<&str, &str> {
+        root.children()
+            .filter(|key| is(key, "key") && key.attribute("for").is_none_or(|target| target == "all" || target == of))
+            .filter_map(|key| Some((key.attribute("id")?, key.attribute("attr.name").or(key.attribute("id"))?)))
+            .collect()
+    };
+    let (node_keys, edge_keys) = (names("node"), names("edge"));
+    let data = |element: Node, keys: &HashMap<&str, &str>, skip: Option<&str>| -> Attrs {
+        element.children()
+            .filter(|data| is(data, "data") && data.attribute("key") != skip)
+            .filter_map(|data| {
+                let name = keys.get(data.attribute("key")?)?;
+                Some((name.to_string(), data.text().unwrap_or("").trim().to_string()))
+            })
+            .collect()
+    };
+
+    let mut indices = HashMap::new();
+    for graph in root.descendants().filter(|graph| is(graph, "graph")) {
+        if graph.attribute("edgedefault") == Some("undirected") {
+            loader.set_undirected(undirected)?;
+        }
+
+        for node in graph.children().filter(|node| is(node, "node")) {
+            let id = node.attribute("id").ok_or_else(|| missing(path, contents, node, "id"))?;
+            let label = label_key.and_then(|key| {
+                node.children()
+                    .find(|data| is(data, "data") && data.attribute("key") == Some(key))
+                    .and_then(|data| data.text())
+                    .map(str::trim)
+            });
+            let index = loader.index_of_name(label.unwrap_or(id));
+            indices.insert(id.to_string(), index);
+            for (key, value) in &data(node, &node_keys, label_key) {
+                loader.items[index].data.attributes.set(key.clone(), value.clone());
+            }
+        }
+
+        for edge in graph.children().filter(|edge| is(edge, "edge")) {
+            let source = edge.attribute("source").ok_or_else(|| missing(path, contents, edge, "source"))?;
+            let target = edge.attribute("target").ok_or_else(|| missing(path, contents, edge, "target"))?;
+            let a = node_index(loader, &mut indices, source);
+            let b = node_index(loader, &mut indices, target);
+            loader.add_edge(a, b);
+            loader.add_edge_attrs(a, b, &data(edge, &edge_keys, None));
+        }
+    }
+
+    Ok(())
+}
+
+/// Matches elements by local name, so namespaced documents work as well.
+fn is(node: &Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+/// Serializes `items` as a directed GraphML document with nodes keyed by
+/// name and labelled nodes carrying a `label` data element, which `load`
+/// reads back as the name. When `levels` is given, each node also gets
+/// its topological level as `level` data, and the `ignored` edges carry
+/// `ignored` data.
+pub fn to_graphml(items: &[Item<crate::graph::Node>], levels: Option<&[usize]>, ignored: &HashSet<(usize, usize)>) -> String {
+    let mut text = String::new();
+    text.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    text.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    if items.iter().any(|item| item.label.is_some()) {
+        text.push_str("  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n");
+    }
+    if levels.is_some() {
+        text.push_str("  <key id=\"level\" for=\"node\" attr.name=\"level\" attr.type=\"int\"/>\n");
+    }
+    if !ignored.is_empty() {
+        text.push_str("  <key id=\"ignored\" for=\"edge\" attr.name=\"ignored\" attr.type=\"boolean\"/>\n");
+    }
+    text.push_str("  <graph edgedefault=\"directed\">\n");
+    for (index, item) in items.iter().enumerate() {
+        let mut data = String::new();
+        if let Some(label) = &item.label {
+            let _ = write!(data, "<data key=\"label\">{}</data>", escape(label));
+        }
+        if let Some(levels) = levels {
+            let _ = write!(data, "<data key=\"level\">{}</data>", levels[index]);
+        }
+        if data.is_empty() {
+            let _ = writeln!(text, "    <node id=\"{}\"/>", escape(&item.name));
+        } else {
+            let _ = writeln!(text, "    <node id=\"{}\">{}</node>", escape(&item.name), data);
+        }
+    }
+    for (index, item) in items.iter().enumerate() {
+        for &dep in &item.deps {
+            let _ = write!(text, "    <edge source=\"{}\" target=\"{}\"", escape(&item.name), escape(&items[dep].name));
+            if ignored.contains(&(index, dep)) {
+                text.push_str("><data key=\"ignored\">true</data></edge>\n");
+            } else {
+                text.push_str("/>\n");
+            }
+        }
+    }
+    text.push_str("  </graph>\n</graphml>\n");
+    text
+}
+
+/// Escapes the characters XML reserves, in text and attribute values alike.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Edges may name nodes that were never declared with a `<node>` element.
+fn node_index(loader: &mut Loader, indices: &mut HashMap<String, usize>, id: &str) -> usize {
+    match indices.get(id) {
+        Some(&index) => index,
+        None => {
+            let index = loader.index_of_name(id);
+            indices.insert(id.to_string(), index);
+            index
+        },
+    }
+}
+
+fn missing(path: &str, contents: &str, node: Node, attribute: &str) -> DepsError {
+    let position = node.document().text_pos_at(node.range().start);
+    let message = format!("<{}> is missing the {} attribute", node.tag_name().name(), attribute);
+    DepsError::at(path, position.row as usize, position.col as usize, &message, contents)
+}
+
+/// roxmltree ends its messages with the position, which the snippet shows.
+fn error_message(err: &roxmltree::Error) -> String {
+    let message = err.to_string();
+    match message.rsplit_once(" at ") {
+        Some((message, _)) => message.to_string(),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::detect_cycle;
+
+    const CYCLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<g:graphml xmlns:g="http://graphml.graphdrawing.org/xmlns">
+  <g:key id="d0" for="node" attr.name="label" attr.type="string"/>
+  <g:graph edgedefault="directed">
+    <g:node id="n0"><g:data key="d0">api</g:data></g:node>
+    <g:node id="n1"/>
+    <g:edge source="n0" target="n1"/>
+    <g:edge source="n1" target="n2"/>
+    <g:edge source="n2" target="n0"/>
+  </g:graph>
+</g:graphml>
+"#;
+
+    #[test]
+    fn a_namespaced_cycle_is_read_and_found() {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "graph.graphml", CYCLE, Undirected::Error).unwrap();
+        let names: Vec<_> = loader.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["api", "n1", "n2"]);
+        assert_eq!(detect_cycle(loader.csr(), &HashSet::new()).unwrap(), Some(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn undirected_graphs_need_to_be_asked_for() {
+        let undirected = CYCLE.replace("edgedefault=\"directed\"", "edgedefault=\"undirected\"");
+        let mut loader = Loader::new(false);
+        let refused = load(&mut loader, "graph.graphml", &undirected, Undirected::Error);
+        assert!(matches!(refused, Err(DepsError::UndirectedGraph)));
+
+        let mut loader = Loader::new(false);
+        load(&mut loader, "graph.graphml", &undirected, Undirected::Both).unwrap();
+        assert_eq!(loader.items[0].deps, [1, 2]);
+    }
+
+    #[test]
+    fn what_is_written_reads_back() {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "graph.graphml", CYCLE, Undirected::Error).unwrap();
+        let mut read = Loader::new(false);
+        load(&mut read, "graph.graphml", &to_graphml(&loader.items, None, &HashSet::new()), Undirected::Error).unwrap();
+        let graph = |loader: &Loader| loader.items.iter().map(|item| (item.name.clone(), item.deps.clone())).collect::<Vec<_>>();
+        assert_eq!(graph(&read), graph(&loader));
+    }
+}
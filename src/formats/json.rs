@@ -0,0 +1,110 @@
+use serde_json::Value;
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Loads either an adjacency map `{"a": ["b", "c"], "b": []}` or a node and
+/// edge list `{"nodes": ["a", "b"], "edges": [["a", "b"]]}`.
+pub fn load(loader: &mut Loader, path: &str, contents: &str) -> Result<(), DepsError> {
+    let value: Value = serde_json::from_str(contents).map_err(|err| {
+        // The position is shown in the snippet, no need to repeat it.
+        let message = err.to_string();
+        let suffix = format!(" at line {} column {}", err.line(), err.column());
+        let message = message.strip_suffix(&suffix).unwrap_or(&message);
+        DepsError::at(path, err.line(), err.column(), message, contents)
+    })?;
+    let invalid = |message: &str| DepsError::Invalid { path: path.to_string(), message: message.to_string() };
+
+    let map = value.as_object().ok_or_else(|| invalid("expected a JSON object"))?;
+
+    if is_node_edge_list(&value) {
+        for node in map["nodes"].as_array().unwrap() {
+            let name = node.as_str().ok_or_else(|| invalid("node names must be strings"))?;
+            loader.index_of_name(name);
+        }
+        for edge in map["edges"].as_array().unwrap() {
+            let pair = edge.as_array()
+                .and_then(|pair| match pair.as_slice() {
+                    [a, b] => Some((a.as_str()?, b.as_str()?)),
+                    _ => None,
+                })
+                .ok_or_else(|| invalid("edges must be pairs of node names"))?;
+            let a = loader.index_of_name(pair.0);
+            let b = loader.index_of_name(pair.1);
+            loader.add_edge(a, b);
+        }
+        return Ok(());
+    }
+
+    for (name, deps) in map {
+        let a = loader.index_of_name(name);
+        let deps = deps.as_array()
+            .ok_or_else(|| invalid(&format!("dependencies of \"{}\" must be an array", name)))?;
+        for dep in deps {
+            let dep = dep.as_str()
+                .ok_or_else(|| invalid(&format!("dependencies of \"{}\" must be strings", name)))?;
+            let b = loader.index_of_name(dep);
+            loader.add_edge(a, b);
+        }
+    }
+
+    Ok(())
+}
+
+/// An adjacency map can have nodes called "nodes" and "edges" too, so only
+/// treat the document as a node and edge list when the edges are pairs.
+fn is_node_edge_list(value: &Value) -> bool {
+    let Some(map) = value.as_object() else {
+        return false;
+    };
+    map.len() == 2
+        && map.get("nodes").is_some_and(Value::is_array)
+        && map.get("edges").and_then(Value::as_array).is_some_and(|edges| edges.iter().all(Value::is_array))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+    use crate::analysis::detect_cycle;
+
+    fn load_str(contents: &str) -> Loader {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "graph.json", contents).unwrap();
+        loader
+    }
+
+    /// Every item with the names of its dependencies, in input order.
+    fn graph(loader: &Loader) -> Vec<(&str, Vec<&str>)> {
+        loader.items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| loader.items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn both_shapes_read_the_same_graph() {
+        let map = load_str(r#"{"a": ["b", "c", "b"], "b": ["c"]}"#);
+        let list = load_str(r#"{"nodes": ["a", "b"], "edges": [["a", "b"], ["a", "c"], ["b", "c"], ["a", "b"]]}"#);
+        assert_eq!(graph(&map), [("a", vec!["b", "c"]), ("b", vec!["c"]), ("c", vec![])]);
+        assert_eq!(graph(&list), graph(&map));
+    }
+
+    #[test]
+    fn nodes_and_edges_can_be_names_in_a_map() {
+        let loader = load_str(r#"{"nodes": ["edges"], "edges": ["nodes"]}"#);
+        assert_eq!(graph(&loader), [("nodes", vec!["edges"]), ("edges", vec!["nodes"])]);
+    }
+
+    #[test]
+    fn cycles_are_found() {
+        let loader = load_str(r#"{"a": ["b"], "b": ["c"], "c": ["a"]}"#);
+        assert_eq!(detect_cycle(loader.csr(), &HashSet::new()).unwrap(), Some(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn malformed_documents_are_refused() {
+        let mut loader = Loader::new(false);
+        assert!(matches!(load(&mut loader, "graph.json", r#"["a"]"#), Err(DepsError::Invalid { .. })));
+        assert!(matches!(load(&mut loader, "graph.json", r#"{"a": "b"}"#), Err(DepsError::Invalid { .. })));
+        assert!(matches!(load(&mut loader, "graph.json", "{\"a\": [}"), Err(DepsError::Parse { line: 1, .. })));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Loads the targets of a Makefile's rules, `target: dep1 dep2`, as nodes
+/// depending on their prerequisites. Variables are not expanded, so a
+/// `$(VAR)` is kept as a node name of its own.
+pub fn load(loader: &mut Loader, path: &str, contents: &str) -> Result<(), DepsError> {
+
+    for (number, line) in logical_lines(contents) {
+        // Recipes are shell commands, not rules.
+        if line.starts_with('\t') {
+            continue;
+        }
+        let line = strip_comment(&line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((targets, prereqs)) = split_rule(line) else {
+            continue;
+        };
+        // A target-specific variable such as `app: CFLAGS += -O2`.
+        if split_outside_parens(prereqs, '=').is_some() {
+            continue;
+        }
+
+        let targets = words(targets);
+        if targets.is_empty() {
+            return Err(DepsError::at(path, number, 1, "rule has no target", contents));
+        }
+        if targets.iter().any(|target| target.contains('%')) {
+            if loader.verbose {
+                crate::report::warning(format_args!("{}:{}: Skipping pattern rule", path, number));
+            }
+            continue;
+        }
+        // Special targets such as .PHONY configure make itself.
+        if targets.iter().all(|target| target.starts_with('.')) {
+            continue;
+        }
+
+        // The `|` separates order-only prerequisites, which still have to
+        // be built first.
+        let prereqs: Vec<&str> = words(prereqs).into_iter().filter(|word| *word != "|").collect();
+        for target in &targets {
+            let a = loader.index_of_name(target);
+            for prereq in &prereqs {
+                let b = loader.index_of_name(prereq);
+                loader.add_edge(a, b);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Joins lines ending in a backslash with the next one, numbering each
+/// logical line by the physical line it starts on.
+fn logical_lines(contents: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (index, line) in contents.lines().enumerate() {
+        let (number, mut text) = current.take().unwrap_or((index + 1, String::new()));
+        if !text.is_empty() {
+            text.push(' ');
+            text.push_str(line.trim_start());
+        } else {
+            text.push_str(line);
+        }
+        match text.strip_suffix('\\') {
+            Some(joined) => current = Some((number, joined.to_string())),
+            None => lines.push((number, text)),
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '#' if !escaped => return &line[..i],
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+/// Splits `targets: prereqs`, ignoring assignments like `CC := gcc` which
+/// also contain a colon.
+fn split_rule(line: &str) -> Option<(&str, &str)> {
+    let colon = split_outside_parens(line, ':')?.0.len();
+    if let Some((before, _)) = split_outside_parens(line, '=') {
+        if before.len() < colon || line[colon..].starts_with(":=") || line[colon..].starts_with("::=") {
+            return None;
+        }
+    }
+    let prereqs = line[colon + 1..].strip_prefix(':').unwrap_or(&line[colon + 1..]);
+    Some((&line[..colon], prereqs))
+}
+
+/// Splits at the first `separator` that is not inside a `$(...)` or `${...}`.
+fn split_outside_parens(text: &str, separator: char) -> Option<(&str, &str)> {
+    let mut depth = 0usize;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 => return Some((&text[..i], &text[i + 1..])),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Splits on whitespace, keeping `$(call f, a b)` together as one word.
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut depth = 0usize;
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(start) = start.take() {
+                    words.push(&text[start..i]);
+                }
+                continue;
+            },
+            _ => {},
+        }
+        start.get_or_insert(i);
+    }
+    words.extend(start.map(|start| &text[start..]));
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_str(contents: &str) -> Loader {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "Makefile", contents).unwrap();
+        loader
+    }
+
+    /// Every item with the names of its dependencies, in input order.
+    fn graph(loader: &Loader) -> Vec<(&str, Vec<&str>)> {
+        loader.items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| loader.items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn continued_lines_are_one_rule() {
+        let loader = load_str("app: main.o \\\n     util.o \\\n\tlib.a\n\t$(CC) -o $@ $^\n");
+        assert_eq!(graph(&loader), [("app", vec!["main.o", "util.o", "lib.a"]), ("main.o", vec![]), ("util.o", vec![]), ("lib.a", vec![])]);
+    }
+
+    #[test]
+    fn every_target_of_a_rule_gets_its_prerequisites() {
+        let loader = load_str("a.o b.o: common.h | build\n");
+        assert_eq!(graph(&loader), [("a.o", vec!["common.h", "build"]), ("common.h", vec![]), ("build", vec![]), ("b.o", vec!["common.h", "build"])]);
+    }
+
+    #[test]
+    fn what_is_not_a_rule_is_skipped() {
+        let contents = "\
+# app: ignored
+CC := gcc
+OBJS = a.o b.o
+.PHONY: all
+%.o: %.c
+\tgcc -c $<
+all: $(OBJS) app # built last
+app: CFLAGS += -O2
+";
+        assert_eq!(graph(&load_str(contents)), [("all", vec!["$(OBJS)", "app"]), ("$(OBJS)", vec![]), ("app", vec![])]);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use clap::ValueEnum;
+use crate::graph::{Item, Node};
+
+/// The direction a Mermaid flowchart is laid out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    /// Top to bottom
+    #[value(name = "TD", alias = "td")]
+    TopDown,
+    /// Left to right
+    #[value(name = "LR", alias = "lr")]
+    LeftRight,
+}
+
+/// Words Mermaid reads as keywords rather than node ids.
+const KEYWORDS: &[&str] = &[
+    "end", "graph", "flowchart", "subgraph", "direction", "click", "call", "href",
+    "style", "class", "classDef", "linkStyle", "default",
+];
+
+/// Converts `items` into a Mermaid flowchart with one `a --> b` line per
+/// edge. Nodes whose name Mermaid can't take as an id, or whose label
+/// differs from their name, are declared as `n1["label"]` first, as are
+/// nodes without any edges so they still appear. The `ignored` edges are
+/// drawn dotted, as `a -.-> b`.
+pub fn to_mermaid(items: &[Item<Node>], direction: Direction, ignored: &HashSet<(usize, usize)>) -> String {
+    let ids: Vec<_> = items.iter().enumerate().map(|(index, item)| node_id(index, &item.name)).collect();
+    let mut has_edges = vec![false; items.len()];
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            has_edges[a] = true;
+            has_edges[b] = true;
+        }
+    }
+
+    let mut text = match direction {
+        Direction::TopDown => "flowchart TD\n".to_string(),
+        Direction::LeftRight => "flowchart LR\n".to_string(),
+    };
+    for (index, item) in items.iter().enumerate() {
+        let label = item.to_string();
+        if label != ids[index] {
+            let _ = writeln!(text, "    {}[\"{}\"]", ids[index], escape(&label));
+        } else if !has_edges[index] {
+            let _ = writeln!(text, "    {}", ids[index]);
+        }
+    }
+    for (a, item) in items.iter().enumerate() {
+        for &b in &item.deps {
+            let arrow = if ignored.contains(&(a, b)) { "-.->" } else { "-->" };
+            let _ = writeln!(text, "    {} {} {}", ids[a], arrow, ids[b]);
+        }
+    }
+    text
+}
+
+/// Uses `name` as the id when it is a plain identifier, and `n<index>`
+/// otherwise. Names that look like `n<digits>` are renamed too so they
+/// can't collide with a generated id.
+fn node_id(index: usize, name: &str) -> String {
+    let is_identifier = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let is_generated = name.strip_prefix('n').is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()));
+    if is_identifier && !is_generated && !KEYWORDS.contains(&name) {
+        name.to_string()
+    } else {
+        format!("n{}", index + 1)
+    }
+}
+
+/// Escapes a label for use inside double quotes, where Mermaid only
+/// understands quotes written as an entity.
+fn escape(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::graph::Loader;
+
+    fn items(nodes: &[&str], edges: &[(&str, &str)]) -> Vec<Item<Node>> {
+        let mut loader = Loader::new(false);
+        for node in nodes {
+            loader.index_of_name(node);
+        }
+        for &(from, to) in edges {
+            let (a, b) = (loader.index_of_name(from), loader.index_of_name(to));
+            loader.add_edge(a, b);
+        }
+        loader.graph.items
+    }
+
+    /// Reads a flowchart back into its nodes and edges by label, checking
+    /// every line is a declaration or an edge between plain ids.
+    fn read_back(text: &str) -> (Vec<String>, Vec<(String, String)>) {
+        let is_id = |id: &str| {
+            id.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !KEYWORDS.contains(&id)
+        };
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("flowchart LR"));
+        let mut labels = HashMap::new();
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for line in lines {
+            let line = line.strip_prefix("    ").unwrap_or_else(|| panic!("not indented: {:?}", line));
+            if let Some((a, b)) = line.split_once(" --> ") {
+                assert!(is_id(a) && is_id(b), "{:?}", line);
+                edges.push((a.to_string(), b.to_string()));
+                continue;
+            }
+            let (id, label) = match line.split_once("[\"") {
+                Some((id, label)) => (id, label.strip_suffix("\"]").unwrap().replace("#quot;", "\"")),
+                None => (line, line.to_string()),
+            };
+            assert!(is_id(id), "{:?}", line);
+            assert!(labels.insert(id.to_string(), label.clone()).is_none(), "{} declared twice", id);
+            nodes.push(label);
+        }
+        let name = |id: &String| labels.get(id).cloned().unwrap_or_else(|| id.clone());
+        (nodes, edges.iter().map(|(a, b)| (name(a), name(b))).collect())
+    }
+
+    #[test]
+    fn awkward_names_get_safe_ids_and_keep_their_labels() {
+        let edges = [("my node", "-lib"), ("-lib", "[x]"), ("end", "n2"), ("plain", "my node"), ("say \"hi\"", "plain")];
+        let items = items(&["lone", "also lone"], &edges);
+        let text = to_mermaid(&items, Direction::LeftRight, &HashSet::new());
+        let (declared, read) = read_back(&text);
+
+        let edges: Vec<_> = edges.iter().map(|&(a, b)| (a.to_string(), b.to_string())).collect();
+        assert_eq!(read, edges);
+        for name in ["lone", "also lone", "my node", "-lib", "[x]", "end", "n2", "say \"hi\""] {
+            assert!(declared.iter().any(|label| label == name), "{} is not declared in\n{}", name, text);
+        }
+        assert!(!declared.iter().any(|label| label == "plain"), "plain needs no declaration");
+    }
+
+    #[test]
+    fn the_direction_is_the_header() {
+        let items = items(&[], &[("a", "b")]);
+        assert_eq!(to_mermaid(&items, Direction::TopDown, &HashSet::new()), "flowchart TD\n    a --> b\n");
+        let ignored = HashSet::from([(0, 1)]);
+        assert_eq!(to_mermaid(&items, Direction::LeftRight, &ignored), "flowchart LR\n    a -.-> b\n");
+    }
+}
@@ -0,0 +1,20 @@
+//! Loaders for the input formats other than Graphviz, each adding its
+//! nodes and edges to a `Loader` the same way `from_graphviz` does, and
+//! writers for the output formats. The formats read with serde are only
+//! there with the `serde` feature.
+
+#[cfg(feature = "serde")]
+pub mod cargo;
+pub mod csv;
+pub mod dot;
+pub mod edgelist;
+pub mod gomod;
+pub mod graphml;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod makefile;
+pub mod mermaid;
+#[cfg(feature = "serde")]
+pub mod npm;
+#[cfg(feature = "serde")]
+pub mod yaml;
@@ -0,0 +1,191 @@
+use std::collections::HashSet;
+use serde_json::{Map, Value};
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Loads a `package-lock.json`, naming each package `name@version`. The
+/// `packages` section of lockfile v2 and v3 is preferred, falling back to
+/// the nested `dependencies` section of v1.
+pub fn load(loader: &mut Loader, path: &str, contents: &str) -> Result<(), DepsError> {
+    let lockfile: Value = serde_json::from_str(contents)
+        .map_err(|err| DepsError::at(path, err.line(), err.column(), "not a valid package-lock.json", contents))?;
+
+    if let Some(packages) = lockfile["packages"].as_object() {
+        load_packages(loader, packages);
+    } else if let Some(dependencies) = lockfile["dependencies"].as_object() {
+        load_v1(loader, dependencies, &mut Vec::new(), &mut HashSet::new());
+    } else {
+        return Err(DepsError::Invalid {
+            path: path.to_string(),
+            message: "expected a \"packages\" or \"dependencies\" section".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+fn package_name(name: &str, package: &Value) -> String {
+    match package["version"].as_str() {
+        Some(version) => format!("{}@{}", name, version),
+        None => name.to_string(),
+    }
+}
+
+/// Loads the flat `packages` map, whose keys are install paths such as
+/// `node_modules/a/node_modules/b`, and `""` for the root package.
+fn load_packages(loader: &mut Loader, packages: &Map<String, Value>) {
+    // Workspace packages are linked into node_modules, follow the link so
+    // both paths resolve to the same package.
+    let resolve = |key: &str| -> Option<(String, &Value)> {
+        let package = packages.get(key)?;
+        if package["link"] == true {
+            let target = package["resolved"].as_str()?;
+            return Some((target.to_string(), packages.get(target)?));
+        }
+        Some((key.to_string(), package))
+    };
+    let name_of = |key: &str, package: &Value| {
+        let name = package["name"].as_str().unwrap_or_else(|| {
+            key.rsplit_once("node_modules/").map_or(key, |(_, name)| name)
+        });
+        package_name(name, package)
+    };
+
+    let mut seen = HashSet::new();
+    for (key, package) in packages {
+        if package["link"] == true {
+            continue;
+        }
+        let a = loader.index_of_name(&name_of(key, package));
+        let Some(dependencies) = package["dependencies"].as_object() else {
+            continue;
+        };
+        for dependency in dependencies.keys() {
+            // Node looks for a dependency in the nearest node_modules
+            // directory, walking up towards the root.
+            let mut base = key.as_str();
+            let found = loop {
+                let candidate = match base {
+                    "" => format!("node_modules/{}", dependency),
+                    base => format!("{}/node_modules/{}", base, dependency),
+                };
+                if let Some(found) = resolve(&candidate) {
+                    break Some(found);
+                }
+                if base.is_empty() {
+                    break None;
+                }
+                base = match base.rfind("/node_modules/") {
+                    Some(end) => &base[..end],
+                    None => "",
+                };
+            };
+            // Optional dependencies for other platforms are never installed.
+            if let Some((key, package)) = found {
+                let b = loader.index_of_name(&name_of(&key, package));
+                if seen.insert((a, b)) {
+                    loader.add_edge(a, b);
+                }
+            }
+        }
+    }
+}
+
+/// Loads the nested v1 `dependencies` tree, where each entry names what it
+/// `requires` and those names are resolved against the entry's own nested
+/// `dependencies` first, then those of its ancestors in `scopes`.
+fn load_v1<'a>(
+    loader: &mut Loader,
+    dependencies: &'a Map<String, Value>,
+    scopes: &mut Vec<&'a Map<String, Value>>,
+    seen: &mut HashSet<(usize, usize)>,
+) {
+    scopes.push(dependencies);
+    for (name, package) in dependencies {
+        let a = loader.index_of_name(&package_name(name, package));
+
+        let nested = package["dependencies"].as_object();
+        if let Some(requires) = package["requires"].as_object() {
+            for required in requires.keys() {
+                let found = nested.and_then(|nested| nested.get(required))
+                    .or_else(|| scopes.iter().rev().find_map(|scope| scope.get(required)));
+                if let Some(found) = found {
+                    let b = loader.index_of_name(&package_name(required, found));
+                    if seen.insert((a, b)) {
+                        loader.add_edge(a, b);
+                    }
+                }
+            }
+        }
+        if let Some(nested) = nested {
+            load_v1(loader, nested, scopes, seen);
+        }
+    }
+    scopes.pop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::detect_cycle;
+
+    /// A workspace of two packages depending on each other, with lodash
+    /// installed both at the top and nested under `a`.
+    const LOCKFILE: &str = r#"{
+      "name": "monorepo",
+      "lockfileVersion": 3,
+      "packages": {
+        "": { "name": "monorepo", "version": "1.0.0", "workspaces": ["packages/*"] },
+        "node_modules/a": { "resolved": "packages/a", "link": true },
+        "node_modules/b": { "resolved": "packages/b", "link": true },
+        "node_modules/lodash": { "version": "4.17.21" },
+        "packages/a": { "name": "a", "version": "0.1.0", "dependencies": { "b": "*", "lodash": "^4.17.0" } },
+        "packages/a/node_modules/lodash": { "version": "4.17.21" },
+        "packages/b": { "name": "b", "version": "0.2.0", "dependencies": { "a": "*", "lodash": "^4.0.0" } }
+      }
+    }"#;
+
+    fn load_str(contents: &str) -> Loader {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "package-lock.json", contents).unwrap();
+        loader
+    }
+
+    /// Every item with the names of its dependencies, in input order.
+    fn graph(loader: &Loader) -> Vec<(&str, Vec<&str>)> {
+        loader.items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| loader.items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn workspace_packages_are_followed_through_their_links() {
+        let loader = load_str(LOCKFILE);
+        assert_eq!(graph(&loader), [
+            ("monorepo@1.0.0", vec![]),
+            ("lodash@4.17.21", vec![]),
+            ("a@0.1.0", vec!["b@0.2.0", "lodash@4.17.21"]),
+            ("b@0.2.0", vec!["a@0.1.0", "lodash@4.17.21"]),
+        ]);
+        let cycle = detect_cycle(loader.csr(), &HashSet::new()).unwrap().unwrap();
+        let names: Vec<_> = cycle.iter().map(|&index| loader.items[index].name.as_str()).collect();
+        assert_eq!(names, ["a@0.1.0", "b@0.2.0"]);
+    }
+
+    #[test]
+    fn version_1_lockfiles_resolve_the_nearest_copy() {
+        let loader = load_str(r#"{
+          "lockfileVersion": 1,
+          "dependencies": {
+            "app": { "version": "1.0.0", "requires": { "left-pad": "^2.0.0" },
+                     "dependencies": { "left-pad": { "version": "2.0.0" } } },
+            "left-pad": { "version": "1.3.0" },
+            "tool": { "version": "0.1.0", "requires": { "left-pad": "^1.0.0" } }
+          }
+        }"#);
+        assert_eq!(graph(&loader), [
+            ("app@1.0.0", vec!["left-pad@2.0.0"]), ("left-pad@2.0.0", vec![]),
+            ("left-pad@1.3.0", vec![]), ("tool@0.1.0", vec!["left-pad@1.3.0"]),
+        ]);
+    }
+}
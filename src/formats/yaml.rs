@@ -0,0 +1,100 @@
+use serde_yaml::Value;
+use crate::graph::Loader;
+use crate::error::DepsError;
+
+/// Loads a manifest mapping each node to its dependencies:
+///
+/// ```yaml
+/// api: [auth, db]
+/// auth: db
+/// db:
+/// ```
+///
+/// A missing or null list means no dependencies and a single scalar is a
+/// single dependency.
+pub fn load(loader: &mut Loader, path: &str, contents: &str) -> Result<(), DepsError> {
+    let value: Value = serde_yaml::from_str(contents).map_err(|err| match err.location() {
+        Some(location) => DepsError::at(path, location.line(), location.column(), &err.to_string(), contents),
+        None => DepsError::Invalid { path: path.to_string(), message: err.to_string() },
+    })?;
+    let invalid = |message: String| DepsError::Invalid { path: path.to_string(), message };
+
+    let map = value.as_mapping()
+        .ok_or_else(|| invalid("expected a mapping of names to dependencies".to_string()))?;
+
+    for (key, deps) in map {
+        let name = key.as_str()
+            .ok_or_else(|| invalid(format!("key {} is not a string", describe(key))))?;
+        let a = loader.index_of_name(name);
+        let deps = match deps {
+            Value::Null => Vec::new(),
+            Value::Sequence(deps) => deps.iter().collect(),
+            scalar => vec![scalar],
+        };
+        for dep in deps {
+            let dep = scalar_to_string(dep)
+                .ok_or_else(|| invalid(format!("dependency {} of \"{}\" is not a name", describe(dep), name)))?;
+            let b = loader.index_of_name(&dep);
+            loader.add_edge(a, b);
+        }
+    }
+
+    Ok(())
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Renders a YAML value on one line for error messages.
+fn describe(value: &Value) -> String {
+    serde_yaml::to_string(value)
+        .map(|s| s.trim_end().replace('\n', " "))
+        .unwrap_or_else(|_| format!("{:?}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+    use crate::analysis::detect_cycle;
+
+    fn load_str(contents: &str) -> Result<Loader, DepsError> {
+        let mut loader = Loader::new(false);
+        load(&mut loader, "deps.yaml", contents)?;
+        Ok(loader)
+    }
+
+    /// Every item with the names of its dependencies, in input order.
+    fn graph(loader: &Loader) -> Vec<(&str, Vec<&str>)> {
+        loader.items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| loader.items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn forward_references_and_missing_lists_are_read() {
+        let loader = load_str("api: [auth, db]\nauth: db\ndb:\nworker: [queue, 3]\n").unwrap();
+        assert_eq!(graph(&loader), [
+            ("api", vec!["auth", "db"]), ("auth", vec!["db"]), ("db", vec![]),
+            ("worker", vec!["queue", "3"]), ("queue", vec![]), ("3", vec![]),
+        ]);
+    }
+
+    #[test]
+    fn cycles_are_found() {
+        let loader = load_str("a: b\nb: [c]\nc: [a]\n").unwrap();
+        assert_eq!(detect_cycle(loader.csr(), &HashSet::new()).unwrap(), Some(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn keys_must_be_names() {
+        let error = load_str("api: [db]\n42: db\n").err().unwrap();
+        assert_eq!(error.to_string(), "Could not load deps.yaml: key 42 is not a string");
+    }
+}
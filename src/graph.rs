@@ -0,0 +1,1200 @@
+//! The graph every analysis works on: items holding their data and the
+//! indices of their dependencies, the `GraphBuilder` that adds them node by
+//! node and edge by edge, merging nodes by name, and the `Loader` that
+//! builds them with it from DOT and the other formats.
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use clap::ValueEnum;
+use derive_more::{Index, Deref, Constructor};
+use dot_structures::{Id, Graph, Stmt, Edge, EdgeTy, Vertex, Node as DotNode, NodeId, Attribute, Subgraph};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+use crate::csr::Csr;
+use crate::error::DepsError;
+use crate::report::{self, Progress};
+
+/// An item of a graph: its data, and the indices of the items it depends
+/// on among the items it was loaded with.
+#[derive(Debug, Clone, Index, Deref, Constructor)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Item<T> {
+    #[deref]
+    pub data: T,
+    #[index]
+    pub deps: Vec<usize>,
+}
+
+/// Checks that every dependency of `items` is the index of one of them,
+/// which the analyses rely on.
+pub fn check_deps<T>(items: &[Item<T>]) -> Result<(), DepsError> {
+    for (index, item) in items.iter().enumerate() {
+        if let Some(&dep) = item.deps.iter().find(|&&dep| dep >= items.len()) {
+            return Err(DepsError::InvalidDependency { item: index, dep, len: items.len() });
+        }
+    }
+    Ok(())
+}
+
+/// A whole graph as it is serialized, `{"nodes": [...], "edges": [[0, 1]]}`,
+/// each edge holding the indices of the node that depends on the other.
+///
+/// ```
+/// use depsys::graph::{GraphBuilder, IndexedGraph};
+///
+/// let mut graph = GraphBuilder::new(false);
+/// graph.add_edge_by_name("app", "lib")?;
+/// graph.add_edge_by_name("lib", "log")?;
+/// let json = serde_json::to_string(&IndexedGraph::from_items(&graph.build())).unwrap();
+/// assert_eq!(json, r#"{"nodes":["app","lib","log"],"edges":[[0,1],[1,2]]}"#);
+/// # Ok::<(), depsys::error::DepsError>(())
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedGraph<T> {
+    pub nodes: Vec<T>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Clone> IndexedGraph<T> {
+    /// The graph of `items`, with their edges in order.
+    pub fn from_items(items: &[Item<T>]) -> Self {
+        let nodes = items.iter().map(|item| item.data.clone()).collect();
+        let edges = items.iter().enumerate()
+            .flat_map(|(index, item)| item.deps.iter().map(move |&dep| (index, dep)))
+            .collect();
+        IndexedGraph { nodes, edges }
+    }
+
+    /// The items of the graph, failing for an edge to a node that isn't
+    /// there.
+    pub fn into_items(self) -> Result<Vec<Item<T>>, DepsError> {
+        let mut items: Vec<_> = self.nodes.into_iter().map(|node| Item::new(node, Vec::new())).collect();
+        for (a, b) in self.edges {
+            if a >= items.len() || b >= items.len() {
+                return Err(DepsError::InvalidDependency { item: a, dep: b, len: items.len() });
+            }
+            items[a].deps.push(b);
+        }
+        Ok(items)
+    }
+}
+
+/// A graph written with names instead of indices, `{"nodes": ["a", "b"],
+/// "edges": [["a", "b"]]}`, the shape `--format json` reads, for handing a
+/// graph to other tools. Nodes only mentioned by an edge may be left out
+/// of `nodes`.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<(String, String)>,
+}
+
+#[cfg(feature = "serde")]
+impl NamedGraph {
+    /// The graph of `items`, by the names of their nodes.
+    pub fn from_items(items: &[Item<Node>]) -> Self {
+        let nodes = items.iter().map(|item| item.name.clone()).collect();
+        let edges = items.iter()
+            .flat_map(|item| item.deps.iter().map(|&dep| (item.name.clone(), items[dep].name.clone())))
+            .collect();
+        NamedGraph { nodes, edges }
+    }
+
+    /// The items of the graph, each holding the name of its node.
+    pub fn build(self) -> Vec<Item<String>> {
+        let mut graph = GraphBuilder::new(false);
+        for node in &self.nodes {
+            graph.add_node(node);
+        }
+        for (a, b) in &self.edges {
+            graph.add_edge_by_name(a, b).expect("self-loops are allowed");
+        }
+        graph.build()
+    }
+}
+
+/// The attributes of a node or an edge, in the order they were first
+/// given. Setting one that is already there replaces its value in place.
+/// They stay with their node or edge when the graph is filtered, and are
+/// written back out with it.
+///
+/// ```
+/// use depsys::graph::Attrs;
+///
+/// let mut attrs = Attrs::new();
+/// attrs.set("shape".to_string(), "box".to_string());
+/// attrs.set("color".to_string(), "red".to_string());
+/// attrs.set("shape".to_string(), "oval".to_string());
+/// let keys: Vec<_> = attrs.iter().map(|(key, _)| key.as_str()).collect();
+/// assert_eq!((keys, attrs.get("shape")), (vec!["shape", "color"], Some("oval")));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Attrs(Vec<(String, String)>);
+
+impl Attrs {
+    /// No attributes.
+    pub fn new() -> Self {
+        Attrs(Vec::new())
+    }
+
+    /// The value of the attribute `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    /// Sets the attribute `key` to `value`.
+    pub fn set(&mut self, key: String, value: String) {
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old)) => *old = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    /// Removes the attribute `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let position = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.remove(position).1)
+    }
+
+    /// Whether the attribute `key` is set to `value`.
+    pub fn contains(&self, key: &str, value: &str) -> bool {
+        self.get(key) == Some(value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The attributes as `(key, value)` pairs, in order.
+    pub fn iter(&self) -> std::slice::Iter<'_, (String, String)> {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<(String, String)> for Attrs {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(pairs: I) -> Self {
+        let mut attrs = Attrs::new();
+        for (key, value) in pairs {
+            attrs.set(key, value);
+        }
+        attrs
+    }
+}
+
+impl<'a> IntoIterator for &'a Attrs {
+    type Item = &'a (String, String);
+    type IntoIter = std::slice::Iter<'a, (String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// A node of the graph. Nodes are told apart by `name`, the `label` is only
+/// used when showing them. They are ordered by `name` first, which is
+/// enough since names are unique.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Node {
+    /// The name the node was written with, which tells it apart.
+    pub name: String,
+    /// What the node is shown as instead of its name.
+    pub label: Option<String>,
+    /// Show the name alongside the label, as `label (name)`.
+    pub show_name: bool,
+    /// The other attributes the node was declared with, in order.
+    pub attributes: Attrs,
+    /// The attributes of the edges from the node that were given any, by
+    /// the name of the node each goes to, so they stay with the edge when
+    /// the items are filtered or rebuilt.
+    pub edges: BTreeMap<String, Attrs>,
+    /// The DOT cluster the node was first declared in, without the
+    /// `cluster_` prefix.
+    pub cluster: Option<String>,
+}
+
+impl Node {
+    /// A node that is only known by its name.
+    pub fn new(name: String) -> Self {
+        Node { name, label: None, show_name: false, attributes: Attrs::new(), edges: BTreeMap::new(), cluster: None }
+    }
+
+    /// The value of one of the other attributes.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key)
+    }
+
+    /// The attributes of the edge from the node to the node named `to`,
+    /// which are none when it wasn't given any.
+    pub fn edge(&self, to: &str) -> Option<&Attrs> {
+        self.edges.get(to)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.label {
+            Some(label) if self.show_name => write!(f, "{} ({})", label, self.name),
+            Some(label) => f.write_str(label),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// The text of an id, borrowed from the parsed graph unless it has escapes
+/// to undo.
+fn id_text(id: &Id) -> Cow<'_, str> {
+    match id {
+        Id::Html(s) | Id::Plain(s) | Id::Anonymous(s) => Cow::Borrowed(s),
+        Id::Escaped(s) => unescape(s),
+    }
+}
+
+/// Turns a quoted DOT string such as `"say \"hi\""` into the text it
+/// stands for, `say "hi"`, joining lines split with a trailing backslash.
+fn unescape(quoted: &str) -> Cow<'_, str> {
+    let inner = quoted.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(quoted);
+    if !inner.contains('\\') {
+        return Cow::Borrowed(inner);
+    }
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('"') | Some('\\') => text.push(chars.next().unwrap()),
+            Some('\n') => {
+                chars.next();
+            },
+            Some('\r') => {
+                chars.next();
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+            },
+            _ => text.push(c),
+        }
+    }
+    Cow::Owned(text)
+}
+
+/// Returns the text shown by a label attribute, with the quotes of an
+/// escaped string and the markup of an HTML-like label removed.
+fn label_text(id: &Id) -> String {
+    match id {
+        Id::Escaped(s) => unescape(s).into_owned(),
+        Id::Html(s) => {
+            let mut text = String::new();
+            let mut in_tag = false;
+            for c in s.strip_prefix('<').and_then(|s| s.strip_suffix('>')).unwrap_or(s).chars() {
+                match c {
+                    '<' => in_tag = true,
+                    '>' => in_tag = false,
+                    c if !in_tag => text.push(c),
+                    _ => {},
+                }
+            }
+            text.replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&nbsp;", " ")
+                .replace("&amp;", "&")
+        },
+        Id::Plain(s) | Id::Anonymous(s) => s.clone(),
+    }
+}
+
+/// How the edges of an undirected `graph { a -- b }` are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Undirected {
+    /// Read `a -- b` as `a -> b`
+    Forward,
+    /// Read `a -- b` as both `a -> b` and `b -> a`
+    Both,
+    /// Refuse to load undirected graphs
+    Error,
+}
+
+/// The index of a node added to a `GraphBuilder`, which it keeps in the
+/// items it builds.
+pub type NodeIdx = usize;
+
+/// Builds a graph without going through any input format. Nodes are
+/// merged by name and an edge added twice is only kept once, in the order
+/// the edges were first added.
+///
+/// ```
+/// use std::collections::HashSet;
+/// use depsys::analysis::{detect_cycle, sort_items};
+/// use depsys::csr::Csr;
+/// use depsys::graph::GraphBuilder;
+///
+/// let mut graph = GraphBuilder::new(true);
+/// graph.add_edge_by_name("app", "lib")?;
+/// graph.add_edge_by_name("lib", "core")?;
+/// assert!(graph.add_edge_by_name("core", "core").is_err());
+/// let items = graph.build();
+/// assert!(detect_cycle(&Csr::forward(&items), &HashSet::new())?.is_none());
+///
+/// let (sorted, _levels) = sort_items(&items, false, None)?;
+/// let names: Vec<_> = sorted.iter().map(|&index| items[index].data.as_str()).collect();
+/// assert_eq!(names, ["core", "lib", "app"]);
+///
+/// let mut graph = GraphBuilder::new(false);
+/// graph.add_edge_by_name("a", "b")?;
+/// graph.add_edge_by_name("b", "a")?;
+/// assert_eq!(detect_cycle(&Csr::forward(&graph.build()), &HashSet::new())?, Some(vec![0, 1]));
+/// # Ok::<(), depsys::error::DepsError>(())
+/// ```
+pub struct GraphBuilder {
+    /// The items added so far, in the order their names were first seen.
+    pub items: Vec<Item<Node>>,
+    /// The index of every item by name.
+    pub indices: HashMap<String, NodeIdx>,
+    /// The edges added so far, so that an edge added again is not repeated
+    /// in `deps`.
+    edges: HashSet<(NodeIdx, NodeIdx)>,
+    /// Whether `add_edge` refuses an edge from a node to itself.
+    reject_self_loops: bool,
+}
+
+impl GraphBuilder {
+    /// A builder holding no nodes yet, which refuses edges from a node to
+    /// itself with `reject_self_loops`.
+    pub fn new(reject_self_loops: bool) -> Self {
+        GraphBuilder { items: Vec::new(), indices: HashMap::new(), edges: HashSet::new(), reject_self_loops }
+    }
+
+    /// Returns the index of the node named `name`, adding it if this is the
+    /// first time the name has been seen.
+    pub fn add_node(&mut self, name: &str) -> NodeIdx {
+        if let Some(&index) = self.indices.get(name) {
+            return index;
+        }
+        self.items.push(Item::new(Node::new(name.to_string()), Vec::new()));
+        self.indices.insert(name.to_string(), self.items.len() - 1);
+        self.items.len() - 1
+    }
+
+    /// Makes node `from` depend on node `to`. Returns whether the edge is
+    /// new, and fails for an edge from a node to itself when those are
+    /// refused, or for a node that was never added.
+    pub fn add_edge(&mut self, from: NodeIdx, to: NodeIdx) -> Result<bool, DepsError> {
+        if let Some(dep) = [from, to].into_iter().find(|&index| index >= self.items.len()) {
+            return Err(DepsError::InvalidDependency { item: from, dep, len: self.items.len() });
+        }
+        if from == to && self.reject_self_loops {
+            return Err(DepsError::SelfLoop { node: self.items[from].name.clone() });
+        }
+        Ok(self.insert_edge(from, to))
+    }
+
+    /// Makes the node named `from` depend on the one named `to`, adding
+    /// either of them that doesn't exist yet.
+    pub fn add_edge_by_name(&mut self, from: &str, to: &str) -> Result<bool, DepsError> {
+        let from = self.add_node(from);
+        let to = self.add_node(to);
+        self.add_edge(from, to)
+    }
+
+    /// Adds the edge unless it is already there, keeping the first
+    /// occurrence so the order of `deps` follows the order edges were added.
+    fn insert_edge(&mut self, from: NodeIdx, to: NodeIdx) -> bool {
+        let added = self.edges.insert((from, to));
+        if added {
+            self.items[from].deps.push(to);
+        }
+        added
+    }
+
+    /// Gives the edge from node `from` to node `to` the attributes `attrs`,
+    /// unless it was given some already.
+    pub fn add_edge_attrs(&mut self, from: NodeIdx, to: NodeIdx, attrs: &Attrs) {
+        let to = &self.items[to].name;
+        if !self.items[from].data.edges.contains_key(to) {
+            let to = to.clone();
+            self.items[from].data.edges.insert(to, attrs.clone());
+        }
+    }
+
+    /// Whether node `from` depends on node `to`.
+    pub fn has_edge(&self, from: NodeIdx, to: NodeIdx) -> bool {
+        self.edges.contains(&(from, to))
+    }
+
+    /// Gives each node of `renames` its new name in turn, unless another
+    /// node has that name by then, keeping the attributes of the edges to
+    /// it. Returns the renames that were refused.
+    pub fn rename(&mut self, renames: impl IntoIterator<Item = (NodeIdx, String)>) -> Vec<(NodeIdx, String)> {
+        let mut refused = Vec::new();
+        let mut renamed = HashMap::new();
+        for (index, name) in renames {
+            if self.indices.contains_key(&name) {
+                refused.push((index, name));
+                continue;
+            }
+            let old = std::mem::replace(&mut self.items[index].data.name, name.clone());
+            self.indices.remove(&old);
+            self.indices.insert(name.clone(), index);
+            renamed.insert(old, name);
+        }
+        if !renamed.is_empty() {
+            for item in &mut self.items {
+                let edges = std::mem::take(&mut item.data.edges);
+                item.data.edges = edges.into_iter()
+                    .map(|(to, attrs)| (renamed.get(&to).cloned().unwrap_or(to), attrs))
+                    .collect();
+            }
+        }
+        refused
+    }
+
+    /// Drops the nodes `keep` is not set for, along with their edges. With
+    /// `rewire`, an edge to a dropped node is replaced by edges to whatever
+    /// that node depends on, so the order among the rest is kept. Returns
+    /// the new index of every node that was kept.
+    pub fn retain(&mut self, keep: &[bool], rewire: bool) -> Vec<Option<NodeIdx>> {
+        let mut new_index = vec![None; self.items.len()];
+        let mut next = 0;
+        for (index, &kept) in keep.iter().enumerate() {
+            if kept {
+                new_index[index] = Some(next);
+                next += 1;
+            }
+        }
+
+        let mut items = Vec::new();
+        for (index, item) in self.items.iter().enumerate() {
+            if !keep[index] {
+                continue;
+            }
+            let mut deps = Vec::new();
+            let mut seen = HashSet::new();
+            let mut stack: Vec<_> = item.deps.iter().rev().copied().collect();
+            while let Some(dep) = stack.pop() {
+                if !seen.insert(dep) {
+                    continue;
+                }
+                match new_index[dep] {
+                    Some(dep) => deps.push(dep),
+                    None if rewire => stack.extend(self.items[dep].deps.iter().rev()),
+                    None => {},
+                }
+            }
+            // Edges to dropped nodes take their attributes with them.
+            let mut data = item.data.clone();
+            data.edges.retain(|to, _| self.indices.get(to).is_some_and(|&to| keep[to]));
+            items.push(Item::new(data, deps));
+        }
+
+        self.edges = self.edges.iter().filter_map(|&(a, b)| Some((new_index[a]?, new_index[b]?))).collect();
+        for (index, item) in items.iter().enumerate() {
+            self.edges.extend(item.deps.iter().map(|&dep| (index, dep)));
+        }
+        self.indices = items.iter().enumerate().map(|(index, item)| (item.name.clone(), index)).collect();
+        self.items = items;
+        new_index
+    }
+
+    /// The items of the graph, each holding the name of its node.
+    pub fn build(self) -> Vec<Item<String>> {
+        self.items.into_iter().map(|item| Item::new(item.data.name, item.deps)).collect()
+    }
+}
+
+/// Collects items from one or more graphs with a `GraphBuilder`, merging
+/// nodes by name. It dereferences to the builder, whose `items` are the
+/// ones loaded so far.
+pub struct Loader {
+    /// The nodes and edges loaded so far. Edges from a node to itself are
+    /// kept, some commands report them. Changing them here rather than
+    /// through the loader leaves `csr` behind.
+    pub graph: GraphBuilder,
+    /// The edges packed for the analyses, once asked for, until the graph
+    /// changes.
+    forward: OnceCell<Csr>,
+    /// Edges of the graph currently being loaded, which are reported in
+    /// `duplicates` when repeated. An edge repeated in a later graph is
+    /// quietly not added twice.
+    edges: HashSet<(usize, usize)>,
+    /// Edges repeated within a single graph, in the order they were found.
+    pub duplicates: Vec<(usize, usize)>,
+    /// Whether edges of the current graph are added in both directions.
+    both_ways: bool,
+    /// Whether any graph so far had its edges added in both directions.
+    pub any_both_ways: bool,
+    /// The edges that were only read from undirected graphs, each of which
+    /// has its twin going the other way. An edge a directed graph has too
+    /// is left out, so the cycles of the directed graphs are still found.
+    pub undirected: HashSet<(usize, usize)>,
+    /// The cluster whose statements are being added.
+    cluster: Option<String>,
+    /// The items already warned about for being in several clusters.
+    warned_clusters: HashSet<usize>,
+    /// Edges dropped by `ignore_edges`, in the order they were found.
+    pub ignored: Vec<(usize, usize)>,
+    /// Whether to warn about the parts of the input that are ignored.
+    pub verbose: bool,
+}
+
+impl Deref for Loader {
+    type Target = GraphBuilder;
+
+    fn deref(&self) -> &GraphBuilder {
+        &self.graph
+    }
+}
+
+impl DerefMut for Loader {
+    /// Changing the builder may change the edges, so they are packed again
+    /// when next asked for.
+    fn deref_mut(&mut self) -> &mut GraphBuilder {
+        self.forward.take();
+        &mut self.graph
+    }
+}
+
+impl Loader {
+    /// A loader holding no items yet.
+    pub fn new(verbose: bool) -> Self {
+        Loader {
+            graph: GraphBuilder::new(false),
+            forward: OnceCell::new(),
+            edges: HashSet::new(),
+            duplicates: Vec::new(),
+            both_ways: false,
+            any_both_ways: false,
+            undirected: HashSet::new(),
+            cluster: None,
+            warned_clusters: HashSet::new(),
+            ignored: Vec::new(),
+            verbose,
+        }
+    }
+
+    /// Returns the index of the item named by `id`, creating it if this is
+    /// the first time the name has been seen. The item joins the cluster
+    /// being added unless it is in one already.
+    fn index_of(&mut self, id: &Id) -> usize {
+        self.forward.take();
+        let index = self.graph.add_node(&id_text(id));
+        let node = &mut self.graph.items[index].data;
+        if node.cluster.is_none() {
+            node.cluster = self.cluster.clone();
+        }
+        index
+    }
+
+    /// Returns the index of the item named `name`, creating it if this is
+    /// the first time the name has been seen.
+    pub fn index_of_name(&mut self, name: &str) -> usize {
+        self.forward.take();
+        self.graph.add_node(name)
+    }
+
+    /// Drops the items `keep` is not set for, along with their edges. With
+    /// `rewire`, an edge to a dropped item is replaced by edges to whatever
+    /// that item depends on, so the order among the rest is kept.
+    pub fn retain(&mut self, keep: &[bool], rewire: bool) {
+        self.forward.take();
+        let new_index = self.graph.retain(keep, rewire);
+        let remap = |&(a, b): &(usize, usize)| Some((new_index[a]?, new_index[b]?));
+        self.duplicates = self.duplicates.iter().filter_map(remap).collect();
+        self.edges = self.edges.iter().filter_map(remap).collect();
+        self.undirected = self.undirected.iter().filter_map(remap).collect();
+        self.ignored = self.ignored.iter().filter_map(remap).collect();
+    }
+
+    /// The edges of the items loaded so far, packed the first time they are
+    /// asked for and kept for every analysis after until the graph changes.
+    pub fn csr(&self) -> &Csr {
+        self.forward.get_or_init(|| Csr::forward(&self.graph.items))
+    }
+
+    /// Looks up a node given on the command line, by name or else by label.
+    /// Fails with the names that are close to it when there is none.
+    pub fn find(&self, name: &str) -> Result<usize, DepsError> {
+        if let Some(&index) = self.graph.indices.get(name) {
+            return Ok(index);
+        }
+        let mut labelled = self.graph.items.iter().enumerate().filter(|(_, item)| item.label.as_deref() == Some(name));
+        if let (Some((index, _)), None) = (labelled.next(), labelled.next()) {
+            return Ok(index);
+        }
+
+        let lowercase = name.to_lowercase();
+        let mut close: Vec<_> = self.graph.items.iter()
+            .map(|item| {
+                let candidate = item.name.to_lowercase();
+                let distance = if candidate.contains(&lowercase) || lowercase.contains(&candidate) {
+                    0
+                } else {
+                    edit_distance(&lowercase, &candidate)
+                };
+                (distance, item.to_string())
+            })
+            .filter(|&(distance, _)| distance <= name.chars().count() / 3 + 1)
+            .collect();
+        close.sort();
+        let suggestions = close.into_iter().take(5).map(|(_, name)| name).collect();
+        Err(DepsError::UnknownNode { name: name.to_string(), suggestions })
+    }
+
+    /// Sets how the edges of the current input are added, given that it is
+    /// undirected.
+    pub fn set_undirected(&mut self, undirected: Undirected) -> Result<(), DepsError> {
+        match undirected {
+            Undirected::Forward => {},
+            Undirected::Both => {
+                self.both_ways = true;
+                self.any_both_ways = true;
+            },
+            Undirected::Error => return Err(DepsError::UndirectedGraph),
+        }
+        Ok(())
+    }
+
+    /// How many slots the tables from names and edges to indices have
+    /// allocated.
+    pub fn index_entries(&self) -> usize {
+        self.graph.indices.capacity() + self.graph.edges.capacity() + self.edges.capacity()
+    }
+
+    /// Marks the end of one input, after which its edges count as merged.
+    pub fn end_graph(&mut self) {
+        self.edges.clear();
+        self.both_ways = false;
+    }
+
+    /// Makes item `a` depend on item `b`, and `b` on `a` as well while
+    /// undirected edges are read both ways.
+    pub fn add_edge(&mut self, a: usize, b: usize) {
+        self.forward.take();
+        self.add_edge_from(a, b, self.both_ways);
+        if self.both_ways {
+            self.add_edge_from(b, a, true);
+        }
+    }
+
+    /// Adds the edge from `a` to `b`, keeping track of whether only
+    /// undirected graphs have had it.
+    fn add_edge_from(&mut self, a: usize, b: usize, undirected: bool) {
+        let added = self.push_edge(a, b);
+        if !undirected {
+            self.undirected.remove(&(a, b));
+        } else if added {
+            self.undirected.insert((a, b));
+        }
+    }
+
+    /// Gives the edge from item `a` to item `b` the attributes it was
+    /// written with, unless it has some from an earlier time it was seen,
+    /// and its twin the same while undirected edges are read both ways.
+    pub fn add_edge_attrs(&mut self, a: usize, b: usize, attrs: &Attrs) {
+        if attrs.is_empty() {
+            return;
+        }
+        self.graph.add_edge_attrs(a, b, attrs);
+        if self.both_ways {
+            self.graph.add_edge_attrs(b, a, attrs);
+        }
+    }
+
+    /// Adds the edge unless it is already there, remembering it in
+    /// `duplicates` when the current input repeats it. Returns whether it
+    /// was added.
+    fn push_edge(&mut self, a: usize, b: usize) -> bool {
+        if self.graph.insert_edge(a, b) {
+            self.edges.insert((a, b));
+            true
+        } else {
+            if self.edges.contains(&(a, b)) {
+                self.duplicates.push((a, b));
+            }
+            false
+        }
+    }
+
+    /// Drops the edges whose attributes `matches`, remembering them in
+    /// `ignored`, so soft dependencies don't count as cycles.
+    pub fn ignore_edges(&mut self, matches: impl Fn(&Attrs) -> bool) {
+        self.forward.take();
+        let none = Attrs::new();
+        for a in 0..self.graph.items.len() {
+            let deps = std::mem::take(&mut self.graph.items[a].deps);
+            for b in deps {
+                let attributes = self.graph.items[a].edge(&self.graph.items[b].name).unwrap_or(&none);
+                if matches(attributes) {
+                    self.ignored.push((a, b));
+                } else {
+                    self.graph.items[a].deps.push(b);
+                }
+            }
+        }
+    }
+
+    /// Adds every node and edge in `stmts`, descending into subgraphs as if
+    /// their statements were written inline. Returns the indices of all
+    /// nodes mentioned by the statements, in first-seen order.
+    fn add_stmts(&mut self, stmts: &[Stmt], progress: &mut Progress) -> Vec<usize> {
+        let mut mentioned = Vec::new();
+        // The nodes at either end of the edge being added, kept from one
+        // edge to the next so adding an edge between known nodes allocates
+        // nothing.
+        let (mut prev, mut ends) = (Vec::new(), Vec::new());
+        for stmt in stmts {
+            match stmt {
+                Stmt::Edge(Edge { ty, attributes }) => {
+                    progress.add(1);
+                    let attributes: Attrs = attributes.iter()
+                        .map(|Attribute(key, value)| (id_text(key).into_owned(), id_text(value).into_owned()))
+                        .collect();
+                    let (first, rest) = match ty {
+                        EdgeTy::Pair(a, b) => (a, std::slice::from_ref(b)),
+                        EdgeTy::Chain(vertices) => match vertices.split_first() {
+                            Some(split) => split,
+                            None => continue,
+                        },
+                    };
+                    prev.clear();
+                    self.add_vertex(first, &mut prev, progress);
+                    mentioned.extend_from_slice(&prev);
+                    for vertex in rest {
+                        ends.clear();
+                        self.add_vertex(vertex, &mut ends, progress);
+                        // An edge to or from a subgraph connects every node
+                        // inside it.
+                        for &a in &prev {
+                            for &b in &ends {
+                                self.add_edge(a, b);
+                                self.add_edge_attrs(a, b, &attributes);
+                            }
+                        }
+                        mentioned.extend_from_slice(&ends);
+                        std::mem::swap(&mut prev, &mut ends);
+                    }
+                },
+                // Declaring a node that already appeared in an edge is a no-op.
+                Stmt::Node(DotNode { id: NodeId(id, _), attributes }) => {
+                    let index = self.index_of(id);
+                    let node = &mut self.graph.items[index].data;
+                    // Edges may point into other clusters, but a node is only
+                    // declared in one.
+                    if let (Some(first), Some(cluster)) = (&node.cluster, &self.cluster) {
+                        if first != cluster && self.warned_clusters.insert(index) {
+                            report::warning(format_args!(
+                                "Node {} is declared in the clusters {} and {}, keeping {}",
+                                node.name, first, cluster, first,
+                            ));
+                        }
+                    }
+                    for Attribute(key, value) in attributes {
+                        let key = id_text(key).into_owned();
+                        if key == "label" {
+                            node.label = Some(label_text(value));
+                            continue;
+                        }
+                        // A later declaration overrides the same attribute.
+                        node.attributes.set(key, id_text(value).into_owned());
+                    }
+                    mentioned.push(index);
+                },
+                Stmt::Subgraph(subgraph) => {
+                    mentioned.extend(self.add_subgraph(subgraph, progress));
+                },
+                // Attributes only affect how the graph is drawn, they carry
+                // no dependency information.
+                Stmt::Attribute(_) | Stmt::GAttribute(_) => {
+                    if self.verbose {
+                        report::warning(format_args!("Ignoring attribute statement: {:?}", stmt));
+                    }
+                },
+            }
+        }
+
+        let mut seen = HashSet::new();
+        mentioned.retain(|index| seen.insert(*index));
+        mentioned
+    }
+
+    /// Adds the node or subgraph at one end of an edge, pushing the nodes
+    /// it stands for onto `ends`.
+    fn add_vertex(&mut self, vertex: &Vertex, ends: &mut Vec<usize>, progress: &mut Progress) {
+        match vertex {
+            Vertex::N(NodeId(id, _)) => ends.push(self.index_of(id)),
+            Vertex::S(subgraph) => ends.extend(self.add_subgraph(subgraph, progress)),
+        }
+    }
+
+    /// Adds the statements of a subgraph, putting the nodes of a
+    /// `subgraph cluster_name` in the cluster `name`. Nodes of nested
+    /// clusters go in the innermost one.
+    fn add_subgraph(&mut self, subgraph: &Subgraph, progress: &mut Progress) -> Vec<usize> {
+        let name = id_text(&subgraph.id);
+        let Some(cluster) = name.strip_prefix("cluster") else {
+            return self.add_stmts(&subgraph.stmts, progress);
+        };
+        let cluster = cluster.strip_prefix('_').unwrap_or(cluster).to_string();
+        let outer = self.cluster.replace(cluster);
+        let mentioned = self.add_stmts(&subgraph.stmts, progress);
+        self.cluster = outer;
+        mentioned
+    }
+
+    /// Adds the items of `input`, one graph loaded on its own, as loading it
+    /// here would have: nodes are merged by name, keeping the first cluster
+    /// and edge attributes while later labels and attributes win, and the
+    /// edges it repeats count as duplicates unless an earlier graph had them.
+    pub fn merge(&mut self, input: Loader) {
+        self.forward.take();
+        if self.graph.items.is_empty() {
+            let (verbose, any_both_ways) = (self.verbose, self.any_both_ways);
+            *self = input;
+            self.verbose = verbose;
+            self.any_both_ways |= any_both_ways;
+            return;
+        }
+        let index: Vec<_> = input.graph.items.iter().map(|item| self.graph.add_node(&item.name)).collect();
+        let mut edge_attrs = Vec::new();
+        for (index_in_input, (item, &at)) in input.graph.items.into_iter().zip(&index).enumerate() {
+            let Node { label, show_name, attributes, edges, cluster, .. } = item.data;
+            let node = &mut self.graph.items[at].data;
+            match (&node.cluster, cluster) {
+                (None, cluster) => node.cluster = cluster,
+                (Some(first), Some(cluster)) if *first != cluster && self.warned_clusters.insert(at) => {
+                    report::warning(format_args!(
+                        "Node {} is declared in the clusters {} and {}, keeping {}",
+                        node.name, first, cluster, first,
+                    ));
+                },
+                _ => {},
+            }
+            if label.is_some() {
+                node.label = label;
+            }
+            node.show_name |= show_name;
+            for (key, value) in &attributes {
+                node.attributes.set(key.clone(), value.clone());
+            }
+            for &dep in &item.deps {
+                self.add_edge_from(at, index[dep], input.undirected.contains(&(index_in_input, dep)));
+            }
+            edge_attrs.extend(edges.into_iter().map(|(to, attrs)| (at, to, attrs)));
+        }
+        for (from, to, attrs) in edge_attrs {
+            let to = self.graph.indices[&to];
+            self.graph.add_edge_attrs(from, to, &attrs);
+        }
+        for (a, b) in input.duplicates {
+            let edge = (index[a], index[b]);
+            if self.edges.contains(&edge) {
+                self.duplicates.push(edge);
+            }
+        }
+        self.any_both_ways |= input.any_both_ways;
+    }
+
+    /// Prefixes the name of every node in a cluster with the cluster, as
+    /// `cluster.name`, leaving nodes whose new name is taken as they were.
+    pub fn qualify_clusters(&mut self) {
+        let renames: Vec<_> = self.graph.items.iter().enumerate()
+            .filter_map(|(index, item)| Some((index, format!("{}.{}", item.cluster.as_ref()?, item.name))))
+            .collect();
+        for (index, qualified) in self.graph.rename(renames) {
+            report::warning(format_args!("Not renaming {} to {}, which is taken", self.graph.items[index].name, qualified));
+        }
+    }
+}
+
+/// Adds the nodes and edges of `graph` to the ones `loader` already holds.
+pub fn from_graphviz(loader: &mut Loader, graph: &Graph, undirected: Undirected) -> Result<(), DepsError> {
+    let stmts = match graph {
+        Graph::DiGraph { stmts, .. } => stmts,
+        Graph::Graph { stmts, .. } => {
+            loader.set_undirected(undirected)?;
+            stmts
+        },
+    };
+    let mut progress = Progress::hidden();
+    if log::log_enabled!(log::Level::Debug) || report::showing_progress() {
+        let mut counts = [0; 5];
+        count_stmts(stmts, &mut counts);
+        let [nodes, edges, attributes, graph_attributes, subgraphs] = counts;
+        log::debug!(
+            "The graph has {} node, {} edge, {} attribute, {} graph attribute and {} subgraph statements",
+            nodes, edges, attributes, graph_attributes, subgraphs,
+        );
+        progress = Progress::new("Adding the edges", "edge statements", Some(edges));
+    }
+    loader.add_stmts(stmts, &mut progress);
+
+    Ok(())
+}
+
+/// Counts the node, edge, attribute, graph attribute and subgraph
+/// statements of `stmts` and the subgraphs inside them.
+fn count_stmts(stmts: &[Stmt], counts: &mut [usize; 5]) {
+    for stmt in stmts {
+        let kind = match stmt {
+            Stmt::Node(_) => 0,
+            Stmt::Edge(_) => 1,
+            Stmt::Attribute(_) => 2,
+            Stmt::GAttribute(_) => 3,
+            Stmt::Subgraph(subgraph) => {
+                count_stmts(&subgraph.stmts, counts);
+                4
+            },
+        };
+        counts[kind] += 1;
+    }
+}
+
+/// The number of single character insertions, deletions and substitutions
+/// that turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut row: Vec<_> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::detect_cycle;
+
+    /// Loads the DOT `inputs` one after the other, as the command line does.
+    fn load(inputs: &[&str], undirected: Undirected) -> Loader {
+        let mut loader = Loader::new(false);
+        for input in inputs {
+            from_graphviz(&mut loader, &graphviz_rust::parse(input).unwrap(), undirected).unwrap();
+            loader.end_graph();
+        }
+        loader
+    }
+
+    /// Every item with the names of its dependencies, in input order.
+    fn graph(loader: &Loader) -> Vec<(&str, Vec<&str>)> {
+        loader.items.iter()
+            .map(|item| (item.name.as_str(), item.deps.iter().map(|&dep| loader.items[dep].name.as_str()).collect()))
+            .collect()
+    }
+
+    fn cycle(loader: &Loader) -> Option<Vec<String>> {
+        let cycle = detect_cycle(loader.csr(), &loader.undirected).unwrap()?;
+        Some(cycle.into_iter().map(|index| loader.items[index].name.clone()).collect())
+    }
+
+    #[test]
+    fn edge_chains_are_pairs_of_edges() {
+        let chain = load(&["digraph { a -> b -> c -> a }"], Undirected::Error);
+        assert_eq!(graph(&chain), [("a", vec!["b"]), ("b", vec!["c"]), ("c", vec!["a"])]);
+        assert_eq!(cycle(&chain), Some(vec!["a".to_string(), "b".to_string(), "c".to_string()]));
+
+        let mixed = load(&["digraph { b -> x; a -> b -> c }"], Undirected::Error);
+        assert_eq!(graph(&mixed), [("b", vec!["x", "c"]), ("x", vec![]), ("a", vec!["b"]), ("c", vec![])]);
+    }
+
+    #[test]
+    fn undirected_edges_are_not_cycles() {
+        let loader = load(&["graph { a -- b; b -- c }"], Undirected::Both);
+        assert_eq!(cycle(&loader), None);
+        let loader = load(&["graph { a -- b -- c -- a }"], Undirected::Both);
+        assert_eq!(cycle(&loader), Some(vec!["a".into(), "b".into(), "c".into()]));
+    }
+
+    #[test]
+    fn directed_cycles_are_found_next_to_undirected_inputs() {
+        let digraph = "digraph { a -> b; b -> a }";
+        let graph = "graph { c -- d; a -- b }";
+        for inputs in [[digraph, graph], [graph, digraph]] {
+            let loader = load(&inputs, Undirected::Both);
+            assert_eq!(cycle(&loader), Some(vec!["a".into(), "b".into()]), "{:?}", inputs);
+            let undirected: HashSet<_> = loader.undirected.iter()
+                .map(|&(a, b)| (loader.items[a].name.as_str(), loader.items[b].name.as_str()))
+                .collect();
+            assert_eq!(undirected, HashSet::from([("c", "d"), ("d", "c")]));
+        }
+    }
+
+    #[test]
+    fn merged_inputs_keep_which_edges_were_undirected() {
+        let mut merged = load(&["digraph { x -> a; a -> b; b -> a }"], Undirected::Both);
+        merged.merge(load(&["graph { c -- d; a -- b }"], Undirected::Both));
+        assert_eq!(cycle(&merged), Some(vec!["a".into(), "b".into()]));
+
+        let mut merged = load(&["digraph { x -> a }"], Undirected::Both);
+        merged.merge(load(&["graph { c -- d; a -- b }"], Undirected::Both));
+        assert_eq!(cycle(&merged), None);
+    }
+
+    #[test]
+    fn quoted_names_are_the_names_they_quote() {
+        let loader = load(&["digraph { foo -> \"foo\" }"], Undirected::Error);
+        assert_eq!(graph(&loader), [("foo", vec!["foo"])]);
+        assert_eq!(crate::analysis::find_self_loops(&loader.items), [0]);
+
+        let loader = load(&["digraph { \"my node\" -> \"say \\\"hi\\\"\"; \"my node\" -> \"back\\\\slash\" }"], Undirected::Error);
+        assert_eq!(graph(&loader), [("my node", vec!["say \"hi\"", "back\\slash"]), ("say \"hi\"", vec![]), ("back\\slash", vec![])]);
+        let loader = load(&["digraph { \"split \\\nname\" -> b }"], Undirected::Error);
+        assert_eq!(graph(&loader), [("split name", vec!["b"]), ("b", vec![])]);
+    }
+
+    #[test]
+    fn node_statements_add_isolated_nodes() {
+        let loader = load(&["digraph { foo; bar [label=\"Bar\"]; }"], Undirected::Error);
+        assert_eq!(graph(&loader), [("foo", vec![]), ("bar", vec![])]);
+        assert_eq!(loader.items[1].label.as_deref(), Some("Bar"));
+    }
+
+    #[test]
+    fn node_statements_mix_with_edges() {
+        let loader = load(&["digraph { lone; a; a -> b; c [shape=box]; b -> c }"], Undirected::Error);
+        assert_eq!(graph(&loader), [("lone", vec![]), ("a", vec!["b"]), ("b", vec!["c"]), ("c", vec![])]);
+    }
+
+    #[test]
+    fn nodes_declared_after_their_edges_are_not_added_again() {
+        let loader = load(&["digraph { a -> b; b [label=\"B\"]; a }"], Undirected::Error);
+        assert_eq!(graph(&loader), [("a", vec!["b"]), ("b", vec![])]);
+        assert_eq!(loader.indices.len(), 2);
+        assert_eq!(loader.items[1].label.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn attribute_statements_are_skipped() {
+        let items = |input| {
+            let items = load(&[input], Undirected::Error).graph.build();
+            items.into_iter().map(|item| (item.data, item.deps)).collect::<Vec<_>>()
+        };
+        let stripped = items("digraph { a -> b; b -> c; d }");
+        for input in [
+            "digraph { rankdir=LR; label=\"deps\"; a -> b; b -> c; d }",
+            "digraph { graph [rankdir=LR]; a -> b; b -> c; d }",
+            "digraph { node [shape=box, color=grey]; a -> b; b -> c; d }",
+            "digraph { edge [style=dashed]; a -> b; b -> c; d }",
+            "digraph { rankdir=LR; node [shape=box]; edge [color=red]; a -> b; node [shape=oval]; b -> c; d }",
+        ] {
+            assert_eq!(items(input), stripped, "{}", input);
+        }
+    }
+
+    #[test]
+    fn undirected_graphs_are_read_as_asked() {
+        let input = "graph { a -- b; b -- c }";
+        assert_eq!(graph(&load(&[input], Undirected::Forward)), [("a", vec!["b"]), ("b", vec!["c"]), ("c", vec![])]);
+        let both = load(&[input], Undirected::Both);
+        assert_eq!(graph(&both), [("a", vec!["b"]), ("b", vec!["a", "c"]), ("c", vec!["b"])]);
+        assert_eq!(cycle(&both), None);
+
+        let mut loader = Loader::new(false);
+        let parsed = graphviz_rust::parse(input).unwrap();
+        assert!(matches!(from_graphviz(&mut loader, &parsed, Undirected::Error), Err(DepsError::UndirectedGraph)));
+        // Directed graphs are read the same whatever the setting.
+        let directed = load(&["digraph { a -> b }"], Undirected::Error);
+        assert_eq!(graph(&directed), [("a", vec!["b"]), ("b", vec![])]);
+    }
+
+    #[test]
+    fn repeated_edges_are_kept_once_and_reported() {
+        let repeated = load(&["digraph { a -> b; c -> b; a -> b; b -> d; c -> b; a -> b }"], Undirected::Error);
+        let once = load(&["digraph { a -> b; c -> b; b -> d }"], Undirected::Error);
+        assert_eq!(graph(&repeated), graph(&once));
+        let (sorted, _) = crate::analysis::sort_order(&repeated.items, false);
+        assert_eq!(sorted, crate::analysis::sort_order(&once.items, false).0);
+
+        let names: Vec<_> = repeated.duplicates.iter()
+            .map(|&(a, b)| (repeated.items[a].name.as_str(), repeated.items[b].name.as_str()))
+            .collect();
+        assert_eq!(names, [("a", "b"), ("c", "b"), ("a", "b")]);
+        assert!(once.duplicates.is_empty());
+    }
+
+    #[test]
+    fn edges_repeated_by_a_later_input_are_not_duplicates() {
+        let loader = load(&["digraph { a -> b }", "digraph { a -> b; b -> c }"], Undirected::Error);
+        assert_eq!(graph(&loader), [("a", vec!["b"]), ("b", vec!["c"]), ("c", vec![])]);
+        assert!(loader.duplicates.is_empty());
+    }
+
+    #[test]
+    fn the_packed_edges_follow_the_graph() {
+        let mut loader = load(&["digraph { a -> b }"], Undirected::Error);
+        assert_eq!(loader.csr().edge_count(), 1);
+        let c = loader.index_of_name("c");
+        loader.add_edge(1, c);
+        assert_eq!((loader.csr().len(), loader.csr().targets(1).collect::<Vec<_>>()), (3, vec![2]));
+        loader.retain(&[false, true, true], false);
+        assert_eq!((loader.csr().len(), loader.csr().targets(0).collect::<Vec<_>>()), (2, vec![1]));
+    }
+
+    #[test]
+    fn unknown_nodes_come_with_the_close_names() {
+        let loader = load(&["digraph { api -> core; apps -> core }"], Undirected::Error);
+        assert!(matches!(
+            loader.find("apo"),
+            Err(DepsError::UnknownNode { name, suggestions }) if name == "apo" && suggestions == ["api", "apps"]
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn indexed_graphs_read_back_as_the_items_they_were() {
+        let mut graph = GraphBuilder::new(false);
+        for (from, to) in [("app", "lib"), ("app", "log"), ("lib", "log")] {
+            graph.add_edge_by_name(from, to).unwrap();
+        }
+        let items = graph.build();
+
+        let json = serde_json::to_string(&IndexedGraph::from_items(&items)).unwrap();
+        assert_eq!(json, r#"{"nodes":["app","lib","log"],"edges":[[0,1],[0,2],[1,2]]}"#);
+        let read = serde_json::from_str::<IndexedGraph<String>>(&json).unwrap().into_items().unwrap();
+        let pairs = |items: &[Item<String>]| items.iter().map(|item| (item.data.clone(), item.deps.clone())).collect::<Vec<_>>();
+        assert_eq!(pairs(&read), pairs(&items));
+        let (sorted, _) = crate::analysis::sort_items(&items, false, None).unwrap();
+        assert_eq!(crate::analysis::sort_items(&read, false, None).unwrap().0, sorted);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn indexed_graphs_with_an_edge_past_the_nodes_are_refused() {
+        let graph = IndexedGraph { nodes: vec!["a".to_string()], edges: vec![(0, 1)] };
+        assert!(matches!(graph.into_items(), Err(DepsError::InvalidDependency { item: 0, dep: 1, len: 1 })));
+    }
+
+    #[test]
+    fn attributes_stay_with_what_is_kept_and_are_written_back_out() {
+        let mut loader = load(&["digraph { a [shape=box]; a -> b [style=dashed]; b -> c [weight=2]; c -> d [color=red] }"], Undirected::Error);
+        loader.retain(&[true, true, true, false], false);
+        let text = crate::formats::dot::print(&crate::formats::dot::to_graphviz(&loader.items, |_| Attrs::new(), |_, _| Attrs::new()));
+
+        let again = load(&[&text], Undirected::Error);
+        assert_eq!(again.items.len(), 3);
+        assert_eq!(again.items[0].attribute("shape"), Some("box"));
+        assert_eq!(again.items[0].edge("b").and_then(|attrs| attrs.get("style")), Some("dashed"));
+        assert_eq!(again.items[1].edge("c").and_then(|attrs| attrs.get("weight")), Some("2"));
+        assert!(again.items[2].edge("d").is_none());
+    }
+}
@@ -0,0 +1,25 @@
+//! Finds circular dependencies in graphs read from Graphviz DOT and the
+//! other formats, and sorts, queries and rewrites them. `graph` holds the
+//! items of a graph and loads them, `analysis` has what can be worked out
+//! from them and `traverse` walks over them. `csr` packs the edges of a
+//! graph into two flat vectors for the analyses that follow all of them,
+//! and `parallel` runs some of them on several threads. `run` runs a
+//! command for every item, `cache` keeps a loaded input so it doesn't have
+//! to be parsed again, and `timings` times the stages of a run.
+
+pub mod analysis;
+pub mod cache;
+pub mod csr;
+pub mod error;
+pub mod formats;
+pub mod graph;
+pub mod output;
+pub mod parallel;
+pub mod report;
+#[cfg(feature = "serde")]
+pub mod rules;
+pub mod run;
+#[cfg(test)]
+mod testing;
+pub mod timings;
+pub mod traverse;
@@ -1,187 +1,2825 @@
+mod completions;
+mod config;
+mod queries;
+mod repl;
+mod watch;
+
+use std::env;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, Read, Write};
+use std::num::NonZeroUsize;
 use std::process::ExitCode;
-use std::collections::{HashMap, HashSet, VecDeque};
-use derive_more::{Index, Deref, Constructor};
-use clap::{Parser, Subcommand};
-use dot_structures::{Id, Graph, Stmt, Edge, EdgeTy, Vertex, NodeId};
-
-#[derive(Debug, Clone, Index, Deref, Constructor)]
-struct Item<T: Clone> {
-    #[deref]
-    data: T,
-    #[index]
-    deps: Vec<usize>,
-}
-
-fn id_to_string(id: Id) -> String {
-    match id {
-        Id::Html(s) => s,
-        Id::Escaped(s) => s,
-        Id::Plain(s) => s,
-        Id::Anonymous(s) => s,
-    }
-}
-
-fn from_graphviz(graph: &Graph) -> Vec<Item<String>> {
-    let mut items = Vec::new();
-    let mut indices = HashMap::new();
-    let stmts = match graph {
-        Graph::DiGraph { stmts, .. } => stmts,
-        Graph::Graph { .. } => panic!("Only directed graphs are supported"),
-    };
-    for stmt in stmts {
-        let edge_ty = match stmt {
-            Stmt::Edge(Edge { ty, .. }) => ty,
-            _ => panic!("Statement is not supported: {:?}", stmt),
-        };
-        match edge_ty {
-            EdgeTy::Pair(Vertex::N(NodeId(a, _)), Vertex::N(NodeId(b, _))) => {
-                let a = id_to_string(a.clone());
-                let b = id_to_string(b.clone());
+use std::time::{Duration, Instant};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use depsys::{cache, error, formats, output, parallel, report, rules, timings};
+use depsys::run::{RunStatus, run_jobs, shell_quote, skip_blocked};
+use depsys::analysis::{
+    Bottlenecks, Changed, Closure, Coverage, CriticalPath, Diff, Fan, SameDeps, WhatIf, bottlenecks, collapse, condense,
+    costs, coverage, critical_path, cycle_chain, degrees, detect_cycle, diff, elementary_cycles, ensure_acyclic,
+    fan_limits, feedback_edges, find_path, find_self_loops, impact, implied_edges, induced_subgraph, each_reachable,
+    layer_violations, longest_chains, most_connected, new_cycle, order_violations, orphans, ranked, reachable,
+    redundant_edges, rename, same_deps, simulate_schedule, sort_items, sort_order, strict_violations, weak_components,
+    what_if,
+};
+use depsys::csr::Csr;
+use depsys::graph::{Attrs, Loader, NamedGraph, Node, Undirected, from_graphviz};
+use depsys::traverse::{Charset, Edges, write_tree};
+use error::DepsError;
+use output::Output;
+use report::{ColorChoice, Progress, ProgressChoice, Style};
+use timings::Phase;
+use formats::mermaid::Direction;
 
-                let index_a = *indices.entry(a.clone()).or_insert_with(|| items.len());
-                if index_a == items.len() {
-                    items.push(Item::new(a, Vec::new()));
-                }
+/// How many members `components` shows of each component.
+const COMPONENT_SAMPLE: usize = 5;
 
-                let index_b = *indices.entry(b.clone()).or_insert_with(|| items.len());
-                if index_b == items.len() {
-                    items.push(Item::new(b, Vec::new()));
-                }
+/// How many nodes `fanout` lists for each metric when no limit is given.
+const FANOUT_TOP: usize = 10;
+
+/// How many cycles `merge --fail-on-new-cycles` and `suggest-breaks` look at.
+const CYCLE_LIMIT: usize = 10_000;
 
-                items[index_a].deps.push(index_b);
+/// Reads the whole of `path`, where `-` means standard input. Returns the
+/// name to use for the input in messages alongside its contents.
+fn read_input(path: &str) -> Result<(String, String), DepsError> {
+    let name = if path == "-" { "standard input" } else { path };
+    let contents = if path == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)
+            .map_err(|source| DepsError::Io { path: name.to_string(), source })?;
+        contents
+    } else {
+        read_file(path).map_err(|source| DepsError::Io { path: name.to_string(), source })?
+    };
+    log::debug!("Read {}: {} bytes", name, contents.len());
+    if contents.trim().is_empty() {
+        return Err(DepsError::EmptyInput { path: name.to_string() });
+    }
+
+    Ok((name.to_string(), contents))
+}
+
+/// Reads the file at `path` like `fs::read_to_string`, a piece at a time
+/// to show how far it got.
+fn read_file(path: &str) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata().ok().and_then(|metadata| usize::try_from(metadata.len()).ok());
+    let mut progress = Progress::bytes(format_args!("Reading {}", path), len);
+    let mut bytes = Vec::with_capacity(len.unwrap_or(0));
+    let mut chunk = vec![0; 1 << 20];
+    loop {
+        match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(count) => {
+                bytes.extend_from_slice(&chunk[..count]);
+                progress.add(count);
             },
-            EdgeTy::Chain(_) => todo!(),
-            // If we don't match a chain or a pair of nodes, we are
-            // an edge connected to a subgraph.
-            _ => panic!("Subgraphs are not supported"),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
         }
     }
+    String::from_utf8(bytes).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+}
 
-    items
+/// Parses an edge written `a:b`, splitting at the first colon.
+fn parse_edge(text: &str) -> Result<(String, String), String> {
+    match text.split_once(':') {
+        Some((a, b)) if !a.is_empty() && !b.is_empty() => Ok((a.to_string(), b.to_string())),
+        _ => Err("expected an edge written as A:B".to_string()),
+    }
 }
 
-fn detect_cycle<T: Clone>(items: &[Item<T>]) -> Option<(usize, usize)> {
-    let mut visited = HashSet::new();
-    let mut stack = Vec::new();
+/// The ends of an edge given as `--from A --to B` or as `-- A B`, if it
+/// was given at all.
+fn ends(from: Option<String>, to: Option<String>, pair: Vec<String>) -> Option<(String, String)> {
+    from.zip(to).or_else(|| <[String; 2]>::try_from(pair).ok().map(|[from, to]| (from, to)))
+}
 
-    for start in 0..items.len() {
-        if visited.contains(&start) {
-            continue;
-        }
+/// Parses an ordering constraint written as `before:A:B`.
+fn parse_constraint(text: &str) -> Result<(String, String), String> {
+    let pair = text.strip_prefix("before:").and_then(|pair| pair.split_once(':'));
+    match pair {
+        Some((a, b)) if !a.is_empty() && !b.is_empty() => Ok((a.to_string(), b.to_string())),
+        _ => Err(format!("expected a constraint written as before:A:B, not {}", text)),
+    }
+}
 
-        stack.push((start, 0, None));
-        let mut path = HashSet::new();
+/// Parses an edge attribute given on the command line as `key=value`.
+fn parse_attribute(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err("expected an attribute written as KEY=VALUE".to_string()),
+    }
+}
 
-        while let Some((node, dep_index, parent)) = stack.pop() {
-            if dep_index == 0 {
-                if path.contains(&node) {
-                    return parent.map(|p| (node, p));
-                }
-                path.insert(node);
-                visited.insert(node);
-            }
+/// Reads a file of node names, one per line, written like an edge list,
+/// along with the line each name is on.
+fn read_names(path: &str) -> Result<Vec<(usize, String)>, DepsError> {
+    let (name, contents) = read_input(path)?;
+    let mut names = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let number = number + 1;
+        let tokens = formats::edgelist::tokenize(line)
+            .map_err(|(column, message)| DepsError::at(&name, number, column, message, &contents))?;
+        match <[String; 1]>::try_from(tokens) {
+            Ok([node]) => names.push((number, node)),
+            Err(tokens) if tokens.is_empty() => {},
+            Err(_) => return Err(DepsError::at(&name, number, 1, "expected a single name", &contents)),
+        }
+    }
+    Ok(names)
+}
 
-            if dep_index < items[node].deps.len() {
-                stack.push((node, dep_index + 1, parent));
-                let next_node = items[node].deps[dep_index];
-                stack.push((next_node, 0, Some(node)));
-            } else {
-                path.remove(&node);
-            }
+/// Reads a file of `old new` lines, with the quoting and comments of an
+/// edge list, into pairs of names.
+fn read_renames(path: &str) -> Result<Vec<(String, String)>, DepsError> {
+    let (name, contents) = read_input(path)?;
+    let mut renames: Vec<(String, String)> = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let number = number + 1;
+        let names = formats::edgelist::tokenize(line)
+            .map_err(|(column, message)| DepsError::at(&name, number, column, message, &contents))?;
+        match <[String; 2]>::try_from(names) {
+            Ok([old, new]) => {
+                if renames.iter().any(|(other, _)| *other == old) {
+                    return Err(DepsError::at(&name, number, 1, &format!("{} is renamed more than once", old), &contents));
+                }
+                renames.push((old, new));
+            },
+            Err(names) if names.is_empty() => {},
+            Err(_) => return Err(DepsError::at(&name, number, 1, "expected an old name and a new name", &contents)),
         }
     }
+    Ok(renames)
+}
 
-    None
+#[derive(Clone, Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Print warnings about ignored parts of the input and log the phases
+    /// of the work on stderr, again for more detail
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// When to color messages
+    #[arg(long, value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Show the progress of reading, parsing, looking for cycles and
+    /// sorting on a line of stderr, by default only when it is a terminal
+    #[arg(
+        long, value_enum, global = true, value_name = "WHEN", num_args = 0..=1, require_equals = true,
+        default_value_t = ProgressChoice::Never, default_missing_value = "auto",
+    )]
+    progress: ProgressChoice,
+    /// After the command, print on stderr how long reading, parsing,
+    /// building the graph, the analysis and the output took, and how big
+    /// the graph got. With --json it goes under a `timings` key of the
+    /// document on stdout
+    #[arg(long, global = true)]
+    timings: bool,
+    /// Read the default options from this file rather than the closest
+    /// dep-sys.toml in the current directory or above
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+    /// Don't read default options from a dep-sys.toml
+    #[arg(long, global = true, conflicts_with = "config")]
+    no_config: bool,
+    /// Look for cycles and strongly connected components and work out the
+    /// statistics of the weakly connected components of the graph on this
+    /// many threads, by default as many as the machine runs at once
+    #[arg(long, global = true, value_name = "N")]
+    threads: Option<NonZeroUsize>,
+
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn sort_items<T: Clone>(items: &[Item<T>]) -> Vec<Item<T>> {
-    let mut graph = HashMap::new();
-    let mut in_degree = vec![0; items.len()];
+/// The formats a graph can be read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// A Graphviz digraph
+    Dot,
+    /// An adjacency map `{"a": ["b"]}` or `{"nodes": [...], "edges": [["a", "b"]]}`
+    Json,
+    /// A mapping `{a: [b, c], b: c}` of names to dependencies
+    Yaml,
+    /// One `a b` or `a -> b` edge per line
+    Edgelist,
+    /// One edge per row, see --from-column and --to-column
+    Csv,
+    /// A GraphML document
+    Graphml,
+    /// The output of `cargo metadata`, or a `Cargo.toml` to run it on
+    CargoMetadata,
+    /// An npm `package-lock.json`
+    NpmLock,
+    /// The rules of a Makefile
+    Makefile,
+    /// The output of `go mod graph`
+    Gomod,
+}
 
-    for (index, item) in items.iter().enumerate() {
-        for &dep_index in &item.deps {
-            graph.entry(dep_index).or_insert_with(Vec::new).push(index);
-            in_degree[index] += 1;
-        }
+/// The formats `export` can write a graph in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExportFormat {
+    /// A Graphviz digraph
+    Dot,
+    /// A Mermaid flowchart
+    Mermaid,
+    /// A GraphML document, for yEd and Gephi
+    Graphml,
+    /// A `{"nodes": [...], "edges": [["a", "b"]]}` list, as `--format json` reads
+    Json,
+}
+
+/// The extra node attributes `export` can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum NodeAttr {
+    /// The topological level of the node, which needs the graph to be acyclic
+    Level,
+}
+
+/// What `rank` scores the nodes by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RankBy {
+    /// The number of direct dependents
+    In,
+    /// The number of direct dependencies
+    Out,
+    /// The number of nodes it depends on, directly or not
+    Reach,
+    /// The number of nodes that depend on it, directly or not
+    Rdeps,
+}
+
+/// What `nodes` sorts the nodes by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum NodeSort {
+    /// By name
+    Name,
+    /// By fan-in, highest first
+    In,
+    /// By fan-out, highest first
+    Out,
+}
+
+/// What `layers-check` and `verify` do about nodes left out of the layers
+/// or the order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Severity {
+    /// List them with the violations and fail
+    Error,
+    /// Warn about them on stderr
+    Warn,
+    /// Say nothing about them
+    Ignore,
+}
+
+/// Writes a list of names one per line, or as a JSON array, or only how many
+/// there are.
+fn write_names(out: &mut impl Write, names: &[String], count: bool, json: bool) -> io::Result<()> {
+    match (count, json) {
+        (true, true) => writeln!(out, "{}", serde_json::json!({ "count": names.len() })),
+        (true, false) => writeln!(out, "{}", names.len()),
+        (false, true) => writeln!(out, "{}", serde_json::json!(names)),
+        (false, false) => names.iter().try_for_each(|name| writeln!(out, "{}", name)),
     }
+}
+
+/// The options shared by `roots` and `leaves`.
+#[derive(Clone, clap::Args)]
+struct ListArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Only print how many nodes there are
+    #[arg(long)]
+    count: bool,
+    /// Leave out nodes without any edges
+    #[arg(long)]
+    exclude_isolated: bool,
+    /// Print the names, or the count, as JSON
+    #[arg(long)]
+    json: bool,
+    /// Write the list to a file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+impl ListArgs {
+    /// Prints the names of the nodes `pick` accepts given their fan-out and
+    /// fan-in, sorted.
+    fn run<F>(self, verbose: bool, pick: F) -> Result<ExitCode, DepsError>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        let json = self.json;
+        json_errors(json, (|| {
+            let mut out = Output::open(self.output.as_deref(), &self.input.input_paths)?;
+            let items = self.input.load(verbose)?.graph.items;
+            let (fan_out, fan_in) = degrees(&items);
+
+            let mut names: Vec<_> = (0..items.len())
+                .filter(|&index| pick(fan_out[index], fan_in[index]))
+                .filter(|&index| !(self.exclude_isolated && fan_out[index] == 0 && fan_in[index] == 0))
+                .map(|index| items[index].to_string())
+                .collect();
+            names.sort();
 
-    let mut queue = VecDeque::new();
-    for (index, &degree) in in_degree.iter().enumerate() {
-        if degree == 0 {
-            queue.push_back(index);
+            write_names(&mut out, &names, self.count, json)?;
+
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })())
+    }
+}
+
+// The options of the commands that can keep running as their inputs
+// change.
+#[derive(Clone, clap::Args)]
+struct WatchArgs {
+    /// Run again every time an input changes, until Ctrl-C is pressed
+    #[arg(long)]
+    watch: bool,
+    /// How often to look for changes with --watch, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 500, requires = "watch")]
+    interval: u64,
+}
+
+#[derive(Clone, clap::Args)]
+struct InputArgs {
+    /// Paths to the input graphs, or `-` for standard input. Several graphs
+    /// are merged into one by node name
+    #[arg(required = true)]
+    input_paths: Vec<String>,
+    /// The format of the input graphs
+    #[arg(long, value_enum, default_value_t = Format::Dot)]
+    format: Format,
+    /// How to interpret an undirected graph
+    #[arg(long, value_enum, default_value_t = Undirected::Error)]
+    treat_undirected_as: Undirected,
+    /// Show the name of each labelled node next to its label
+    #[arg(long)]
+    show_ids: bool,
+    /// Print a warning for every edge that appears more than once in an input
+    #[arg(long)]
+    warn_duplicates: bool,
+    /// Leave out the nodes whose name matches this regular expression
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Option<String>,
+    /// Only keep the nodes whose name matches this regular expression
+    #[arg(long, value_name = "PATTERN")]
+    only: Option<String>,
+    /// Connect what depended on a left out node to what it depended on,
+    /// instead of dropping its edges
+    #[arg(long)]
+    rewire: bool,
+    /// The CSV column holding the dependent, by header name or number
+    #[arg(long, default_value = "1")]
+    from_column: String,
+    /// The CSV column holding the dependency, by header name or number
+    #[arg(long, default_value = "2")]
+    to_column: String,
+    /// Read the first CSV row as an edge rather than a header
+    #[arg(long)]
+    no_header: bool,
+    /// Include third-party crates in a Cargo workspace graph
+    #[arg(long)]
+    include_external: bool,
+    /// Include dev-dependencies in a Cargo workspace graph
+    #[arg(long)]
+    include_dev: bool,
+    /// Merge every version of a Go module into one node
+    #[arg(long)]
+    collapse_versions: bool,
+    /// Prefix the name of every node in a DOT cluster with the cluster, as
+    /// `payments.api` for `api` in `subgraph cluster_payments`
+    #[arg(long)]
+    qualify_clusters: bool,
+    /// Drop the DOT edges with this attribute before anything else, such as
+    /// `style=dashed` for soft dependencies that may form cycles
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_attribute)]
+    ignore_edge_attr: Vec<(String, String)>,
+    /// Keep each input file, once loaded, in `INPUT.depsys-cache` next to
+    /// it, and load that instead for as long as the file is unchanged
+    #[arg(long)]
+    cache: bool,
+    /// Neither read nor write caches, even with --cache
+    #[arg(long)]
+    no_cache: bool,
+}
+
+impl InputArgs {
+    fn load(&self, verbose: bool) -> Result<Loader, DepsError> {
+        self.load_paths(&self.input_paths, verbose)
+    }
+
+    /// Adds the graph read from `contents` to `loader`.
+    fn parse(&self, loader: &mut Loader, name: &str, contents: &str) -> Result<(), DepsError> {
+        let _parse = Phase::start("parse");
+        match self.format {
+            Format::Dot => {
+                let parsing = Progress::phase(format_args!("Parsing {}, {:.1} MB", name, contents.len() as f64 / 1e6));
+                let graph = graphviz_rust::parse(contents)
+                    .map_err(|report| DepsError::parse(name, &report, contents))?;
+                drop(parsing);
+                let _graph = Phase::start("graph");
+                from_graphviz(loader, &graph, self.treat_undirected_as)?;
+            },
+            Format::Json => formats::json::load(loader, name, contents)?,
+            Format::Yaml => formats::yaml::load(loader, name, contents)?,
+            Format::Edgelist => formats::edgelist::load(loader, name, contents)?,
+            Format::Csv => {
+                let columns = formats::csv::Columns {
+                    from: &self.from_column,
+                    to: &self.to_column,
+                    has_header: !self.no_header,
+                };
+                formats::csv::load(loader, name, contents, &columns)?;
+            },
+            Format::Graphml => {
+                formats::graphml::load(loader, name, contents, self.treat_undirected_as)?;
+            },
+            Format::CargoMetadata => {
+                let options = formats::cargo::Options {
+                    include_external: self.include_external,
+                    include_dev: self.include_dev,
+                };
+                formats::cargo::load(loader, name, contents, &options)?;
+            },
+            Format::NpmLock => formats::npm::load(loader, name, contents)?,
+            Format::Makefile => formats::makefile::load(loader, name, contents)?,
+            Format::Gomod => {
+                formats::gomod::load(loader, name, contents, self.collapse_versions)?;
+            },
         }
+        Ok(())
+    }
+
+    /// The options that change what parsing an input gives, which a cache
+    /// of it is only good for.
+    fn parse_options(&self) -> String {
+        format!(
+            "{:?} {:?} {:?} {:?} {} {} {} {}",
+            self.format, self.treat_undirected_as, self.from_column, self.to_column,
+            self.no_header, self.include_external, self.include_dev, self.collapse_versions,
+        )
     }
 
-    let mut sorted_indices = Vec::new();
-    while let Some(index) = queue.pop_front() {
-        if let Some(deps) = graph.get(&index) {
-            for &dep_index in deps {
-                in_degree[dep_index] -= 1;
-                if in_degree[dep_index] == 0 {
-                    queue.push_back(dep_index);
+    /// Loads `paths` as if they were the input paths.
+    fn load_paths(&self, paths: &[String], verbose: bool) -> Result<Loader, DepsError> {
+        let _graph = Phase::start("graph");
+        let mut loader = Loader::new(verbose);
+        for path in paths {
+            let started = Instant::now();
+            let generated = self.format == Format::CargoMetadata && path.ends_with("Cargo.toml");
+            let read = Phase::start("read");
+            let (name, contents) = if generated {
+                (path.clone(), formats::cargo::metadata(path)?)
+            } else {
+                read_input(path)?
+            };
+            drop(read);
+            if self.cache && !self.no_cache && !generated && path != "-" {
+                let cache_path = cache::path_for(path);
+                let key = cache::key(&contents, &self.parse_options());
+                let loading = Phase::start("cache");
+                let cached = cache::load(&cache_path, key, verbose);
+                drop(loading);
+                if let Some(cached) = cached {
+                    loader.merge(cached);
+                    log::debug!("Loaded {} from {} in {:.1?}", name, cache_path.display(), started.elapsed());
+                } else {
+                    let mut input = Loader::new(verbose);
+                    self.parse(&mut input, &name, &contents)?;
+                    input.end_graph();
+                    let _saving = Phase::start("cache");
+                    if let Err(err) = cache::save(&cache_path, &input, key) {
+                        report::warning(&err);
+                    }
+                    loader.merge(input);
+                    log::debug!("Loaded {} and cached it in {:.1?}", name, started.elapsed());
                 }
+            } else {
+                self.parse(&mut loader, &name, &contents)?;
+                log::debug!("Loaded {} in {:.1?}", name, started.elapsed());
+            }
+            note_size(&loader);
+            loader.end_graph();
+        }
+        if self.qualify_clusters {
+            loader.qualify_clusters();
+        }
+        if !self.ignore_edge_attr.is_empty() {
+            loader.ignore_edges(|attributes| {
+                self.ignore_edge_attr.iter().any(|(key, value)| attributes.contains(key, value))
+            });
+        }
+        if self.exclude.is_some() || self.only.is_some() {
+            let pattern = |pattern: &Option<String>| pattern.as_deref().map(Regex::new).transpose()
+                .map_err(|err| DepsError::Usage(format!("Invalid pattern: {}", err)));
+            let (exclude, only) = (pattern(&self.exclude)?, pattern(&self.only)?);
+            let keep: Vec<_> = loader.items.iter()
+                .map(|item| {
+                    only.as_ref().is_none_or(|only| only.is_match(&item.name))
+                        && !exclude.as_ref().is_some_and(|exclude| exclude.is_match(&item.name))
+                })
+                .collect();
+            loader.retain(&keep, self.rewire);
+        }
+        if self.show_ids {
+            for item in &mut loader.items {
+                item.data.show_name = true;
+            }
+        }
+        if self.warn_duplicates {
+            for &(a, b) in &loader.duplicates {
+                report::warning(format_args!("Duplicate edge {} -> {}", *loader.items[a], *loader.items[b]));
             }
         }
-        sorted_indices.push(index);
+        let edges: usize = loader.items.iter().map(|item| item.deps.len()).sum();
+        log::debug!("The graph has {} nodes and {} edges", loader.items.len(), edges);
+        note_size(&loader);
+        Ok(loader)
     }
-
-    sorted_indices.into_iter().map(|index| items[index].clone()).collect()
 }
 
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    #[command(subcommand)]
-    command: Command,
+/// Notes the size of the graph loaded so far for `--timings`.
+fn note_size(loader: &Loader) {
+    if timings::enabled() {
+        let edges = loader.items.iter().map(|item| item.deps.len()).sum();
+        timings::note_size(loader.items.len(), edges, loader.index_entries());
+    }
 }
 
-#[derive(Subcommand)]
+#[derive(Clone, Subcommand)]
 enum Command {
+    /// Look for circular dependencies, exiting with 1 when there is one
     Check {
-        input_path: String,
+        #[command(flatten)]
+        input: InputArgs,
+        /// Also fail on self-loops and duplicate edges, printing every
+        /// problem on its own line
+        #[arg(long)]
+        strict: bool,
+        /// Print the cycles and graph size as JSON, with messages on stderr
+        #[arg(long)]
+        json: bool,
+        /// Write the report to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Write a copy of the graph to PATH with every cycle highlighted
+        #[arg(long, value_name = "PATH")]
+        annotate: Option<String>,
+        /// Print nothing and only report through the exit status: 0 for no
+        /// cycles, 1 for cycles, 2 or 3 when the graph could not be read
+        #[arg(short, long, conflicts_with_all = ["json", "output"])]
+        quiet: bool,
+        #[command(flatten)]
+        watch: WatchArgs,
     },
+    /// Print the nodes in an order where every node comes after its
+    /// dependencies
     Sort {
-        input_path: String,
+        #[command(flatten)]
+        input: InputArgs,
+        /// Print the order and the levels of independent items as JSON
+        #[arg(long)]
+        json: bool,
+        /// Print dependents before their dependencies, the shutdown order
+        /// rather than the build order
+        #[arg(long)]
+        reverse: bool,
+        /// Group the order into levels of items that can be handled at the
+        /// same time, each only depending on the levels before it
+        #[arg(long = "levels", conflicts_with = "json")]
+        by_level: bool,
+        /// Order items that could go in either order by name, so the result
+        /// doesn't change when the input is reordered
+        #[arg(long)]
+        stable: bool,
+        /// Put A somewhere before B in the order, as if one depended on the
+        /// other, without adding an edge to the graph
+        #[arg(long, value_name = "before:A:B", value_parser = parse_constraint)]
+        constraint: Vec<(String, String)>,
+        /// Read more constraints from a file, one `before:A:B` per line
+        #[arg(long, value_name = "PATH")]
+        constraints_file: Option<String>,
+        /// Among the items that are ready, put the ones with the highest
+        /// value of this numeric node attribute first, counting a missing
+        /// one as 0
+        #[arg(long, value_name = "ATTR")]
+        priority_attr: Option<String>,
+        /// Write the order to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+    /// Run a shell command for every node, dependencies first
+    Run {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The command to run, with `{}` replaced by the name of the node
+        #[arg(long, value_name = "COMMAND")]
+        exec: String,
+        /// After a failure, still run the nodes that don't depend on it
+        #[arg(long)]
+        keep_going: bool,
+        /// Run up to this many commands at once, prefixing each line they
+        /// print with the node
+        #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+        /// Print the commands instead of running them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List every elementary cycle of the graph, one per line
+    Cycles {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Stop after this many cycles
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Write the cycles to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the strongly connected components of the graph, largest first
+    Scc {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Include the components of a single node without a self-loop
+        #[arg(long)]
+        all: bool,
+        /// Print the components as a JSON array of arrays of names
+        #[arg(long)]
+        json: bool,
+        /// Write the components to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Collapse every strongly connected component into one node and write
+    /// the resulting graph without cycles as DOT
+    Condense {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List every node with its fan-in and fan-out
+    Nodes {
+        #[command(flatten)]
+        input: InputArgs,
+        /// What to sort the nodes by
+        #[arg(long, value_enum, default_value_t = NodeSort::Name)]
+        sort: NodeSort,
+        /// Only print how many nodes there are
+        #[arg(long)]
+        count: bool,
+        /// Print the nodes, or their count, as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the nodes to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List every edge, sorted
+    Edges {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Only list edges from nodes matching this regular expression
+        #[arg(long, value_name = "PATTERN")]
+        from: Option<Regex>,
+        /// Only list edges to nodes matching this regular expression
+        #[arg(long, value_name = "PATTERN")]
+        to: Option<Regex>,
+        /// Only print how many edges there are
+        #[arg(long)]
+        count: bool,
+        /// Print the edges, or their count, as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the edges to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the nodes nothing depends on, the entry points
+    Roots {
+        #[command(flatten)]
+        list: ListArgs,
+    },
+    /// List the nodes that depend on nothing
+    Leaves {
+        #[command(flatten)]
+        list: ListArgs,
+    },
+    /// List everything a node depends on, directly or not
+    Deps {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The node to start from, by name or label
+        #[arg(required = true)]
+        node: String,
+        /// Only follow this many edges
+        #[arg(long, conflicts_with = "direct")]
+        depth: Option<usize>,
+        /// Only list the direct dependencies, like --depth 1
+        #[arg(long)]
+        direct: bool,
+        /// Draw the dependencies as an indented tree, 100 edges deep unless
+        /// --depth is given
+        #[arg(long)]
+        tree: bool,
+        /// Write the dependencies to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Print what there is to know about one node and its neighbors
+    Show {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The node to show, by name or label
+        #[arg(required = true)]
+        node: String,
+        /// Print the node as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the node to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Draw the dependencies of a node as an indented tree
+    Tree {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The node at the root of the tree, by name or label
+        #[arg(required = true)]
+        node: String,
+        /// Only follow this many edges, 100 when not given
+        #[arg(long)]
+        depth: Option<usize>,
+        /// Draw the tree of dependents instead
+        #[arg(long)]
+        reverse: bool,
+        /// The characters to draw the branches with
+        #[arg(long, value_enum, default_value_t = Charset::Utf8)]
+        charset: Charset,
+        /// Write the tree to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List everything that depends on a node, directly or not
+    Rdeps {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The node to start from, by name or label
+        #[arg(required = true)]
+        node: String,
+        /// Only follow this many edges
+        #[arg(long, conflicts_with = "direct")]
+        depth: Option<usize>,
+        /// Only list the direct dependents, like --depth 1
+        #[arg(long)]
+        direct: bool,
+        /// Only print how many dependents there are
+        #[arg(long)]
+        count: bool,
+        /// Print the dependents, or their count, as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the dependents to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Report how much of the graph each entry point reaches, and what none
+    /// of them reach
+    Coverage {
+        #[command(flatten)]
+        input: InputArgs,
+        /// An entry point, by name or label
+        #[arg(long = "root", value_name = "NODE")]
+        roots: Vec<String>,
+        /// A file listing the entry points, one per line
+        #[arg(long, value_name = "PATH")]
+        roots_file: Option<String>,
+        /// Fail when some nodes are not reached from any entry point
+        #[arg(long)]
+        fail_on_unreachable: bool,
+        /// Print the nodes each entry point reaches, and the rest, as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the report to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check whether adding an edge would create a cycle, printing `ok` when
+    /// it would not
+    #[command(group = clap::ArgGroup::new("new_edge").required(true).multiple(true))]
+    CheckAdd {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The node that would get the dependency, by name or label
+        #[arg(long, value_name = "NODE", requires = "to", conflicts_with = "pair", group = "new_edge")]
+        from: Option<String>,
+        /// The node it would depend on, by name or label
+        #[arg(long, value_name = "NODE", requires = "from")]
+        to: Option<String>,
+        /// The node that would get the dependency and the node it would
+        /// depend on, after `--` since the input paths take every value before
+        #[arg(last = true, num_args = 2, value_names = ["FROM", "TO"], group = "new_edge")]
+        pair: Vec<String>,
+        /// An edge to add, written `a:b`, after the one given by the nodes
+        #[arg(long = "edge", value_name = "A:B", value_parser = parse_edge, group = "new_edge")]
+        edges: Vec<(String, String)>,
+        /// Print nothing and only report through the exit status
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Summarize what removing nodes or edges would change, without touching
+    /// the input
+    WhatIf {
+        #[command(flatten)]
+        input: InputArgs,
+        /// A node to remove with all of its edges, by name or label
+        #[arg(long = "remove-node", value_name = "NODE")]
+        remove_nodes: Vec<String>,
+        /// An edge to remove, written `a:b`
+        #[arg(long = "remove-edge", value_name = "A:B", value_parser = parse_edge)]
+        remove_edges: Vec<(String, String)>,
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+        /// Also write the graph after the removals to a DOT file
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List everything that depends on any of the changed nodes, directly
+    /// or not, and so needs rebuilding
+    Impact {
+        #[command(flatten)]
+        input: InputArgs,
+        /// A node that changed, by name or label
+        #[arg(long = "changed", value_name = "NODE")]
+        changed: Vec<String>,
+        /// A file listing the changed nodes, one per line
+        #[arg(long, value_name = "PATH")]
+        changed_file: Option<String>,
+        /// Only list the impacted nodes nothing depends on
+        #[arg(long)]
+        roots_only: bool,
+        /// List the changed nodes too
+        #[arg(long)]
+        include_changed: bool,
+        /// Print the nodes and their count as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the nodes to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Show how one node comes to depend on another
+    #[command(group = clap::ArgGroup::new("ends").required(true))]
+    Why {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The dependent node, by name or label
+        #[arg(long, value_name = "NODE", requires = "to", group = "ends")]
+        from: Option<String>,
+        /// The dependency, by name or label
+        #[arg(long, value_name = "NODE", requires = "from")]
+        to: Option<String>,
+        /// The dependent and the dependency, after `--` since the input paths
+        /// take every value before
+        #[arg(last = true, num_args = 2, value_names = ["FROM", "TO"], group = "ends")]
+        pair: Vec<String>,
+        /// List every path that visits no node twice, not only a shortest one
+        #[arg(long)]
+        all: bool,
+        /// Stop after this many paths
+        #[arg(long, requires = "all")]
+        limit: Option<usize>,
+        /// Write the paths to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Write a completion script for a shell to stdout
+    Completions {
+        /// The shell to complete for
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+    /// List the node names starting with a prefix, for completion scripts.
+    /// Prints nothing when the graph can't be read
+    #[command(name = "__complete-nodes", hide = true)]
+    CompleteNodes {
+        #[command(flatten)]
+        input: InputArgs,
+        /// What the names have to start with
+        #[arg(long, default_value = "")]
+        prefix: String,
+    },
+    /// Load the graph once and answer queries about it typed one per line
+    Repl {
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// Print statistics about the size and shape of the graph
+    Stats {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the statistics to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the nodes with more dependencies or dependents than allowed, or
+    /// the ten with the most of each when no limit is given
+    Fanout {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The most dependencies a node may have
+        #[arg(long, value_name = "N")]
+        max_out: Option<usize>,
+        /// The most dependents a node may have
+        #[arg(long, value_name = "N")]
+        max_in: Option<usize>,
+        /// Print the nodes as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the nodes to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Suggest edges to remove so that the graph has no cycles, those on the
+    /// most cycles first
+    SuggestBreaks {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Write the graph as DOT with the edges removed instead
+        #[arg(long)]
+        apply: bool,
+        /// Write the edges or the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the edges that another path already implies, without changing
+    /// the graph
+    Redundant {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Fail when there are any
+        #[arg(long)]
+        fail: bool,
+        /// Write the edges to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Print the longest chain of dependencies in the graph
+    Depth {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Only look at the chains starting from this node
+        #[arg(long, value_name = "NODE")]
+        from: Option<String>,
+        /// The node to start from, after `--` since the input paths take
+        /// every value before
+        #[arg(last = true, value_name = "NODE", conflicts_with = "from")]
+        node: Option<String>,
+        /// Fail when the longest chain is longer than this
+        #[arg(long)]
+        max: Option<usize>,
+        /// Write the chain to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Print the chain of dependencies with the highest total cost
+    CriticalPath {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The numeric node attribute holding the cost, which is 1 for
+        /// nodes that don't set it
+        #[arg(long, default_value = "cost")]
+        attr: String,
+        /// Write the path to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Estimate how long a parallel build takes by simulating it on a
+    /// number of workers
+    Schedule {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The numeric node attribute holding the cost, which is 1 for
+        /// nodes that don't set it
+        #[arg(long, default_value = "cost")]
+        attr: String,
+        /// How many nodes can run at the same time
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        workers: u32,
+        /// Print the schedule as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the schedule to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Remove the edges that other paths already imply and write the result
+    /// as DOT
+    Reduce {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Only list the edges that would be removed
+        #[arg(long)]
+        list: bool,
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List every pair of nodes where the first depends on the second,
+    /// directly or not
+    Closure {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Only list what this node depends on
+        #[arg(long, value_name = "NODE")]
+        from: Option<String>,
+        /// Write the closure as a DOT graph rather than one pair per line
+        #[arg(long)]
+        dot: bool,
+        /// Write the closure to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Keep only some nodes and everything they depend on, and write the
+    /// result as DOT
+    #[command(group = clap::ArgGroup::new("starts").required(true).multiple(true))]
+    Extract {
+        #[command(flatten)]
+        input: InputArgs,
+        /// A node to keep, by name or label. Can be given several times
+        #[arg(long = "from", value_name = "NODE", group = "starts")]
+        seeds: Vec<String>,
+        /// More nodes to keep, after `--` since the input paths take every
+        /// value before
+        #[arg(last = true, value_name = "NODE", group = "starts")]
+        nodes: Vec<String>,
+        /// Keep what depends on the nodes instead of what they depend on
+        #[arg(long, conflicts_with = "both")]
+        reverse: bool,
+        /// Keep both what the nodes depend on and what depends on them
+        #[arg(long)]
+        both: bool,
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Compare two graphs, showing the nodes and edges added and removed
+    Diff {
+        /// Give the old graph and then the new one as the input paths
+        #[command(flatten)]
+        input: InputArgs,
+        /// Only show the added and removed edges
+        #[arg(long, conflicts_with = "nodes_only")]
+        edges_only: bool,
+        /// Only show the added and removed nodes
+        #[arg(long)]
+        nodes_only: bool,
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the differences to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Combine several graphs into one DOT graph, merging nodes by name
+    Merge {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Fail when the merged graph has a cycle that none of the inputs
+        /// have on their own
+        #[arg(long)]
+        fail_on_new_cycles: bool,
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the nodes without any edges
+    Orphans {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Print the names as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the list to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the groups of nodes connected by edges in either direction
+    Components {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Fail unless there are exactly this many components, listing the
+        /// smaller ones in full
+        #[arg(long)]
+        expect: Option<usize>,
+        /// Print the components as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the components to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Rank the nodes by how much they depend or are depended on
+    Rank {
+        #[command(flatten)]
+        input: InputArgs,
+        /// What to score the nodes by
+        #[arg(long, value_enum, default_value_t = RankBy::Rdeps)]
+        by: RankBy,
+        /// Only print the first N nodes
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+        /// Print the ranking as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the ranking to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the groups of nodes that have exactly the same direct
+    /// dependencies
+    Duplicates {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Skip groups sharing fewer than this many dependencies
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        min_deps: usize,
+        /// Print the groups as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the groups to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the nodes and edges whose removal would disconnect the graph,
+    /// taking edges in either direction
+    Bottlenecks {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Print the nodes and edges as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write the nodes and edges to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Merge the nodes of each namespace into one node and write the result
+    /// as DOT
+    #[command(group = clap::ArgGroup::new("key").required(true))]
+    Collapse {
+        #[command(flatten)]
+        input: InputArgs,
+        /// Group nodes by the first segments of their names, split at SEP
+        #[arg(long, visible_alias = "sep", value_name = "SEP", group = "key")]
+        by_prefix: Option<String>,
+        /// How many segments make up a group
+        #[arg(long, default_value_t = 1, requires = "by_prefix")]
+        depth: usize,
+        /// Group nodes by the first capture group of this regular expression,
+        /// leaving nodes it doesn't match alone
+        #[arg(long, value_name = "PATTERN", group = "key")]
+        by_regex: Option<Regex>,
+        /// Group nodes by the DOT cluster they were declared in, leaving
+        /// nodes outside clusters alone
+        #[arg(long, group = "key")]
+        by_cluster: bool,
+        /// Keep an edge from a group to itself when nodes within it depend
+        /// on each other
+        #[arg(long)]
+        keep_self: bool,
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Rename nodes and write the result as DOT, merging nodes that end up
+    /// with the same name
+    Rename {
+        #[command(flatten)]
+        input: InputArgs,
+        /// A file of `old-name new-name` lines
+        #[arg(long, value_name = "PATH")]
+        map: String,
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check the edges against the allow, deny and require rules of a TOML
+    /// file, failing on any violation
+    Validate {
+        #[command(flatten)]
+        input: InputArgs,
+        /// A TOML file of `[[rule]]` tables
+        #[arg(long, value_name = "PATH")]
+        rules: String,
+        /// Write the violations to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check that an order written by hand lists every node after its
+    /// dependencies
+    Verify {
+        #[command(flatten)]
+        input: InputArgs,
+        /// A file listing the nodes in order, one per line
+        #[arg(long, value_name = "PATH")]
+        order: String,
+        /// What to do about nodes of the graph the order leaves out
+        #[arg(long, value_enum, default_value_t = Severity::Warn)]
+        missing: Severity,
+        /// What to do about names in the order that aren't in the graph
+        #[arg(long, value_enum, default_value_t = Severity::Warn)]
+        extra: Severity,
+        /// Write the violations to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check that every edge goes from a higher layer to a lower one
+    LayersCheck {
+        #[command(flatten)]
+        input: InputArgs,
+        /// A YAML mapping of node names or patterns to layer numbers
+        #[arg(long, value_name = "PATH")]
+        layers: String,
+        /// Also allow edges between nodes of the same layer
+        #[arg(long)]
+        allow_same_layer: bool,
+        /// What to do about nodes without a layer
+        #[arg(long, value_enum, default_value_t = Severity::Warn)]
+        unassigned: Severity,
+        /// Write the violations to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Convert the graph into another format
+    Export {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The format to write
+        #[arg(long, value_enum)]
+        to: ExportFormat,
+        /// The direction of a Mermaid flowchart
+        #[arg(long, value_enum, default_value_t = Direction::TopDown)]
+        direction: Direction,
+        /// Add an attribute to every node of a DOT or GraphML export
+        #[arg(long, value_enum)]
+        attr: Vec<NodeAttr>,
+        /// Keep the edges dropped by --ignore-edge-attr, drawn dashed and
+        /// gray, without letting them count for --attr level
+        #[arg(long, requires = "ignore_edge_attr")]
+        include_ignored: bool,
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
     },
 }
 
-fn main() -> ExitCode {
-    let args = Args::parse();
+/// With `json` set, reports an error as a JSON document on stdout rather
+/// than leaving it to `main`.
+fn json_errors(json: bool, result: Result<ExitCode, DepsError>) -> Result<ExitCode, DepsError> {
+    match result {
+        Err(err) if json => {
+            report::error(&err);
+            let mut out = Output::open(None, &[])?;
+            writeln!(out, "{}", serde_json::json!({ "error": err.to_string() }))?;
+            out.finish()?;
+            Ok(error_code(&err))
+        },
+        result => result,
+    }
+}
 
+fn run(args: Args) -> Result<ExitCode, DepsError> {
+    // Whatever the stages below don't time is part of the analysis.
+    let _analysis = Phase::start("analysis");
+    let verbose = args.verbose > 0;
+    let threads = args.threads.map_or_else(parallel::default_threads, NonZeroUsize::get);
     match args.command {
-        Command::Check { input_path } => {
-            let contents = fs::read_to_string(input_path).unwrap();
-            let graph = graphviz_rust::parse(&contents).unwrap();
+        Command::Check { input, strict, json, output, annotate, quiet, watch: _ } => {
+            if quiet {
+                report::silence();
+            }
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let items = &loader.items;
+            if let Some(path) = annotate {
+                let mut annotated = Output::open(Some(&path), &input.input_paths)?;
+                annotated.write_all(formats::dot::annotate_cycles(items, loader.csr(), threads).as_bytes())?;
+                annotated.finish()?;
+            }
+            // Keep the output for the JSON document when one was asked for.
+            let colored = if json { report::stderr_colored() } else { out.colored() };
+            let mut report = |line: &str| if quiet {
+                Ok(())
+            } else if json {
+                eprintln!("{}", line);
+                Ok(())
+            } else {
+                writeln!(out, "{}", line)
+            };
+
+            let self_loops = find_self_loops(items);
+            let cycle = parallel::detect_cycle(items, loader.csr(), &loader.undirected, threads)?;
+
+            let failed = if strict {
+                let violations = strict_violations(&loader, threads)?;
+                for violation in &violations {
+                    report(violation)?;
+                }
+                !violations.is_empty()
+            } else {
+                for &index in &self_loops {
+                    report::problem(format_args!(
+                        "Node '{}' depends on itself", report::highlight(Style::Red, &*items[index])
+                    ));
+                }
+                if let Some(cycle) = &cycle {
+                    let names: Vec<_> = cycle.iter().map(|&index| report::highlight(Style::Red, &*items[index])).collect();
+                    report::problem(format_args!("Circular dependency detected: {}", cycle_chain(&names)));
+                }
+                !self_loops.is_empty() || cycle.is_some()
+            };
+            if !failed && self_loops.is_empty() && cycle.is_none() {
+                report(&report::paint(Style::Green, "The graph has no circular dependencies", colored))?;
+            }
+
+            if json {
+                let name = |index: usize| items[index].to_string();
+                let mut cycles: Vec<_> = self_loops.iter()
+                    .map(|&index| serde_json::json!({ "nodes": [name(index)] }))
+                    .collect();
+                if let Some(cycle) = cycle {
+                    let nodes: Vec<_> = cycle.into_iter().map(name).collect();
+                    cycles.push(serde_json::json!({ "nodes": nodes }));
+                }
+                let report = serde_json::json!({
+                    "cycles": cycles,
+                    "node_count": items.len(),
+                    "edge_count": items.iter().map(|item| item.deps.len()).sum::<usize>(),
+                });
+                writeln!(out, "{}", report)?;
+            }
+
+            out.finish()?;
+            Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+        },
+        Command::Sort { input, json, reverse, by_level, stable, mut constraint, constraints_file, priority_attr, output, watch: _ } => json_errors(json, (|| {
+            if let Some(path) = &constraints_file {
+                for (number, text) in read_names(path)? {
+                    let parsed = parse_constraint(&text).map_err(|message| DepsError::Invalid {
+                        path: path.clone(),
+                        message: format!("line {}: {}", number, message),
+                    })?;
+                    constraint.push(parsed);
+                }
+            }
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            if loader.any_both_ways {
+                return Err(DepsError::Unsupported(
+                    "Sorts of undirected graphs read with --treat-undirected-as both".to_string()
+                ));
+            }
+            ensure_acyclic(&loader.items, loader.csr())?;
+
+            // A constraint is an edge that only this sort sees, pointing the
+            // way that puts A first in the order that is printed.
+            let mut items = loader.items.clone();
+            let mut introduced = HashMap::new();
+            for (before, after) in &constraint {
+                let (a, b) = (loader.find(before)?, loader.find(after)?);
+                if a == b {
+                    return Err(DepsError::Usage(format!("{} can't come before itself", before)));
+                }
+                let (dependent, dependency) = if reverse { (a, b) } else { (b, a) };
+                if !items[dependent].deps.contains(&dependency) {
+                    items[dependent].deps.push(dependency);
+                    introduced.insert((dependent, dependency), format!("before:{}:{}", before, after));
+                }
+            }
+            // The graph itself is acyclic, so only constraints can have
+            // closed a cycle.
+            let cycle = if introduced.is_empty() { None } else { detect_cycle(&Csr::forward(&items), &HashSet::new())? };
+            if let Some(cycle) = cycle {
+                let constraints = (0..cycle.len())
+                    .filter_map(|i| introduced.get(&(cycle[i], cycle[(i + 1) % cycle.len()])).cloned())
+                    .collect();
+                let cycle = cycle.iter().map(|&index| items[index].to_string()).collect();
+                return Err(DepsError::ConstraintCycle { cycle, constraints });
+            }
+
+            let priorities = priority_attr.as_ref()
+                .map(|attr| items.iter()
+                    .map(|item| match item.attribute(attr) {
+                        None => Ok(0.0),
+                        Some(value) => value.parse::<f64>().ok().filter(|priority| priority.is_finite()).ok_or_else(|| {
+                            DepsError::Attribute {
+                                node: item.to_string(),
+                                key: attr.clone(),
+                                value: value.to_string(),
+                                expected: "a number",
+                            }
+                        }),
+                    })
+                    .collect::<Result<Vec<_>, _>>())
+                .transpose()?;
+            let (mut sorted, mut levels) = sort_items(&items, stable, priorities.as_deref())?;
+            if reverse {
+                sorted.reverse();
+                levels.reverse();
+            }
+            let order: Vec<_> = sorted.iter().map(|&index| items[index].to_string()).collect();
+            let mut grouped = vec![Vec::new(); levels.iter().max().map_or(0, |max| max + 1)];
+            for (name, &level) in order.iter().zip(&levels) {
+                grouped[level].push(name);
+            }
+            if reverse {
+                grouped.reverse();
+            }
+
+            if json {
+                writeln!(out, "{}", serde_json::json!({ "order": order, "levels": grouped }))?;
+            } else if by_level {
+                for (level, names) in grouped.iter().enumerate() {
+                    let names: Vec<_> = names.iter().map(|name| name.as_str()).collect();
+                    writeln!(out, "Level {}: {}", level, names.join(", "))?;
+                }
+            } else {
+                for index in sorted {
+                    writeln!(out, "{}", *items[index])?;
+                }
+            }
+
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Run { input, exec, keep_going, jobs, dry_run } => {
+            let loader = input.load(verbose)?;
+            if loader.any_both_ways {
+                return Err(DepsError::Unsupported(
+                    "Runs of undirected graphs read with --treat-undirected-as both".to_string()
+                ));
+            }
+            ensure_acyclic(&loader.items, loader.csr())?;
+            let items = loader.graph.items;
+            let (order, _) = sort_order(&items, false);
+
+            if dry_run {
+                for &index in &order {
+                    println!("{}", exec.replace("{}", &shell_quote(&items[index].name)));
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let mut status = run_jobs(&items, &exec, jobs as usize, keep_going);
+            skip_blocked(&items, &order, &mut status);
+
+            let names = |wanted: RunStatus| -> Vec<String> {
+                order.iter().filter(|&&index| status[index] == wanted).map(|&index| items[index].to_string()).collect()
+            };
+            let (succeeded, failed, skipped, not_run) = (
+                names(RunStatus::Succeeded),
+                names(RunStatus::Failed),
+                names(RunStatus::Skipped),
+                names(RunStatus::NotRun),
+            );
+            report::info(format_args!(
+                "{} succeeded, {} failed, {} skipped, {} not run",
+                succeeded.len(), failed.len(), skipped.len(), not_run.len(),
+            ));
+            for (heading, names) in [("Failed", &failed), ("Skipped after a failed dependency", &skipped), ("Not run", &not_run)] {
+                if !names.is_empty() {
+                    report::info(format_args!("{}: {}", heading, names.join(", ")));
+                }
+            }
+            Ok(if failed.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+        },
+        Command::Cycles { input, limit, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let colored = out.colored();
+            let code = queries::cycles(&loader, limit, colored, &mut out)?;
+            out.finish()?;
+            Ok(code)
+        },
+        Command::Scc { input, all, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let items = &loader.items;
+
+            let mut components: Vec<_> = parallel::strongly_connected_components(items, loader.csr(), threads)
+                .into_iter()
+                .filter(|members| all || members.len() > 1 || items[members[0]].deps.contains(&members[0]))
+                .collect();
+            for members in &mut components {
+                members.sort_unstable();
+            }
+            components.sort_by_key(|members| Reverse(members.len()));
+
+            let names = |members: &[usize]| members.iter().map(|&index| items[index].to_string()).collect::<Vec<_>>();
+            if json {
+                let components: Vec<_> = components.iter().map(|members| names(members)).collect();
+                writeln!(out, "{}", serde_json::json!(components))?;
+            } else {
+                for members in &components {
+                    writeln!(out, "{}: {}", members.len(), names(members).join(", "))?;
+                }
+            }
+
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Condense { input, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let items = condense(&loader.items, loader.csr());
+            let graph = formats::dot::to_graphviz(&items, |_| Attrs::new(), |_, _| Attrs::new());
+            out.write_all(formats::dot::print(&graph).as_bytes())?;
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Nodes { input, sort, count, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = input.load(verbose)?.graph.items;
+            let (fan_out, fan_in) = degrees(&items);
+            let mut nodes: Vec<_> = (0..items.len()).collect();
+            nodes.sort_by(|&a, &b| {
+                let by_degree = match sort {
+                    NodeSort::Name => Ordering::Equal,
+                    NodeSort::In => fan_in[b].cmp(&fan_in[a]),
+                    NodeSort::Out => fan_out[b].cmp(&fan_out[a]),
+                };
+                by_degree.then_with(|| items[a].name.cmp(&items[b].name))
+            });
+
+            match (count, json) {
+                (true, true) => writeln!(out, "{}", serde_json::json!({ "count": nodes.len() }))?,
+                (true, false) => writeln!(out, "{}", nodes.len())?,
+                (false, true) => {
+                    let nodes: Vec<_> = nodes.iter()
+                        .map(|&index| serde_json::json!({ "node": items[index].name, "in": fan_in[index], "out": fan_out[index] }))
+                        .collect();
+                    writeln!(out, "{}", serde_json::Value::from(nodes))?;
+                },
+                (false, false) => for &index in &nodes {
+                    writeln!(out, "{} {} {}", formats::edgelist::quote(&items[index].name), fan_in[index], fan_out[index])?;
+                },
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Edges { input, from, to, count, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = &input.load(verbose)?.graph.items;
+            let matches = |pattern: &Option<Regex>, name: &str| pattern.as_ref().is_none_or(|pattern| pattern.is_match(name));
+            let mut edges: Vec<_> = items.iter()
+                .flat_map(|item| item.deps.iter().map(move |&dep| (item.name.as_str(), items[dep].name.as_str())))
+                .filter(|&(a, b)| matches(&from, a) && matches(&to, b))
+                .collect();
+            edges.sort_unstable();
+            edges.dedup();
+
+            match (count, json) {
+                (true, true) => writeln!(out, "{}", serde_json::json!({ "count": edges.len() }))?,
+                (true, false) => writeln!(out, "{}", edges.len())?,
+                (false, true) => writeln!(out, "{}", serde_json::json!(edges))?,
+                (false, false) => for &(a, b) in &edges {
+                    writeln!(out, "{} -> {}", formats::edgelist::quote(a), formats::edgelist::quote(b))?;
+                },
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Roots { list } => list.run(verbose, |_, fan_in| fan_in == 0),
+        Command::Leaves { list } => list.run(verbose, |fan_out, _| fan_out == 0),
+        Command::Deps { input, node, depth, direct, tree, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let depth = if direct { Some(1) } else { depth };
+            let code = queries::deps(&loader, &node, depth, tree, &mut out)?;
+            out.finish()?;
+            Ok(code)
+        },
+        Command::Show { input, node, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let code = queries::show(&loader, &node, json, &mut out)?;
+            out.finish()?;
+            Ok(code)
+        })()),
+        Command::Tree { input, node, depth, reverse, charset, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let root = loader.find(&node)?;
+            let items = &loader.items;
+            let edges = if reverse { Edges::reverse(items) } else { Edges::packed(items, loader.csr()) };
+
+            write_tree(&mut out, items, edges, root, depth, charset)?;
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Rdeps { input, node, depth, direct, count, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let depth = if direct { Some(1) } else { depth };
+            let code = queries::rdeps(&loader, &node, depth, count, json, &mut out)?;
+            out.finish()?;
+            Ok(code)
+        })()),
+        Command::Coverage { input, mut roots, roots_file, fail_on_unreachable, json, output } => json_errors(json, (|| {
+            let protected: Vec<_> = input.input_paths.iter().chain(&roots_file).cloned().collect();
+            let mut out = Output::open(output.as_deref(), &protected)?;
+            if let Some(path) = &roots_file {
+                roots.extend(read_names(path)?.into_iter().map(|(_, name)| name));
+            }
+            if roots.is_empty() {
+                return Err(DepsError::Usage("give the entry points with --root or --roots-file".to_string()));
+            }
+            let loader = input.load(verbose)?;
+            let mut starts = roots.iter().map(|name| loader.find(name)).collect::<Result<Vec<_>, _>>()?;
+            starts.sort_unstable();
+            starts.dedup();
+            let items = &loader.items;
+            let Coverage { roots: coverage, unreachable } = coverage(items, loader.csr(), &starts);
+            let unreachable: Vec<_> = unreachable.iter().map(|&index| items[index].to_string()).collect();
+            let fraction = |count: usize| count as f64 / items.len() as f64;
+
+            if json {
+                let coverage: Vec<_> = coverage.iter()
+                    .map(|(start, covered)| {
+                        let names: Vec<_> = covered.iter().map(|&index| items[index].to_string()).collect();
+                        serde_json::json!({
+                            "root": items[*start].to_string(),
+                            "reachable": names,
+                            "count": covered.len(),
+                            "fraction": fraction(covered.len()),
+                        })
+                    })
+                    .collect();
+                writeln!(out, "{}", serde_json::json!({ "roots": coverage, "unreachable": unreachable }))?;
+            } else {
+                for (start, covered) in &coverage {
+                    writeln!(
+                        out, "{}: {} of {} nodes ({:.1}%)",
+                        items[*start].data, covered.len(), items.len(), 100.0 * fraction(covered.len()),
+                    )?;
+                }
+                writeln!(out, "Unreachable: {}", unreachable.len())?;
+                for name in &unreachable {
+                    writeln!(out, "  {}", name)?;
+                }
+            }
+            out.finish()?;
+
+            if fail_on_unreachable && !unreachable.is_empty() {
+                report::problem(format_args!("{} nodes are not reached from any entry point", unreachable.len()));
+                return Ok(ExitCode::FAILURE);
+            }
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::CheckAdd { input, from, to, pair, edges, quiet } => {
+            if quiet {
+                report::silence();
+            }
+            let mut loader = input.load(verbose)?;
+            let index_of = |loader: &mut Loader, name: &str| match loader.find(name) {
+                Ok(index) => index,
+                Err(_) => {
+                    report::info(format_args!("Note: {} is not in the graph yet", name));
+                    loader.index_of_name(name)
+                },
+            };
+
+            let mut creates_cycle = false;
+            for (from, to) in ends(from, to, pair).into_iter().chain(edges) {
+                let (a, b) = (index_of(&mut loader, &from), index_of(&mut loader, &to));
+                // The new edge closes a cycle when the dependency already
+                // leads back to the dependent.
+                if let Some(path) = find_path(&loader.items, b, a, None) {
+                    let cycle: Vec<_> = [a].into_iter().chain(path).map(|index| loader.items[index].to_string()).collect();
+                    report::problem(format_args!(
+                        "Adding {} -> {} creates a cycle: {}",
+                        from, to, cycle_chain(&cycle[..cycle.len() - 1]),
+                    ));
+                    creates_cycle = true;
+                }
+                loader.add_edge(a, b);
+            }
 
-            let items = from_graphviz(&graph);
+            if creates_cycle {
+                return Ok(ExitCode::FAILURE);
+            }
+            if !quiet {
+                println!("ok");
+            }
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::WhatIf { input, remove_nodes, remove_edges, json, output } => json_errors(json, (|| {
+            let graph_out = output.map(|path| Output::open(Some(&path), &input.input_paths)).transpose()?;
+            let loader = input.load(verbose)?;
+            let items = &loader.items;
+            let remove_nodes = remove_nodes.iter().map(|name| loader.find(name)).collect::<Result<Vec<_>, _>>()?;
+            let mut removed_edges = HashSet::new();
+            for (a, b) in &remove_edges {
+                let (a_index, b_index) = (loader.find(a)?, loader.find(b)?);
+                if !items[a_index].deps.contains(&b_index) {
+                    return Err(DepsError::Usage(format!("There is no edge {} -> {} to remove", a, b)));
+                }
+                removed_edges.insert((a_index, b_index));
+            }
+            let what_if = what_if(items, loader.csr(), &remove_nodes, &removed_edges);
+            let names = |indices: &[usize]| indices.iter().map(|&index| items[index].to_string()).collect::<Vec<_>>();
+            let (unreachable, orphans, no_longer_cyclic) =
+                (names(&what_if.unreachable), names(&what_if.orphans), names(&what_if.no_longer_cyclic));
+            let WhatIf { nodes, edges, cycles: cycle_counts, .. } = what_if;
 
-            if let Some((a, b)) = detect_cycle(&items) {
-                eprintln!("Circular dependency detected between {} and {}", items[a].data, items[b].data);
+            let mut out = Output::open(None, &[])?;
+            if json {
+                let pair = |(before, after): (usize, usize)| serde_json::json!({ "before": before, "after": after });
+                writeln!(out, "{}", serde_json::json!({
+                    "nodes": pair(nodes),
+                    "edges": pair(edges),
+                    "cycles": pair(cycle_counts),
+                    "unreachable": unreachable,
+                    "orphans": orphans,
+                    "no_longer_cyclic": no_longer_cyclic,
+                }))?;
             } else {
-                println!("The graph has no circular dependencies");
+                let list = |names: &[String]| if names.is_empty() { "none".to_string() } else { names.join(", ") };
+                writeln!(out, "Nodes: {} -> {}", nodes.0, nodes.1)?;
+                writeln!(out, "Edges: {} -> {}", edges.0, edges.1)?;
+                writeln!(out, "Cycles: {} -> {}", cycle_counts.0, cycle_counts.1)?;
+                writeln!(out, "No longer reached from a root: {}", list(&unreachable))?;
+                writeln!(out, "New orphans: {}", list(&orphans))?;
+                writeln!(out, "No longer on a cycle: {}", list(&no_longer_cyclic))?;
             }
+            out.finish()?;
 
-            ExitCode::SUCCESS
+            if let Some(mut graph_out) = graph_out {
+                let graph = formats::dot::to_graphviz(&induced_subgraph(&what_if.after, &what_if.keep), |_| Attrs::new(), |_, _| Attrs::new());
+                graph_out.write_all(formats::dot::print(&graph).as_bytes())?;
+                graph_out.finish()?;
+            }
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Impact { input, mut changed, changed_file, roots_only, include_changed, json, output } => json_errors(json, (|| {
+            let protected: Vec<_> = input.input_paths.iter().chain(&changed_file).cloned().collect();
+            let mut out = Output::open(output.as_deref(), &protected)?;
+            if let Some(path) = &changed_file {
+                changed.extend(read_names(path)?.into_iter().map(|(_, name)| name));
+            }
+            if changed.is_empty() {
+                return Err(DepsError::Usage("give the changed nodes with --changed or --changed-file".to_string()));
+            }
+            let loader = input.load(verbose)?;
+            let mut starts = changed.iter().map(|name| loader.find(name)).collect::<Result<Vec<_>, _>>()?;
+            starts.sort_unstable();
+            starts.dedup();
+            let items = &loader.items;
+            let impacted = impact(items, &starts, include_changed, roots_only);
+            let names: Vec<_> = impacted.iter().map(|&index| items[index].to_string()).collect();
+
+            if json {
+                let changed: Vec<_> = starts.iter().map(|&index| items[index].to_string()).collect();
+                writeln!(out, "{}", serde_json::json!({ "changed": changed, "impacted": names, "count": names.len() }))?;
+            } else {
+                write_names(&mut out, &names, false, false)?;
+            }
+            out.finish()?;
+            if !json {
+                report::info(format_args!("{} nodes impacted", names.len()));
+            }
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Why { input, from, to, pair, all, limit, output } => {
+            let (from, to) = ends(from, to, pair).expect("clap requires the ends");
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let code = queries::why(&loader, &from, &to, all, limit, &mut out)?;
+            out.finish()?;
+            Ok(code)
+        },
+        Command::Completions { shell } => {
+            print!("{}", completions::script(shell, &mut Args::command()));
+            Ok(ExitCode::SUCCESS)
         },
-        Command::Sort { input_path } => {
-            let contents = fs::read_to_string(input_path).unwrap();
-            let graph = graphviz_rust::parse(&contents).unwrap();
+        Command::CompleteNodes { input, prefix } => {
+            // Whatever goes wrong, the shell is better off without a message.
+            report::silence();
+            let Ok(loader) = input.load(false) else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            let mut names: Vec<_> = loader.items.iter().map(|item| &item.name).filter(|name| name.starts_with(&prefix)).collect();
+            names.sort();
+            let mut out = io::stdout().lock();
+            for name in names {
+                if writeln!(out, "{}", name).is_err() {
+                    break;
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Repl { input } => {
+            if input.input_paths.iter().any(|path| path == "-") {
+                return Err(DepsError::Usage("repl reads the queries from standard input, not the graph".to_string()));
+            }
+            let loader = input.load(verbose)?;
+            repl::run(&loader, threads)
+        },
+        Command::Stats { input, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let code = queries::stats(&loader, json, threads, &mut out)?;
+            out.finish()?;
+            Ok(code)
+        })()),
+        Command::Fanout { input, max_out, max_in, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = input.load(verbose)?.graph.items;
+            let metric = |fan: Fan| match fan {
+                Fan::Out => "dependencies",
+                Fan::In => "dependents",
+            };
 
-            let items = from_graphviz(&graph);
+            if max_out.is_none() && max_in.is_none() {
+                let (fan_out, fan_in) = degrees(&items);
+                let mut report = serde_json::Map::new();
+                for (fan, degrees) in [(Fan::Out, &fan_out), (Fan::In, &fan_in)] {
+                    let (metric, top) = (metric(fan), most_connected(&items, degrees, FANOUT_TOP));
+                    if json {
+                        let top: Vec<_> = top.iter()
+                            .map(|&index| serde_json::json!({ "node": items[index].to_string(), "count": degrees[index] }))
+                            .collect();
+                        report.insert(metric.to_string(), top.into());
+                    } else {
+                        writeln!(out, "Most {}:", metric)?;
+                        for index in top {
+                            writeln!(out, "  {} {}", degrees[index], items[index].data)?;
+                        }
+                    }
+                }
+                if json {
+                    writeln!(out, "{}", serde_json::Value::Object(report))?;
+                }
+                out.finish()?;
+                return Ok(ExitCode::SUCCESS);
+            }
 
-            if let Some((a, b)) = detect_cycle(&items) {
-                eprintln!("ERROR: Circular dependency detected between {} and {}", *items[a], *items[b]);
-                eprintln!("           Cannot sort a graph with cycles");
-                return ExitCode::FAILURE;
+            let violations = fan_limits(&items, max_out, max_in);
+            if json {
+                let violations: Vec<_> = violations.iter()
+                    .map(|excess| serde_json::json!({
+                        "node": items[excess.node].to_string(),
+                        "metric": metric(excess.fan),
+                        "count": excess.count,
+                        "max": excess.max,
+                        "excess": excess.over(),
+                    }))
+                    .collect();
+                writeln!(out, "{}", serde_json::Value::from(violations))?;
+            } else {
+                for excess in &violations {
+                    writeln!(
+                        out, "{}: {} {} (max {}, {} over)",
+                        items[excess.node].data, excess.count, metric(excess.fan), excess.max, excess.over(),
+                    )?;
+                }
             }
+            out.finish()?;
 
-            let sorted = sort_items(&items);
-            for item in sorted {
-                println!("{}", *item);
+            if violations.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                report::problem(format_args!("{} limits exceeded", violations.len()));
+                Ok(ExitCode::FAILURE)
             }
+        })()),
+        Command::Depth { input, from, node, max, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let start = from.or(node).map(|name| loader.find(&name)).transpose()?;
+            let items = &loader.items;
+            ensure_acyclic(items, loader.csr())?;
 
-            ExitCode::SUCCESS
+            let (order, _) = sort_order(items, false);
+            let chains = longest_chains(items, &order);
+            // The first of the items with the longest chain, in input order.
+            let start = start.or_else(|| (0..items.len()).rev().max_by_key(|&index| chains[index].0));
+            if let Some(start) = start {
+                let mut chain = vec![start];
+                while let Some(next) = chains[*chain.last().unwrap()].1 {
+                    chain.push(next);
+                }
+                let names: Vec<_> = chain.iter().map(|&index| items[index].to_string()).collect();
+                writeln!(out, "{} (length {})", names.join(" -> "), chains[start].0)?;
+            }
+            out.finish()?;
+
+            let depth = start.map_or(0, |start| chains[start].0);
+            if let Some(max) = max.filter(|&max| depth > max) {
+                report::problem(format_args!("The longest chain has length {}, more than the maximum of {}", depth, max));
+                return Ok(ExitCode::FAILURE);
+            }
+            Ok(ExitCode::SUCCESS)
         },
+        Command::CriticalPath { input, attr, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            ensure_acyclic(&loader.items, loader.csr())?;
+            let items = loader.graph.items;
+            let weights = costs(&items, &attr)?;
+
+            let (order, _) = sort_order(&items, false);
+            if let Some(CriticalPath { path, total, slack_free }) = critical_path(&items, &order, &weights) {
+                let names: Vec<_> = path.iter().map(|&index| items[index].to_string()).collect();
+                let slack_free: Vec<_> = slack_free.iter().map(|&index| items[index].to_string()).collect();
+                writeln!(out, "{} (total {})", names.join(" -> "), total)?;
+                writeln!(out, "Slack-free: {}", slack_free.join(", "))?;
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Schedule { input, attr, workers, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            ensure_acyclic(&loader.items, loader.csr())?;
+            let items = loader.graph.items;
+            let costs = costs(&items, &attr)?;
+            let (order, _) = sort_order(&items, false);
+            let workers = workers as usize;
+
+            let (mut slots, critical) = simulate_schedule(&items, &order, &costs, workers);
+            let makespan = slots.iter().map(|slot| slot.end).fold(0.0, f64::max);
+            let mut busy = vec![0.0; workers];
+            for slot in &slots {
+                busy[slot.worker] += slot.end - slot.start;
+            }
+            let utilization = |busy: f64| if makespan > 0.0 { busy / makespan } else { 0.0 };
+            let gap = makespan - critical;
+            slots.sort_by(|a, b| a.start.total_cmp(&b.start).then(a.worker.cmp(&b.worker)));
+
+            if json {
+                let workers: Vec<_> = busy.iter().enumerate()
+                    .map(|(worker, &busy)| serde_json::json!({
+                        "worker": worker + 1,
+                        "busy": busy,
+                        "utilization": utilization(busy),
+                    }))
+                    .collect();
+                let timeline: Vec<_> = slots.iter()
+                    .map(|slot| serde_json::json!({
+                        "node": items[slot.index].to_string(),
+                        "worker": slot.worker + 1,
+                        "start": slot.start,
+                        "end": slot.end,
+                    }))
+                    .collect();
+                writeln!(out, "{}", serde_json::json!({
+                    "makespan": makespan,
+                    "critical_path": critical,
+                    "gap": gap,
+                    "workers": workers,
+                    "timeline": timeline,
+                }))?;
+            } else {
+                writeln!(out, "Makespan: {}", makespan)?;
+                if critical > 0.0 {
+                    writeln!(out, "Critical path: {}, {} less than the makespan ({:.1}%)", critical, gap, 100.0 * gap / critical)?;
+                } else {
+                    writeln!(out, "Critical path: {}", critical)?;
+                }
+                for (worker, &busy) in busy.iter().enumerate() {
+                    writeln!(out, "Worker {}: busy {} ({:.1}%)", worker + 1, busy, 100.0 * utilization(busy))?;
+                }
+                writeln!(out, "Timeline:")?;
+                for slot in &slots {
+                    writeln!(out, "  {}-{} worker {}: {}", slot.start, slot.end, slot.worker + 1, *items[slot.index])?;
+                }
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Reduce { input, list, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            ensure_acyclic(&loader.items, loader.csr())?;
+            let mut items = loader.graph.items;
+
+            let (order, _) = sort_order(&items, false);
+            let redundant = redundant_edges(&items, &order);
+            if list {
+                for &(a, b) in &redundant {
+                    writeln!(out, "{} -> {}", *items[a], *items[b])?;
+                }
+            } else {
+                for &(a, b) in &redundant {
+                    items[a].deps.retain(|&dep| dep != b);
+                }
+                let graph = formats::dot::to_graphviz(&items, |_| Attrs::new(), |_, _| Attrs::new());
+                out.write_all(formats::dot::print(&graph).as_bytes())?;
+            }
+
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::SuggestBreaks { input, apply, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let (cycles, truncated) = elementary_cycles(loader.csr(), Some(CYCLE_LIMIT));
+            let mut items = loader.graph.items;
+            if truncated {
+                report::warning(format_args!("Only the first {} cycles were counted", CYCLE_LIMIT));
+            }
+            let mut on_cycles: HashMap<_, usize> = HashMap::new();
+            for cycle in &cycles {
+                for (&a, &b) in cycle.iter().zip(cycle.iter().cycle().skip(1)) {
+                    *on_cycles.entry((a, b)).or_default() += 1;
+                }
+            }
+            let mut cut = feedback_edges(&items);
+            let count = |edge: &(usize, usize)| on_cycles.get(edge).copied().unwrap_or(0);
+            cut.sort_by(|x, y| {
+                count(y).cmp(&count(x)).then_with(|| (&items[x.0].name, &items[x.1].name).cmp(&(&items[y.0].name, &items[y.1].name)))
+            });
+
+            if apply {
+                for &(a, b) in &cut {
+                    let (a, b) = (formats::dot::quote(&items[a].name), formats::dot::quote(&items[b].name));
+                    writeln!(out, "// removed to break cycles: {} -> {}", a, b)?;
+                }
+                for &(a, b) in &cut {
+                    items[a].deps.retain(|&dep| dep != b);
+                }
+                let graph = formats::dot::to_graphviz(&items, |_| Attrs::new(), |_, _| Attrs::new());
+                out.write_all(formats::dot::print(&graph).as_bytes())?;
+            } else {
+                for edge @ &(a, b) in &cut {
+                    writeln!(out, "{} -> {} (on {} cycles)", *items[a], *items[b], count(edge))?;
+                }
+            }
+            out.finish()?;
+            if cut.is_empty() {
+                report::info("The graph has no circular dependencies");
+            }
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Redundant { input, fail, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let items = &loader.items;
+            let names = |path: &[usize]| path.iter().map(|&index| items[index].to_string()).collect::<Vec<_>>();
+
+            let mut redundant = 0;
+            for (a, b, witness) in implied_edges(items, loader.csr()) {
+                match witness {
+                    Some(path) => {
+                        writeln!(out, "{} -> {} is implied by {}", *items[a], *items[b], names(&path).join(" -> "))?;
+                        redundant += 1;
+                    },
+                    None => writeln!(out, "{} -> {} (in cycle, skipped)", *items[a], *items[b])?,
+                }
+            }
+            out.finish()?;
+
+            if fail && redundant > 0 {
+                report::problem(format_args!("{} redundant edges", redundant));
+                return Ok(ExitCode::FAILURE);
+            }
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Closure { input, from, dot, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let from = from.map(|name| loader.find(&name)).transpose()?;
+            let items = &loader.items;
+
+            // Pairs are written as they are found so a large closure never
+            // has to be held in memory.
+            let sources: Vec<_> = match from {
+                Some(index) => vec![index],
+                None => (0..items.len()).collect(),
+            };
+            if dot {
+                writeln!(out, "digraph {{")?;
+                for &a in &sources {
+                    writeln!(out, "    {}", formats::dot::quote(&items[a].name))?;
+                }
+            }
+            let mut write_pairs = |a: usize, reached: &[usize]| -> Result<(), DepsError> {
+                for &b in reached {
+                    if dot {
+                        writeln!(out, "    {} -> {}", formats::dot::quote(&items[a].name), formats::dot::quote(&items[b].name))?;
+                    } else {
+                        writeln!(out, "{} -> {}", *items[a], *items[b])?;
+                    }
+                }
+                Ok(())
+            };
+            // A single node, or a graph too large for the closure, is
+            // searched from each source in turn.
+            if from.is_some() || !Closure::fits(items.len()) {
+                each_reachable(loader.csr(), &sources, write_pairs)?;
+            } else {
+                let closure = Closure::new(loader.csr());
+                for &a in &sources {
+                    write_pairs(a, &closure.reachable_from(a))?;
+                }
+            }
+            if dot {
+                writeln!(out, "}}")?;
+            }
+
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Extract { input, seeds, nodes, reverse, both, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let seeds = seeds.iter().chain(&nodes).map(|name| loader.find(name)).collect::<Result<Vec<_>, _>>()?;
+            let items = &loader.items;
+
+            let mut keep = vec![false; items.len()];
+            for &seed in &seeds {
+                keep[seed] = true;
+            }
+            if !reverse {
+                for index in reachable(loader.csr(), &seeds, None) {
+                    keep[index] = true;
+                }
+            }
+            if reverse || both {
+                for index in reachable(&Csr::reverse(items), &seeds, None) {
+                    keep[index] = true;
+                }
+            }
+
+            let extracted = induced_subgraph(items, &keep);
+            let graph = formats::dot::to_graphviz(&extracted, |_| Attrs::new(), |_, _| Attrs::new());
+            out.write_all(formats::dot::print(&graph).as_bytes())?;
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Diff { input, edges_only, nodes_only, json, output } => json_errors(json, (|| {
+            let [old_path, new_path] = input.input_paths.as_slice() else {
+                return Err(DepsError::Usage("diff takes exactly two graphs, the old one and the new one".to_string()));
+            };
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let old = input.load_paths(std::slice::from_ref(old_path), verbose)?;
+            let new = input.load_paths(std::slice::from_ref(new_path), verbose)?;
+
+            let Diff { added_nodes, removed_nodes, added_edges, removed_edges, changed } = diff(&old.items, &new.items);
+            let show_nodes = !edges_only;
+            let show_edges = !nodes_only;
+
+            if json {
+                let mut report = serde_json::Map::new();
+                if show_nodes {
+                    report.insert("added_nodes".to_string(), serde_json::json!(added_nodes));
+                    report.insert("removed_nodes".to_string(), serde_json::json!(removed_nodes));
+                }
+                if show_edges {
+                    report.insert("added_edges".to_string(), serde_json::json!(added_edges));
+                    report.insert("removed_edges".to_string(), serde_json::json!(removed_edges));
+                    let changed: serde_json::Map<_, _> = changed.iter()
+                        .map(|changed| (changed.node.clone(), serde_json::json!({ "added": changed.added, "removed": changed.removed })))
+                        .collect();
+                    report.insert("changed".to_string(), serde_json::Value::Object(changed));
+                }
+                writeln!(out, "{}", serde_json::Value::Object(report))?;
+            } else {
+                let colored = out.colored();
+                let added = |text: String| report::paint(Style::Green, text, colored);
+                let removed = |text: String| report::paint(Style::Red, text, colored);
+                if show_nodes {
+                    for name in &added_nodes {
+                        writeln!(out, "{}", added(format!("+ {}", name)))?;
+                    }
+                    for name in &removed_nodes {
+                        writeln!(out, "{}", removed(format!("- {}", name)))?;
+                    }
+                }
+                if show_edges {
+                    for (a, b) in &added_edges {
+                        writeln!(out, "{}", added(format!("+ {} -> {}", a, b)))?;
+                    }
+                    for (a, b) in &removed_edges {
+                        writeln!(out, "{}", removed(format!("- {} -> {}", a, b)))?;
+                    }
+                    for Changed { node, added, removed } in &changed {
+                        let changes: Vec<_> = added.iter().map(|dep| format!("+{}", dep))
+                            .chain(removed.iter().map(|dep| format!("-{}", dep)))
+                            .collect();
+                        writeln!(out, "~ {}: {}", node, changes.join(" "))?;
+                    }
+                }
+            }
+            out.finish()?;
+
+            let differs = (show_nodes && !(added_nodes.is_empty() && removed_nodes.is_empty()))
+                || (show_edges && !(added_edges.is_empty() && removed_edges.is_empty()));
+            Ok(if differs { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+        })()),
+        Command::Merge { input, fail_on_new_cycles, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let merged = input.load(verbose)?;
+            let items = &merged.items;
+
+            // The edges of each input, by their indices in the merged graph.
+            let mut input_edges = Vec::new();
+            let mut node_inputs = vec![0; items.len()];
+            for path in &input.input_paths {
+                let loader = input.load_paths(std::slice::from_ref(path), verbose)?;
+                let edges: usize = loader.items.iter().map(|item| item.deps.len()).sum();
+                report::info(format_args!("{}: {} nodes, {} edges", path, loader.items.len(), edges));
+
+                let index = |local: usize| merged.indices[&loader.items[local].name];
+                let mut edges = HashSet::new();
+                for (a, item) in loader.items.iter().enumerate() {
+                    node_inputs[index(a)] += 1;
+                    edges.extend(item.deps.iter().map(|&b| (index(a), index(b))));
+                }
+                input_edges.push(edges);
+            }
+            let edges: Vec<_> = items.iter().enumerate()
+                .flat_map(|(a, item)| item.deps.iter().map(move |&b| (a, b)))
+                .collect();
+            let shared_nodes = node_inputs.iter().filter(|&&count| count > 1).count();
+            let shared_edges = edges.iter()
+                .filter(|edge| input_edges.iter().filter(|input| input.contains(edge)).count() > 1)
+                .count();
+            report::info(format_args!("Shared: {} nodes, {} edges", shared_nodes, shared_edges));
+            report::info(format_args!("Merged: {} nodes, {} edges", items.len(), edges.len()));
+
+            let graph = formats::dot::to_graphviz(items, |_| Attrs::new(), |_, _| Attrs::new());
+            out.write_all(formats::dot::print(&graph).as_bytes())?;
+            out.finish()?;
+
+            if fail_on_new_cycles {
+                // A cycle is new when no input has all of its edges.
+                let (new_cycle, truncated) = new_cycle(merged.csr(), &input_edges, CYCLE_LIMIT);
+                if let Some(cycle) = new_cycle {
+                    let names: Vec<_> = cycle.iter().map(|&index| items[index].to_string()).collect();
+                    report::problem(format_args!("The merge creates a new cycle: {}", cycle_chain(&names)));
+                    return Ok(ExitCode::FAILURE);
+                }
+                if truncated {
+                    report::warning(format_args!("Only the first {} cycles were checked", CYCLE_LIMIT));
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Orphans { input, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = input.load(verbose)?.graph.items;
+            let names: Vec<_> = orphans(&items).iter().map(|&index| items[index].to_string()).collect();
+            write_names(&mut out, &names, false, json)?;
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Components { input, expect, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = input.load(verbose)?.graph.items;
+            let components = weak_components(&items);
+            let names = |members: &[usize]| members.iter().map(|&index| items[index].to_string()).collect::<Vec<_>>();
+
+            if json {
+                let components: Vec<_> = components.iter()
+                    .map(|members| serde_json::json!({ "size": members.len(), "members": names(members) }))
+                    .collect();
+                writeln!(out, "{}", serde_json::json!(components))?;
+            } else {
+                for (number, members) in components.iter().enumerate() {
+                    let mut shown = names(members);
+                    // The components past the expected ones are likely typos,
+                    // so they are shown in full.
+                    if expect.is_none_or(|expect| number < expect) && shown.len() > COMPONENT_SAMPLE {
+                        let more = shown.len() - COMPONENT_SAMPLE;
+                        shown.truncate(COMPONENT_SAMPLE);
+                        shown.push(format!("and {} more", more));
+                    }
+                    writeln!(out, "{}: {}", members.len(), shown.join(", "))?;
+                }
+            }
+            out.finish()?;
+
+            if let Some(expect) = expect.filter(|&expect| expect != components.len()) {
+                report::problem(format_args!("Expected {} components, found {}", expect, components.len()));
+                return Ok(ExitCode::FAILURE);
+            }
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Rank { input, by, top, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let items = &loader.items;
+            let scores = match by {
+                RankBy::In => degrees(items).1,
+                RankBy::Out => degrees(items).0,
+                RankBy::Reach | RankBy::Rdeps if !Closure::fits(items.len()) => {
+                    return Err(DepsError::Unsupported(format!(
+                        "Rankings by {} of graphs of {} nodes, whose closure would take over 1 GiB,",
+                        if by == RankBy::Reach { "reach" } else { "rdeps" }, items.len(),
+                    )));
+                },
+                RankBy::Reach => Closure::new(loader.csr()).counts().0,
+                RankBy::Rdeps => Closure::new(loader.csr()).counts().1,
+            };
+            let ranked = ranked(items, &scores, top);
+
+            if json {
+                let ranked: Vec<_> = ranked.iter()
+                    .enumerate()
+                    .map(|(rank, &index)| serde_json::json!({
+                        "rank": rank + 1,
+                        "node": items[index].to_string(),
+                        "score": scores[index],
+                    }))
+                    .collect();
+                writeln!(out, "{}", serde_json::Value::from(ranked))?;
+            } else {
+                let rank_width = ranked.len().to_string().len();
+                let score_width = ranked.first().map_or(1, |&index| scores[index].to_string().len());
+                for (rank, &index) in ranked.iter().enumerate() {
+                    writeln!(out, "{:>rank_width$}  {:>score_width$}  {}", rank + 1, scores[index], items[index].data)?;
+                }
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Duplicates { input, min_deps, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = input.load(verbose)?.graph.items;
+            let groups = same_deps(&items, min_deps);
+
+            if json {
+                let groups: Vec<_> = groups.iter()
+                    .map(|SameDeps { members, deps }| serde_json::json!({ "members": members, "deps": deps }))
+                    .collect();
+                writeln!(out, "{}", serde_json::Value::from(groups))?;
+            } else {
+                for SameDeps { members, deps } in &groups {
+                    let deps = if deps.is_empty() { "(nothing)".to_string() } else { deps.join(", ") };
+                    writeln!(out, "{} -> {}", members.join(", "), deps)?;
+                }
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Bottlenecks { input, json, output } => json_errors(json, (|| {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = input.load(verbose)?.graph.items;
+            let Bottlenecks { mut points, bridges } = bottlenecks(&items);
+            points.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| items[a.0].name.cmp(&items[b.0].name)));
+            // Show each bridge the way its edges go, looked up in a single
+            // pass over the edges as a node can have any number of them.
+            let mut ways: HashMap<_, _> = bridges.iter().map(|&(a, b, _)| ((a, b), (false, false))).collect();
+            for (a, item) in items.iter().enumerate() {
+                for &b in &item.deps {
+                    if let Some(way) = ways.get_mut(&(a, b)) {
+                        way.0 = true;
+                    } else if let Some(way) = ways.get_mut(&(b, a)) {
+                        way.1 = true;
+                    }
+                }
+            }
+            let mut bridges: Vec<_> = bridges.into_iter()
+                .map(|(a, b, separated)| {
+                    let (forward, backward) = ways[&(a, b)];
+                    let (a, b) = if forward { (a, b) } else { (b, a) };
+                    let arrow = if forward && backward { "<->" } else { "->" };
+                    (a, b, arrow, separated)
+                })
+                .collect();
+            bridges.sort_by(|x, y| {
+                y.3.cmp(&x.3).then_with(|| (&items[x.0].name, &items[x.1].name).cmp(&(&items[y.0].name, &items[y.1].name)))
+            });
+
+            if json {
+                let points: Vec<_> = points.iter()
+                    .map(|&(index, separated)| serde_json::json!({ "node": items[index].to_string(), "separated": separated }))
+                    .collect();
+                let bridges: Vec<_> = bridges.iter()
+                    .map(|&(a, b, arrow, separated)| serde_json::json!({
+                        "from": items[a].to_string(),
+                        "to": items[b].to_string(),
+                        "both_ways": arrow == "<->",
+                        "separated": separated,
+                    }))
+                    .collect();
+                writeln!(out, "{}", serde_json::json!({ "articulation_points": points, "bridges": bridges }))?;
+            } else {
+                writeln!(out, "Articulation points:")?;
+                for &(index, separated) in &points {
+                    writeln!(out, "  {} (cuts off {})", items[index].data, separated)?;
+                }
+                writeln!(out, "Bridges:")?;
+                for &(a, b, arrow, separated) in &bridges {
+                    writeln!(out, "  {} {} {} (cuts off {})", items[a].data, arrow, items[b].data, separated)?;
+                }
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        })()),
+        Command::Collapse { input, by_prefix, depth, by_regex, by_cluster, keep_self, output } => {
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let items = input.load(verbose)?.graph.items;
+            let group = |node: &Node| -> String {
+                let name = node.name.as_str();
+                if let Some(sep) = &by_prefix {
+                    name.split(sep.as_str()).take(depth.max(1)).collect::<Vec<_>>().join(sep)
+                } else if by_cluster {
+                    node.cluster.as_deref().unwrap_or(name).to_string()
+                } else {
+                    by_regex.as_ref()
+                        .and_then(|regex| regex.captures(name))
+                        .and_then(|captures| captures.get(1))
+                        .map_or(name, |key| key.as_str())
+                        .to_string()
+                }
+            };
+
+            let collapsed = collapse(&items, group, keep_self);
+            let graph = formats::dot::to_graphviz(&collapsed, |_| Attrs::new(), |_, _| Attrs::new());
+            out.write_all(formats::dot::print(&graph).as_bytes())?;
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Rename { input, map, dry_run, output } => {
+            let protected: Vec<_> = input.input_paths.iter().chain([&map]).cloned().collect();
+            let mut out = Output::open(output.as_deref(), &protected)?;
+            let renames = read_renames(&map)?;
+            let loader = input.load(verbose)?;
+            let items = &loader.items;
+            for (old, _) in &renames {
+                if !loader.indices.contains_key(old) {
+                    report::warning(format_args!("There is no node named {} to rename", old));
+                }
+            }
+
+            let renames: HashMap<_, _> = renames.into_iter().collect();
+            let new_name = |index: usize| renames.get(&items[index].name).unwrap_or(&items[index].name);
+            let renamed = rename(items, &renames);
+            for &(a, b) in &renamed.merged_loops {
+                report::warning(format_args!(
+                    "Merging {} and {} into {} makes it depend on itself",
+                    items[a].name, items[b].name, new_name(a),
+                ));
+            }
+
+            if dry_run {
+                let mut merged: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+                for (index, item) in items.iter().enumerate() {
+                    merged.entry(new_name(index)).or_default().push(&item.name);
+                }
+                for (new, olds) in merged {
+                    match olds.as_slice() {
+                        [old] if *old == new => {},
+                        [old] => writeln!(out, "{} => {}", old, new)?,
+                        olds => writeln!(out, "{} => {} (merged)", olds.join(", "), new)?,
+                    }
+                }
+            } else {
+                let graph = formats::dot::to_graphviz(&renamed.items, |_| Attrs::new(), |_, _| Attrs::new());
+                out.write_all(formats::dot::print(&graph).as_bytes())?;
+            }
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Validate { input, rules, output } => {
+            let protected: Vec<_> = input.input_paths.iter().chain([&rules]).cloned().collect();
+            let mut out = Output::open(output.as_deref(), &protected)?;
+            let (name, contents) = read_input(&rules)?;
+            let rules = rules::load(&name, &contents)?;
+            let items = input.load(verbose)?.graph.items;
+
+            let violations = rules::check(&items, &rules);
+            for violation in &violations {
+                writeln!(out, "{}", violation)?;
+            }
+            out.finish()?;
+            if violations.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                report::problem(format_args!("{} rule violations", violations.len()));
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Command::Verify { input, order, missing, extra, output } => {
+            let protected: Vec<_> = input.input_paths.iter().chain([&order]).cloned().collect();
+            let mut out = Output::open(output.as_deref(), &protected)?;
+            let names = read_names(&order)?;
+            let loader = input.load(verbose)?;
+            let items = &loader.items;
+
+            let mut line = vec![None; items.len()];
+            let mut extras = Vec::new();
+            for (number, name) in &names {
+                match loader.indices.get(name) {
+                    Some(&index) => match line[index] {
+                        Some(first) => return Err(DepsError::Invalid {
+                            path: order.clone(),
+                            message: format!("{} is listed twice, on lines {} and {}", name, first, number),
+                        }),
+                        None => line[index] = Some(*number),
+                    },
+                    None => extras.push((*number, name)),
+                }
+            }
+
+            let violations = order_violations(items, &line);
+            for &(index, dep) in &violations {
+                let (at, dep_at) = (line[index].unwrap_or_default(), line[dep].unwrap_or_default());
+                if dep == index {
+                    writeln!(out, "{} (line {}) depends on itself", items[index].name, at)?;
+                } else {
+                    writeln!(out, "{} (line {}) depends on {} (line {})", items[index].name, at, items[dep].name, dep_at)?;
+                }
+            }
+
+            let mut failed = !violations.is_empty();
+            let mut note = |severity: Severity, message: String| -> Result<(), DepsError> {
+                match severity {
+                    Severity::Error => {
+                        writeln!(out, "{}", message)?;
+                        failed = true;
+                    },
+                    Severity::Warn => report::warning(message),
+                    Severity::Ignore => {},
+                }
+                Ok(())
+            };
+            for index in (0..items.len()).filter(|&index| line[index].is_none()) {
+                note(missing, format!("{} is not in {}", items[index].name, order))?;
+            }
+            for (number, name) in extras {
+                note(extra, format!("{} (line {}) is not in the graph", name, number))?;
+            }
+            out.finish()?;
+
+            if !violations.is_empty() {
+                report::problem(format_args!("{} dependencies come after their dependents", violations.len()));
+            }
+            Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+        },
+        Command::LayersCheck { input, layers, allow_same_layer, unassigned, output } => {
+            let protected: Vec<_> = input.input_paths.iter().chain([&layers]).cloned().collect();
+            let mut out = Output::open(output.as_deref(), &protected)?;
+            let (name, contents) = read_input(&layers)?;
+            let layers = rules::Layers::load(&name, &contents)?;
+            let items = input.load(verbose)?.graph.items;
+            let layer: Vec<_> = items.iter().map(|item| layers.layer_of(&item.name)).collect();
+
+            let violations = layer_violations(&items, &layer, allow_same_layer);
+            for &(a, b) in &violations {
+                let (from, to) = (layer[a].unwrap_or_default(), layer[b].unwrap_or_default());
+                writeln!(out, "{} -> {} (layer {} -> layer {})", items[a].name, items[b].name, from, to)?;
+            }
+            let missing: Vec<_> = (0..items.len()).filter(|&index| layer[index].is_none()).collect();
+            for &index in &missing {
+                match unassigned {
+                    Severity::Error => writeln!(out, "{} is not in any layer", items[index].name)?,
+                    Severity::Warn => report::warning(format_args!("{} is not in any layer", items[index].name)),
+                    Severity::Ignore => {},
+                }
+            }
+            out.finish()?;
+
+            let missing = if unassigned == Severity::Error { missing.len() } else { 0 };
+            if violations.is_empty() && missing == 0 {
+                return Ok(ExitCode::SUCCESS);
+            }
+            if !violations.is_empty() {
+                report::problem(format_args!("{} edges break the layering", violations.len()));
+            }
+            if missing > 0 {
+                report::problem(format_args!("{} nodes are not in any layer", missing));
+            }
+            Ok(ExitCode::FAILURE)
+        },
+        Command::Export { input, to, direction, attr, include_ignored, output } => {
+            if matches!(to, ExportFormat::Mermaid | ExportFormat::Json) && !attr.is_empty() {
+                let to = to.to_possible_value().expect("no format is skipped");
+                return Err(DepsError::Usage(format!("--attr can't be used with --to {}", to.get_name())));
+            }
+            let mut out = Output::open(output.as_deref(), &input.input_paths)?;
+            let loader = input.load(verbose)?;
+            let levels = if attr.contains(&NodeAttr::Level) {
+                ensure_acyclic(&loader.items, loader.csr())?;
+                Some(sort_order(&loader.items, false).1)
+            } else {
+                None
+            };
+            let mut items = loader.graph.items;
+            let mut ignored = HashSet::new();
+            if include_ignored {
+                for &(a, b) in &loader.ignored {
+                    items[a].deps.push(b);
+                    ignored.insert((a, b));
+                }
+            }
+            let text = match to {
+                ExportFormat::Dot => {
+                    let node_attrs = |index: usize| match &levels {
+                        Some(levels) => Attrs::from_iter([("level".to_string(), levels[index].to_string())]),
+                        None => Attrs::new(),
+                    };
+                    let edge_attrs = |a: usize, b: usize| {
+                        let mut attrs = Attrs::new();
+                        if ignored.contains(&(a, b)) {
+                            attrs.set("style".to_string(), "dashed".to_string());
+                            attrs.set("color".to_string(), "gray".to_string());
+                        }
+                        attrs
+                    };
+                    formats::dot::print(&formats::dot::to_graphviz(&items, node_attrs, edge_attrs))
+                }
+                ExportFormat::Mermaid => formats::mermaid::to_mermaid(&items, direction, &ignored),
+                ExportFormat::Graphml => formats::graphml::to_graphml(&items, levels.as_deref(), &ignored),
+                ExportFormat::Json => format!("{}\n", serde_json::json!(NamedGraph::from_items(&items))),
+            };
+            out.write_all(text.as_bytes())?;
+            out.finish()?;
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+impl Command {
+    /// The inputs to watch and how, when the command was given `--watch`.
+    fn watched(&self) -> Option<(&[String], &WatchArgs)> {
+        let (input, watch) = match self {
+            Command::Check { input, watch, .. } | Command::Sort { input, watch, .. } => (input, watch),
+            _ => return None,
+        };
+        watch.watch.then_some((&input.input_paths, watch))
+    }
+
+    /// Whether the command was asked for JSON.
+    fn json(&self) -> bool {
+        match self {
+            Command::Check { json, .. }
+            | Command::Sort { json, .. }
+            | Command::Scc { json, .. }
+            | Command::Nodes { json, .. }
+            | Command::Edges { json, .. }
+            | Command::Show { json, .. }
+            | Command::Rdeps { json, .. }
+            | Command::Coverage { json, .. }
+            | Command::WhatIf { json, .. }
+            | Command::Impact { json, .. }
+            | Command::Stats { json, .. }
+            | Command::Fanout { json, .. }
+            | Command::Schedule { json, .. }
+            | Command::Diff { json, .. }
+            | Command::Orphans { json, .. }
+            | Command::Components { json, .. }
+            | Command::Rank { json, .. }
+            | Command::Duplicates { json, .. }
+            | Command::Bottlenecks { json, .. } => *json,
+            Command::Roots { list } | Command::Leaves { list } => list.json,
+            _ => false,
+        }
+    }
+}
+
+/// Runs the command, and with `--timings` prints the breakdown of the run
+/// on stderr after it. When the output is JSON the breakdown goes under a
+/// `timings` key of the document on stdout instead, next to what a list
+/// of results is put under `result` for.
+fn run_timed(args: Args) -> ExitCode {
+    if !args.timings {
+        return exit_code(run(args));
+    }
+    timings::enable();
+    let json = args.command.json();
+    if json {
+        output::hold_stdout();
+    }
+    let code = exit_code(run(args));
+    let breakdown = timings::take();
+    if !json {
+        eprintln!("{}", breakdown);
+        return code;
+    }
+
+    let held = output::release_stdout();
+    let document = if held.iter().all(u8::is_ascii_whitespace) {
+        // The document went to a file, stdout only has the breakdown.
+        Some(serde_json::Map::new())
+    } else {
+        match serde_json::from_slice(&held) {
+            Ok(serde_json::Value::Object(document)) => Some(document),
+            Ok(result) => Some(serde_json::Map::from_iter([("result".to_string(), result)])),
+            Err(_) => None,
+        }
+    };
+    let mut stdout = io::stdout().lock();
+    let Some(mut document) = document else {
+        let _ = stdout.write_all(&held);
+        eprintln!("{}", breakdown);
+        return code;
+    };
+    let phases: serde_json::Map<_, _> = breakdown.phases.iter()
+        .map(|&(name, time)| (name.to_string(), serde_json::json!(time.as_secs_f64())))
+        .collect();
+    document.insert("timings".to_string(), serde_json::json!({
+        "seconds": phases,
+        "total_seconds": breakdown.total.as_secs_f64(),
+        "peak": {
+            "nodes": breakdown.peak.nodes,
+            "edges": breakdown.peak.edges,
+            "index_entries": breakdown.peak.index_entries,
+        },
+    }));
+    let _ = writeln!(stdout, "{}", serde_json::Value::Object(document));
+    code
+}
+
+/// Reports a failed command, turning the result into the exit code.
+fn exit_code(result: Result<ExitCode, DepsError>) -> ExitCode {
+    result.unwrap_or_else(|err| {
+        report::error(&err);
+        error_code(&err)
+    })
+}
+
+/// Usage and IO problems exit with 2, input we cannot understand with 3,
+/// and a graph that fails the analysis with 1.
+fn error_code(err: &DepsError) -> ExitCode {
+    match err {
+        DepsError::Io { .. }
+        | DepsError::Write { .. }
+        | DepsError::Output(_)
+        | DepsError::Usage(_)
+        | DepsError::EmptyInput { .. }
+        | DepsError::UnknownNode { .. } => ExitCode::from(2),
+        DepsError::Parse { .. }
+        | DepsError::Invalid { .. }
+        | DepsError::Unsupported(_)
+        | DepsError::UndirectedGraph
+        | DepsError::Attribute { .. }
+        | DepsError::InvalidDependency { .. } => ExitCode::from(3),
+        DepsError::CycleDetected { .. }
+        | DepsError::ConstraintCycle { .. }
+        | DepsError::Unsorted { .. }
+        | DepsError::SelfLoops(_)
+        | DepsError::SelfLoop { .. } => ExitCode::from(1),
+    }
+}
+
+/// Parses the command line again with the defaults of the config file
+/// added, warning about what the file has that no command reads.
+fn configure(args: Args, arguments: &[OsString]) -> Result<Args, DepsError> {
+    if args.no_config {
+        return Ok(args);
+    }
+    let Some(path) = args.config.as_ref().map(Into::into).or_else(config::discover) else {
+        return Ok(args);
+    };
+    let (arguments, warnings) = config::with_defaults(&path, arguments, Args::command())?;
+    for warning in warnings {
+        report::warning(warning);
+    }
+    let args = Args::parse_from(arguments);
+    report::init(args.color);
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    let arguments: Vec<OsString> = env::args_os().collect();
+    let args = Args::parse_from(&arguments);
+    report::init(args.color);
+    let args = match configure(args, &arguments) {
+        Ok(args) => args,
+        Err(err) => return exit_code(Err(err)),
+    };
+    report::init_log(args.verbose);
+    report::init_progress(args.progress);
+
+    if let Some((paths, options)) = args.command.watched() {
+        if paths.iter().any(|path| path == "-") {
+            return exit_code(Err(DepsError::Usage("--watch needs files to watch, not standard input".to_string())));
+        }
+        let interval = Duration::from_millis(options.interval);
+        return watch::watch(paths, interval, || run_timed(args.clone()));
     }
+    run_timed(args)
 }
@@ -0,0 +1,202 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tempfile::NamedTempFile;
+use crate::error::DepsError;
+use crate::timings::Phase;
+
+/// Where a command writes its results. A file is written under a temporary
+/// name next to it and only takes the place of `path` in `finish`, so a
+/// command that fails halfway leaves any existing file untouched.
+pub enum Output {
+    Stdout(io::Stdout),
+    /// Stdout while it is held back, see `hold_stdout`.
+    Held,
+    File { file: NamedTempFile, path: PathBuf },
+}
+
+/// What was written to stdout since `hold_stdout`, while it is held back.
+static HELD: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+fn held() -> MutexGuard<'static, Option<Vec<u8>>> {
+    HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Keeps what is written to stdout from now on instead of printing it, for
+/// `release_stdout` to hand back.
+pub fn hold_stdout() {
+    *held() = Some(Vec::new());
+}
+
+/// Stops holding stdout back, returning what was written to it.
+pub fn release_stdout() -> Vec<u8> {
+    held().take().unwrap_or_default()
+}
+
+impl Output {
+    /// Opens `path`, or stdout when there is none. Refuses to write over
+    /// one of the `inputs`.
+    pub fn open(path: Option<&str>, inputs: &[String]) -> Result<Self, DepsError> {
+        let Some(path) = path else {
+            return Ok(if held().is_some() { Output::Held } else { Output::Stdout(io::stdout()) });
+        };
+        let path = PathBuf::from(path);
+        let absolute = absolute(&path);
+        if inputs.iter().any(|input| input != "-" && absolute == self::absolute(Path::new(input))) {
+            return Err(DepsError::Usage(format!("Refusing to write the output over the input {}", path.display())));
+        }
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file = NamedTempFile::new_in(dir)
+            .map_err(|source| DepsError::Write { path: path.display().to_string(), source })?;
+        Ok(Output::File { file, path })
+    }
+
+    /// Whether what is written should be colored, which files never are.
+    pub fn colored(&self) -> bool {
+        matches!(self, Output::Stdout(_) | Output::Held) && crate::report::stdout_colored()
+    }
+
+    /// Moves a finished file into place.
+    pub fn finish(self) -> Result<(), DepsError> {
+        let _output = Phase::start("output");
+        match self {
+            Output::Stdout(mut stdout) => stdout.flush()?,
+            Output::Held => {},
+            Output::File { file, path } => {
+                // Temporary files are only readable by their owner, give the
+                // result the permissions of the file it replaces instead.
+                let permissions = match fs::metadata(&path) {
+                    Ok(metadata) => Some(metadata.permissions()),
+                    Err(_) => default_permissions(),
+                };
+                if let Some(permissions) = permissions {
+                    file.as_file().set_permissions(permissions)?;
+                }
+                file.persist(&path)
+                    .map_err(|err| DepsError::Write { path: path.display().to_string(), source: err.error })?;
+            },
+        }
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _output = Phase::start("output");
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::Held => {
+                held().get_or_insert_with(Vec::new).extend_from_slice(buf);
+                Ok(buf.len())
+            },
+            Output::File { file, .. } => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let _output = Phase::start("output");
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::Held => Ok(()),
+            Output::File { file, .. } => file.flush(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn default_permissions() -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(fs::Permissions::from_mode(0o644))
+}
+
+#[cfg(not(unix))]
+fn default_permissions() -> Option<fs::Permissions> {
+    None
+}
+
+/// Resolves `path` as far as it exists, so that two spellings of the same
+/// file compare equal even when the file has not been created yet.
+fn absolute(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf()).join(name)
+        },
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The names of the files in `dir`, sorted.
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn a_failed_command_leaves_the_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("order.txt");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut out = Output::open(Some(path.to_str().unwrap()), &[]).unwrap();
+        writeln!(out, "half of the").unwrap();
+        // The command fails before `finish`, as on a cycle found after
+        // the output was opened.
+        drop(out);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+        assert_eq!(files(dir.path()), ["order.txt"]);
+    }
+
+    #[test]
+    fn finishing_replaces_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("order.txt");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut out = Output::open(Some(path.to_str().unwrap()), &[]).unwrap();
+        writeln!(out, "c\nb\na").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+        out.finish().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "c\nb\na\n");
+        assert_eq!(files(dir.path()), ["order.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_new_file_is_readable_by_others() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("new.txt");
+        Output::open(Some(path.to_str().unwrap()), &[]).unwrap().finish().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o644);
+    }
+
+    #[test]
+    fn the_input_is_never_written_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("graph.dot");
+        fs::write(&input, "digraph { a -> b }").unwrap();
+        let inputs = [input.display().to_string()];
+
+        let another_spelling = dir.path().join(".").join("graph.dot");
+        for path in [&input, &another_spelling] {
+            let result = Output::open(Some(path.to_str().unwrap()), &inputs);
+            assert!(matches!(result, Err(DepsError::Usage(message)) if message.contains("over the input")));
+        }
+        assert_eq!(fs::read_to_string(&input).unwrap(), "digraph { a -> b }");
+        assert!(Output::open(Some(dir.path().join("out.dot").to_str().unwrap()), &inputs).is_ok());
+    }
+}
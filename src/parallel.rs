@@ -0,0 +1,166 @@
+//! Runs the analyses that never cross from one weakly connected component
+//! to another on several threads, a component at a time. The results are
+//! merged in the order a single thread would have found them, so they are
+//! the same whatever the number of threads.
+//!
+//! ```
+//! use std::collections::HashSet;
+//! use depsys::{csr::Csr, graph::GraphBuilder, parallel};
+//!
+//! // The search from `a` finds nothing, so the cycle through `x` is found
+//! // before the one in the component of `a`.
+//! let mut graph = GraphBuilder::new(false);
+//! graph.add_node("a");
+//! for (from, to) in [("x", "y"), ("y", "x"), ("c", "d"), ("d", "c"), ("c", "a")] {
+//!     graph.add_edge_by_name(from, to)?;
+//! }
+//! let items = graph.build();
+//! assert_eq!(parallel::detect_cycle(&items, &Csr::forward(&items), &HashSet::new(), 8)?, Some(vec![1, 2]));
+//! # Ok::<(), depsys::error::DepsError>(())
+//! ```
+
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+use crate::analysis::{self, search_cycle, tarjan, weak_components};
+use crate::csr::Csr;
+use crate::error::DepsError;
+use crate::graph::{Item, check_deps};
+use crate::report::Progress;
+
+/// How many threads to use when none are asked for: as many as the machine
+/// can run at once.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Runs `analyze` on every weakly connected component of the graph, on up
+/// to `threads` threads. It is given the index of every member of the
+/// component and the component as items of its own, its members in input
+/// order. The results come back ordered by the smallest member of their
+/// component. Returns `None` without looking at the graph with a single
+/// thread, and when the graph is one component, so the caller can run the
+/// analysis on the whole graph instead.
+pub fn per_component<'a, T: Sync, R: Send>(
+    items: &'a [Item<T>],
+    threads: usize,
+    analyze: impl Fn(&[usize], &[Item<&'a T>]) -> R + Sync,
+) -> Option<Vec<R>> {
+    if threads <= 1 {
+        return None;
+    }
+    // Largest first, which is the order they are handed out in.
+    let components = weak_components(items);
+    if components.len() <= 1 {
+        return None;
+    }
+
+    let mut local = vec![0; items.len()];
+    for members in &components {
+        for (position, &member) in members.iter().enumerate() {
+            local[member] = position;
+        }
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(components.len()));
+    thread::scope(|scope| {
+        for _ in 0..threads.min(components.len()) {
+            scope.spawn(|| loop {
+                let number = next.fetch_add(1, Ordering::Relaxed);
+                let Some(members) = components.get(number) else { break };
+                let graph: Vec<_> = members.iter()
+                    .map(|&member| Item::new(&items[member].data, items[member].deps.iter().map(|&dep| local[dep]).collect()))
+                    .collect();
+                let result = analyze(members, &graph);
+                results.lock().unwrap().push((members[0], result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_unstable_by_key(|&(smallest, _)| smallest);
+    log::trace!("Analyzed {} components on {} threads", components.len(), threads.min(components.len()));
+    Some(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// `analysis::detect_cycle`, searching the components on up to `threads`
+/// threads. Finds the same cycle as a single search of the whole graph:
+/// the one found from the first item that leads to any.
+pub fn detect_cycle<T: Sync>(
+    items: &[Item<T>],
+    graph: &Csr,
+    undirected: &HashSet<(usize, usize)>,
+    threads: usize,
+) -> Result<Option<Vec<usize>>, DepsError> {
+    check_deps(items)?;
+    let started = Instant::now();
+    // The searches count the items of each component once it is done,
+    // on a single line.
+    let progress = Mutex::new(Progress::new("Looking for cycles", "nodes", Some(items.len())));
+    let found = per_component(items, threads, |members, component| {
+        let undirected = |a: usize, b: usize| undirected.contains(&(members[a], members[b]));
+        let (found, _) = search_cycle(&Csr::forward(component), undirected, &mut Progress::hidden());
+        progress.lock().unwrap().add(members.len());
+        found.map(|(start, cycle)| (members[start], cycle.into_iter().map(|index| members[index]).collect::<Vec<_>>()))
+    });
+    let Some(found) = found else {
+        return analysis::detect_cycle(graph, undirected);
+    };
+    let cycle = found.into_iter().flatten().min_by_key(|&(start, _)| start).map(|(_, cycle)| cycle);
+    let outcome = if cycle.is_some() { "a" } else { "no" };
+    log::debug!("Found {} cycle in {:.1?}, component by component", outcome, started.elapsed());
+    Ok(cycle)
+}
+
+/// `analysis::strongly_connected_components`, finding those of each weakly
+/// connected component on one of up to `threads` threads. They come out
+/// in the same order as from a single search of the whole graph.
+pub fn strongly_connected_components<T: Sync>(items: &[Item<T>], graph: &Csr, threads: usize) -> Vec<Vec<usize>> {
+    let found = per_component(items, threads, |members, component| {
+        tarjan(&Csr::forward(component)).into_iter()
+            .map(|(root, component)| (members[root], component.into_iter().map(|index| members[index]).collect::<Vec<_>>()))
+            .collect::<Vec<_>>()
+    });
+    let Some(found) = found else {
+        return analysis::strongly_connected_components(graph);
+    };
+    // A single search goes through the items in order, finishing the
+    // components it reaches from each before moving on to the next.
+    let mut components: Vec<_> = found.into_iter().flatten().collect();
+    components.sort_by_key(|&(root, _)| root);
+    components.into_iter().map(|(_, members)| members).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_graph;
+
+    #[test]
+    fn any_number_of_threads_finds_what_one_search_does() {
+        for seed in 0..50 {
+            // Only the edges within the same remainder by seven are kept, so
+            // the items fall into up to seven components taking turns.
+            let items: Vec<_> = random_graph(seed, 1 + seed as usize % 60)
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    let deps = item.deps.iter().copied().filter(|dep| dep % 7 == index % 7).collect();
+                    Item::new(item.data, deps)
+                })
+                .collect();
+            let packed = Csr::forward(&items);
+
+            let cycle = analysis::detect_cycle(&packed, &HashSet::new()).unwrap();
+            let components = analysis::strongly_connected_components(&packed);
+            for threads in [1, 2, 8] {
+                assert_eq!(detect_cycle(&items, &packed, &HashSet::new(), threads).unwrap(), cycle, "seed {}", seed);
+                assert_eq!(strongly_connected_components(&items, &packed, threads), components, "seed {}", seed);
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! The questions that can be asked of a loaded graph, shared by their
+//! subcommands and the `repl`, so both always answer alike.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::process::ExitCode;
+use depsys::analysis::{
+    cycle_chain, degrees, dependents, elementary_cycles, find_path, find_self_loops, longest_chains, simple_paths,
+    sort_order, strongly_connected_components,
+};
+use depsys::csr::Csr;
+use depsys::error::DepsError;
+use depsys::graph::{Item, Loader};
+use depsys::parallel;
+use depsys::traverse::{Charset, Edges, Traverse, write_tree};
+use depsys::report::{self, Style};
+use crate::write_names;
+
+/// Lists the nodes `node` depends on, up to `depth` edges away, sorted or
+/// drawn as a tree.
+pub fn deps(loader: &Loader, node: &str, depth: Option<usize>, tree: bool, out: &mut impl Write) -> Result<ExitCode, DepsError> {
+    let start = loader.find(node)?;
+    let items = loader.items.as_slice();
+
+    if tree {
+        write_tree(out, items, Edges::packed(items, loader.csr()), start, depth, Charset::Utf8)?;
+    } else {
+        let mut names: Vec<_> = items.bfs_from(start).max_depth(depth).skip(1).map(|index| items[index].to_string()).collect();
+        names.sort();
+        for name in names {
+            writeln!(out, "{}", name)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Lists the nodes that depend on `node`, up to `depth` edges away.
+pub fn rdeps(
+    loader: &Loader,
+    node: &str,
+    depth: Option<usize>,
+    count: bool,
+    json: bool,
+    out: &mut impl Write,
+) -> Result<ExitCode, DepsError> {
+    let start = loader.find(node)?;
+    let items = loader.items.as_slice();
+
+    let mut names: Vec<_> = items.reverse_bfs_from(start).max_depth(depth).skip(1)
+        .map(|index| items[index].to_string())
+        .collect();
+    names.sort();
+
+    write_names(out, &names, count, json)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints a shortest path from `from` to `to`, or with `all` every simple
+/// one up to `limit`. Fails when there is none.
+pub fn why(
+    loader: &Loader,
+    from: &str,
+    to: &str,
+    all: bool,
+    limit: Option<usize>,
+    out: &mut impl Write,
+) -> Result<ExitCode, DepsError> {
+    let (a, b) = (loader.find(from)?, loader.find(to)?);
+    let items = &loader.items;
+
+    let paths = if all {
+        simple_paths(items, a, b, limit)
+    } else {
+        find_path(items, a, b, None).into_iter().collect()
+    };
+    for path in &paths {
+        let names: Vec<_> = path.iter().map(|&index| items[index].to_string()).collect();
+        writeln!(out, "{}", names.join(" -> "))?;
+    }
+    if paths.is_empty() {
+        report::problem(format_args!("{} does not depend on {}", *items[a], *items[b]));
+    }
+    Ok(if paths.is_empty() { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// Prints the neighbors of `node`, whether it is on a cycle, and its level
+/// and depth when the graph has none.
+pub fn show(loader: &Loader, node: &str, json: bool, out: &mut impl Write) -> Result<ExitCode, DepsError> {
+    let index = loader.find(node)?;
+    let items = &loader.items;
+    let dependents = dependents(items);
+    let names = |indices: &[usize]| {
+        let mut names: Vec<_> = indices.iter().map(|&index| items[index].to_string()).collect();
+        names.sort();
+        names
+    };
+    let (deps, dependent_names) = (names(&items[index].deps), names(&dependents[index]));
+
+    let components = strongly_connected_components(loader.csr());
+    let component = components.iter().find(|members| members.contains(&index)).map_or(1, Vec::len);
+    let on_cycle = component > 1 || items[index].deps.contains(&index);
+    let is_dag = components.iter().all(|members| members.len() == 1) && find_self_loops(items).is_empty();
+    // The level is the longest chain down to a leaf, and the depth
+    // the longest chain up to a root, found on the reversed graph.
+    let (level, depth) = if is_dag {
+        let (order, levels) = sort_order(items, false);
+        let reversed: Vec<_> = dependents.iter().map(|dependents| Item::new((), dependents.clone())).collect();
+        let reversed_order: Vec<_> = order.iter().rev().copied().collect();
+        (Some(levels[index]), Some(longest_chains(&reversed, &reversed_order)[index].0))
+    } else {
+        (None, None)
+    };
+
+    if json {
+        writeln!(out, "{}", serde_json::json!({
+            "node": items[index].to_string(),
+            "dependencies": deps,
+            "dependents": dependent_names,
+            "fan_out": deps.len(),
+            "fan_in": dependent_names.len(),
+            "on_cycle": on_cycle,
+            "level": level,
+            "depth": depth,
+        }))?;
+    } else {
+        let list = |names: &[String]| if names.is_empty() { "none".to_string() } else { names.join(", ") };
+        let number = |number: Option<usize>| number.map_or("none, the graph has cycles".to_string(), |number| number.to_string());
+        writeln!(out, "Node: {}", items[index].data)?;
+        writeln!(out, "Dependencies: {}", list(&deps))?;
+        writeln!(out, "Dependents: {}", list(&dependent_names))?;
+        writeln!(out, "Fan-out: {}", deps.len())?;
+        writeln!(out, "Fan-in: {}", dependent_names.len())?;
+        writeln!(out, "On a cycle: {}", if on_cycle { "yes" } else { "no" })?;
+        writeln!(out, "Level: {}", number(level))?;
+        writeln!(out, "Depth: {}", number(depth))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the size and shape of the graph, working out its cycles and
+/// chains on up to `threads` threads.
+pub fn stats(loader: &Loader, json: bool, threads: usize, out: &mut impl Write) -> Result<ExitCode, DepsError> {
+    let items = &loader.items;
+    let (fan_out, fan_in) = degrees(items);
+    let edges: usize = fan_out.iter().sum();
+    let isolated = (0..items.len()).filter(|&index| fan_out[index] == 0 && fan_in[index] == 0).count();
+    let (cyclic_components, longest_chain) = match parallel::per_component(items, threads, |_, component| shape(component, &Csr::forward(component))) {
+        Some(shapes) => shapes.into_iter().fold((0, Some(0)), |(cyclic, longest), (more, chain)| {
+            (cyclic + more, longest.zip(chain).map(|(a, b)| a.max(b)))
+        }),
+        None => shape(items, loader.csr()),
+    };
+    let is_dag = longest_chain.is_some();
+
+    // The largest degree, the names of up to five items that have it
+    // and the average degree.
+    let degree = |degrees: &[usize]| {
+        let max = degrees.iter().copied().max().unwrap_or(0);
+        let top: Vec<_> = (0..items.len())
+            .filter(|&index| max > 0 && degrees[index] == max)
+            .take(5)
+            .map(|index| items[index].to_string())
+            .collect();
+        let average = if items.is_empty() { 0.0 } else { edges as f64 / items.len() as f64 };
+        (max, top, average)
+    };
+    let clusters = items.iter().filter_map(|item| item.cluster.as_deref()).collect::<HashSet<_>>().len();
+    let roots = fan_in.iter().filter(|&&degree| degree == 0).count();
+    let leaves = fan_out.iter().filter(|&&degree| degree == 0).count();
+    let fan_out = degree(&fan_out);
+    let fan_in = degree(&fan_in);
+
+    if json {
+        let degree = |(max, top, average): &(usize, Vec<String>, f64)| {
+            serde_json::json!({ "max": max, "top": top, "average": average })
+        };
+        writeln!(out, "{}", serde_json::json!({
+            "nodes": items.len(),
+            "edges": edges,
+            "roots": roots,
+            "leaves": leaves,
+            "isolated": isolated,
+            "clusters": clusters,
+            "fan_out": degree(&fan_out),
+            "fan_in": degree(&fan_in),
+            "longest_chain": longest_chain,
+            "cyclic_components": cyclic_components,
+            "is_dag": is_dag,
+        }))?;
+    } else {
+        let degree = |(max, top, average): &(usize, Vec<String>, f64)| if top.is_empty() {
+            format!("max {}, average {:.2}", max, average)
+        } else {
+            format!("max {} ({}), average {:.2}", max, top.join(", "), average)
+        };
+        writeln!(out, "Nodes: {}", items.len())?;
+        writeln!(out, "Edges: {}", edges)?;
+        writeln!(out, "Roots: {}", roots)?;
+        writeln!(out, "Leaves: {}", leaves)?;
+        writeln!(out, "Isolated: {}", isolated)?;
+        writeln!(out, "Clusters: {}", clusters)?;
+        writeln!(out, "Fan-out: {}", degree(&fan_out))?;
+        writeln!(out, "Fan-in: {}", degree(&fan_in))?;
+        match longest_chain {
+            Some(length) => writeln!(out, "Longest chain: {}", length)?,
+            None => writeln!(out, "Longest chain: none, the graph has cycles")?,
+        }
+        writeln!(out, "Components with cycles: {}", cyclic_components)?;
+        writeln!(out, "DAG: {}", if is_dag { "yes" } else { "no" })?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// How many strongly connected components of more than one item there are,
+/// and the length of the longest chain when there are no cycles at all.
+fn shape<T: Ord>(items: &[Item<T>], graph: &Csr) -> (usize, Option<usize>) {
+    let cyclic_components = strongly_connected_components(graph).iter().filter(|members| members.len() > 1).count();
+    let is_dag = cyclic_components == 0 && find_self_loops(items).is_empty();
+    let longest_chain = is_dag.then(|| {
+        let (order, _) = sort_order(items, false);
+        longest_chains(items, &order).iter().map(|&(length, _)| length).max().unwrap_or(0)
+    });
+    (cyclic_components, longest_chain)
+}
+
+/// Prints every elementary cycle, up to `limit`, failing when there are
+/// any. `colored` paints the message for a graph without cycles.
+pub fn cycles(loader: &Loader, limit: Option<usize>, colored: bool, out: &mut impl Write) -> Result<ExitCode, DepsError> {
+    if loader.any_both_ways {
+        return Err(DepsError::Unsupported(
+            "Cycle listings of undirected graphs read with --treat-undirected-as both".to_string()
+        ));
+    }
+    let items = &loader.items;
+
+    let (cycles, truncated) = elementary_cycles(loader.csr(), limit);
+    for cycle in &cycles {
+        let names: Vec<_> = cycle.iter().map(|&index| &*items[index]).collect();
+        writeln!(out, "{}", cycle_chain(&names))?;
+    }
+    if truncated {
+        report::warning(format_args!("Stopped after {} cycles, there may be more", cycles.len()));
+    }
+    if cycles.is_empty() {
+        writeln!(out, "{}", report::paint(Style::Green, "The graph has no circular dependencies", colored))?;
+    }
+    Ok(if cycles.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
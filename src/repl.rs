@@ -0,0 +1,368 @@
+//! An interactive prompt answering queries about a graph that is only
+//! loaded once. Every query is answered by the same function as the
+//! subcommand of the same name.
+
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::process::ExitCode;
+use clap::{CommandFactory, Parser, Subcommand};
+use depsys::error::DepsError;
+use depsys::formats::edgelist;
+use depsys::graph::Loader;
+use depsys::report;
+use crate::queries;
+
+/// The queries the prompt answers, one per line
+#[derive(Parser)]
+#[command(multicall = true)]
+struct Line {
+    #[command(subcommand)]
+    query: Query,
+}
+
+#[derive(Subcommand)]
+enum Query {
+    /// List everything a node depends on, directly or not
+    Deps {
+        /// The node to start from, by name or label
+        node: String,
+        /// Only follow this many edges
+        #[arg(long, conflicts_with = "direct")]
+        depth: Option<usize>,
+        /// Only list the direct dependencies, like --depth 1
+        #[arg(long)]
+        direct: bool,
+        /// Draw the dependencies as an indented tree
+        #[arg(long)]
+        tree: bool,
+    },
+    /// List everything that depends on a node, directly or not
+    Rdeps {
+        /// The node to start from, by name or label
+        node: String,
+        /// Only follow this many edges
+        #[arg(long, conflicts_with = "direct")]
+        depth: Option<usize>,
+        /// Only list the direct dependents, like --depth 1
+        #[arg(long)]
+        direct: bool,
+        /// Only print how many dependents there are
+        #[arg(long)]
+        count: bool,
+        /// Print the dependents, or their count, as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a shortest chain of dependencies from one node to another
+    Why {
+        /// The dependent node, by name or label
+        from: String,
+        /// The dependency, by name or label
+        to: String,
+        /// List every path that visits no node twice, not only a shortest one
+        #[arg(long)]
+        all: bool,
+        /// Stop after this many paths
+        #[arg(long, requires = "all")]
+        limit: Option<usize>,
+    },
+    /// Print what there is to know about one node and its neighbors
+    Show {
+        /// The node to show, by name or label
+        node: String,
+        /// Print the node as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print statistics about the size and shape of the graph
+    Stats {
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List every elementary cycle of the graph, one per line
+    Cycles {
+        /// Stop after this many cycles
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Leave the prompt
+    #[command(alias = "exit")]
+    Quit,
+}
+
+/// How many completions are listed before the rest are only counted.
+const COMPLETION_LIST: usize = 100;
+
+/// Answers queries typed one per line until `quit` or the end of the
+/// input. On a terminal the line can be edited, Tab completes commands and
+/// node names, and the arrow keys go through earlier lines.
+pub fn run(loader: &Loader, threads: usize) -> Result<ExitCode, DepsError> {
+    let edges: usize = loader.items.iter().map(|item| item.deps.len()).sum();
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        report::info(format_args!(
+            "Loaded {} nodes and {} edges. Type help for the commands, or quit to leave.",
+            loader.items.len(), edges,
+        ));
+    }
+
+    let mut commands: Vec<_> = Line::command().get_subcommands().map(|command| command.get_name().to_string()).collect();
+    commands.push("help".to_string());
+    let mut names: Vec<_> = loader.items.iter().map(|item| item.name.clone()).collect();
+    names.sort();
+
+    let mut editor = Editor::new();
+    // The editor reads the terminal itself, so stdin is only locked here
+    // when it isn't one.
+    let mut lines = (!interactive).then(|| io::stdin().lock().lines());
+    loop {
+        let line = match &mut lines {
+            Some(lines) => lines.next().transpose()
+                .map_err(|source| DepsError::Io { path: "standard input".to_string(), source })?,
+            None => editor.read_line("> ", |first_word| if first_word { &commands } else { &names })?,
+        };
+        let Some(line) = line else {
+            return Ok(ExitCode::SUCCESS);
+        };
+        let tokens = match edgelist::tokenize(&line) {
+            Ok(tokens) if tokens.is_empty() => continue,
+            Ok(tokens) => tokens,
+            Err((column, message)) => {
+                report::error(format_args!("column {}: {}", column, message));
+                continue;
+            },
+        };
+        let query = match Line::try_parse_from(tokens) {
+            Ok(line) => line.query,
+            Err(err) => {
+                let _ = err.print();
+                continue;
+            },
+        };
+        if let Query::Quit = query {
+            return Ok(ExitCode::SUCCESS);
+        }
+        if let Err(err) = answer(loader, query, threads) {
+            report::error(&err);
+        }
+    }
+}
+
+fn answer(loader: &Loader, query: Query, threads: usize) -> Result<ExitCode, DepsError> {
+    let out = &mut io::stdout().lock();
+    match query {
+        Query::Deps { node, depth, direct, tree } => {
+            queries::deps(loader, &node, if direct { Some(1) } else { depth }, tree, out)
+        },
+        Query::Rdeps { node, depth, direct, count, json } => {
+            queries::rdeps(loader, &node, if direct { Some(1) } else { depth }, count, json, out)
+        },
+        Query::Why { from, to, all, limit } => queries::why(loader, &from, &to, all, limit, out),
+        Query::Show { node, json } => queries::show(loader, &node, json, out),
+        Query::Stats { json } => queries::stats(loader, json, threads, out),
+        Query::Cycles { limit } => queries::cycles(loader, limit, report::stdout_colored(), out),
+        Query::Quit => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// A small line editor for the terminal, keeping the lines read so far.
+struct Editor {
+    history: Vec<String>,
+}
+
+impl Editor {
+    fn new() -> Self {
+        Editor { history: Vec::new() }
+    }
+
+    /// Reads a line after printing `prompt`, or returns `None` for Ctrl-D
+    /// on an empty line. `candidates` gives what the word before the
+    /// cursor can be completed to, depending on whether it is the first.
+    #[cfg(unix)]
+    fn read_line<'a>(
+        &mut self,
+        prompt: &str,
+        candidates: impl Fn(bool) -> &'a [String],
+    ) -> Result<Option<String>, DepsError> {
+        let io_error = |source| DepsError::Io { path: "the terminal".to_string(), source };
+        let _raw = RawMode::enable().map_err(io_error)?;
+        let mut stdout = io::stdout();
+        let mut stdin = io::stdin().lock();
+        let mut line = String::new();
+        // The bytes of a character that has only been partly read.
+        let mut pending = Vec::new();
+        // Where we are in the history, which is one past its end for the
+        // line being typed.
+        let mut recalled = self.history.len();
+        let redraw = |stdout: &mut io::Stdout, line: &str| {
+            let _ = write!(stdout, "\r\x1b[K{}{}", prompt, line);
+        };
+
+        redraw(&mut stdout, &line);
+        loop {
+            let _ = stdout.flush();
+            let mut byte = [0];
+            if stdin.read(&mut byte).map_err(io_error)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                b'\r' | b'\n' => {
+                    let _ = write!(stdout, "\r\n");
+                    if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                        self.history.push(line.clone());
+                    }
+                    return Ok(Some(line));
+                },
+                // Ctrl-C drops the line, Ctrl-D on an empty one leaves.
+                3 => {
+                    let _ = write!(stdout, "^C\r\n");
+                    return Ok(Some(String::new()));
+                },
+                4 if line.is_empty() => {
+                    let _ = write!(stdout, "\r\n");
+                    return Ok(None);
+                },
+                // Ctrl-U clears the line.
+                21 => {
+                    line.clear();
+                    redraw(&mut stdout, &line);
+                },
+                8 | 127 if line.pop().is_some() => redraw(&mut stdout, &line),
+                b'\t' => {
+                    let listed = complete(&mut line, &candidates);
+                    if !listed.is_empty() {
+                        let _ = write!(stdout, "\r\n{}\r\n", listed.replace('\n', "\r\n"));
+                    }
+                    redraw(&mut stdout, &line);
+                },
+                // An escape sequence, of which only the up and down arrows
+                // mean something here.
+                27 => {
+                    let mut sequence = Vec::new();
+                    loop {
+                        if stdin.read(&mut byte).map_err(io_error)? == 0 {
+                            return Ok(None);
+                        }
+                        sequence.push(byte[0]);
+                        if sequence.len() > 1 && (0x40..=0x7e).contains(&byte[0]) || sequence[0] != b'[' {
+                            break;
+                        }
+                    }
+                    match sequence.as_slice() {
+                        b"[A" if recalled > 0 => recalled -= 1,
+                        b"[B" if recalled < self.history.len() => recalled += 1,
+                        _ => continue,
+                    }
+                    line = self.history.get(recalled).cloned().unwrap_or_default();
+                    redraw(&mut stdout, &line);
+                },
+                byte if byte >= b' ' => {
+                    pending.push(byte);
+                    match std::str::from_utf8(&pending) {
+                        Ok(text) => {
+                            line.push_str(text);
+                            let _ = write!(stdout, "{}", text);
+                            pending.clear();
+                        },
+                        Err(err) if err.error_len().is_some() => pending.clear(),
+                        Err(_) => {},
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Without a way to edit lines, reads them as they are typed.
+    #[cfg(not(unix))]
+    fn read_line<'a>(
+        &mut self,
+        prompt: &str,
+        _candidates: impl Fn(bool) -> &'a [String],
+    ) -> Result<Option<String>, DepsError> {
+        print!("{}", prompt);
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        let read = io::stdin().read_line(&mut line)
+            .map_err(|source| DepsError::Io { path: "standard input".to_string(), source })?;
+        Ok((read > 0).then(|| line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+}
+
+/// Completes the last word of `line` from `candidates`, as far as they
+/// agree. When they don't agree any further, returns them as text to list
+/// below the line.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn complete<'a>(line: &mut String, candidates: impl Fn(bool) -> &'a [String]) -> String {
+    let start = line.rfind(char::is_whitespace).map_or(0, |space| space + 1);
+    let word = line[start..].trim_start_matches('"');
+    let first_word = line[..start].trim().is_empty();
+    let matches: Vec<_> = candidates(first_word).iter().filter(|candidate| candidate.starts_with(word)).collect();
+
+    match matches.as_slice() {
+        [] => String::new(),
+        [only] => {
+            line.truncate(start);
+            line.push_str(&edgelist::quote(only));
+            line.push(' ');
+            String::new()
+        },
+        [first, rest @ ..] => {
+            let prefix = rest.iter().fold(first.as_str(), |common, other| {
+                let length = common.char_indices()
+                    .zip(other.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(common.len().min(other.len()), |((index, _), _)| index);
+                &common[..length]
+            });
+            // A prefix that would need quotes can't be typed halfway.
+            if prefix.len() > word.len() && edgelist::quote(prefix) == prefix {
+                line.truncate(start);
+                line.push_str(prefix);
+                return String::new();
+            }
+            let mut listed: Vec<_> = matches.iter().take(COMPLETION_LIST).map(|name| edgelist::quote(name)).collect();
+            if matches.len() > COMPLETION_LIST {
+                listed.push(format!("and {} more", matches.len() - COMPLETION_LIST));
+            }
+            listed.join("\n")
+        },
+    }
+}
+
+/// Puts the terminal in a mode where every key is read as it is pressed and
+/// not echoed, until dropped.
+#[cfg(unix)]
+struct RawMode(libc::termios);
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        // SAFETY: `termios` is plain data that `tcgetattr` fills in, and it
+        // is only used when the call succeeded.
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode(original))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: this puts back the settings read in `enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::env;
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use clap::ValueEnum;
+use log::LevelFilter;
+
+/// When to color the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color streams that go to a terminal, unless `NO_COLOR` is set
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+/// When to show the progress of the long phases of the work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressChoice {
+    /// Show progress when stderr is a terminal
+    Auto,
+    /// Always show progress
+    Always,
+    /// Never show progress
+    Never,
+}
+
+/// The colors messages are painted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Red,
+    Green,
+    Yellow,
+}
+
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// When the program started, for the time on each log line.
+static START: OnceLock<Instant> = OnceLock::new();
+static PROGRESS: AtomicBool = AtomicBool::new(false);
+/// Whether a progress line is on stderr, to erase before printing anything
+/// else there.
+static PROGRESS_DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// The crates `-v` logs for, the library and the binary.
+const CRATES: [&str; 2] = ["depsys", "dep_sys_rs"];
+
+/// Decides once for each stream whether it gets colored.
+pub fn init(choice: ColorChoice) {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let enabled = |is_terminal: bool| match choice {
+        ColorChoice::Auto => is_terminal && !no_color,
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    STDOUT.store(enabled(io::stdout().is_terminal()), Ordering::Relaxed);
+    STDERR.store(enabled(io::stderr().is_terminal()), Ordering::Relaxed);
+}
+
+/// Decides once whether the progress of the long phases of the work is
+/// shown on stderr.
+pub fn init_progress(choice: ProgressChoice) {
+    let enabled = match choice {
+        ProgressChoice::Auto => io::stderr().is_terminal(),
+        ProgressChoice::Always => true,
+        ProgressChoice::Never => false,
+    };
+    PROGRESS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether progress is shown, to skip working out what it would count.
+pub fn showing_progress() -> bool {
+    !quiet() && PROGRESS.load(Ordering::Relaxed)
+}
+
+/// Logs the phases of the work with `-v` and their details with `-vv`, on
+/// top of what `RUST_LOG` asks for, where naming one of the crates wins.
+/// Log lines go to stderr, so the output stays the same.
+pub fn init_log(verbosity: u8) {
+    START.get_or_init(Instant::now);
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(LevelFilter::Off);
+    let level = match verbosity {
+        0 => None,
+        1 => Some(LevelFilter::Debug),
+        _ => Some(LevelFilter::Trace),
+    };
+    if let Some(level) = level {
+        for name in CRATES {
+            builder.filter_module(name, level);
+        }
+    }
+    builder.parse_default_env();
+    builder.format(|out, record| {
+        erase_progress();
+        let elapsed = START.get_or_init(Instant::now).elapsed();
+        writeln!(out, "[{:>8.3}s {} {}] {}", elapsed.as_secs_f64(), record.level(), record.target(), record.args())
+    });
+    // Only the first call takes, as when a watched command runs again.
+    let _ = builder.try_init();
+}
+
+/// Stops every message below from being printed, for `check --quiet`.
+pub fn silence() {
+    QUIET.store(true, Ordering::Relaxed);
+    log::set_max_level(LevelFilter::Off);
+}
+
+/// Whether messages have been silenced.
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Whether text written to stdout should be colored.
+pub fn stdout_colored() -> bool {
+    STDOUT.load(Ordering::Relaxed)
+}
+
+/// Whether text written to stderr should be colored.
+pub fn stderr_colored() -> bool {
+    STDERR.load(Ordering::Relaxed)
+}
+
+/// Wraps `text` in the escape codes for `style` when `enabled`.
+pub fn paint(style: Style, text: impl Display, enabled: bool) -> String {
+    if !enabled {
+        return text.to_string();
+    }
+    let code = match style {
+        Style::Red => "31",
+        Style::Green => "32",
+        Style::Yellow => "33",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// Paints `text` for a message on stderr.
+pub fn highlight(style: Style, text: impl Display) -> String {
+    paint(style, text, stderr_colored())
+}
+
+/// Prints an `ERROR:` line on stderr.
+pub fn error(message: impl Display) {
+    if quiet() {
+        return;
+    }
+    erase_progress();
+    eprintln!("{} {}", highlight(Style::Red, "ERROR:"), message);
+}
+
+/// Prints a `WARNING:` line on stderr.
+pub fn warning(message: impl Display) {
+    if quiet() {
+        return;
+    }
+    erase_progress();
+    eprintln!("{} {}", highlight(Style::Yellow, "WARNING:"), message);
+}
+
+/// Prints a problem found in the graph on stderr, as is.
+pub fn problem(message: impl Display) {
+    if quiet() {
+        return;
+    }
+    erase_progress();
+    eprintln!("{}", message);
+}
+
+/// Prints a summary meant for people on stderr, keeping stdout for results.
+pub fn info(message: impl Display) {
+    if quiet() {
+        return;
+    }
+    erase_progress();
+    eprintln!("{}", message);
+}
+
+/// How often a progress line is redrawn at most, and how long a phase goes
+/// on before it gets one.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many steps a phase counts between looks at the clock.
+const PROGRESS_STEPS: usize = 1024;
+
+/// A line on stderr counting the steps of a long phase of the work, such as
+/// the bytes read or the nodes sorted so far. It only shows up once the
+/// phase has gone on for a while, is redrawn a few times a second and is
+/// erased when dropped. When progress isn't shown, counting a step only
+/// adds to a number and compares it with one never reached.
+pub struct Progress {
+    /// What the phase does, left empty when progress isn't shown.
+    label: String,
+    /// What is counted, or nothing for a phase that counts no steps.
+    unit: &'static str,
+    total: Option<usize>,
+    done: usize,
+    /// The count at which to look at the clock next.
+    check_at: usize,
+    /// When the line was last drawn, or else when the phase started.
+    drawn_at: Instant,
+}
+
+impl Progress {
+    /// Counts the steps of the phase `label` in `unit`, out of `total`
+    /// when it is known.
+    pub fn new(label: impl Display, unit: &'static str, total: Option<usize>) -> Self {
+        if !showing_progress() {
+            return Progress::hidden();
+        }
+        Progress { label: label.to_string(), unit, total, done: 0, check_at: PROGRESS_STEPS, drawn_at: Instant::now() }
+    }
+
+    /// Counts the bytes of the phase `label`, shown in megabytes.
+    pub fn bytes(label: impl Display, total: Option<usize>) -> Self {
+        Progress::new(label, "MB", total)
+    }
+
+    /// Shows `label` right away until dropped, for a phase that has no
+    /// steps to count.
+    pub fn phase(label: impl Display) -> Self {
+        let progress = Progress::new(label, "", None);
+        if !progress.label.is_empty() {
+            progress.draw();
+        }
+        progress
+    }
+
+    /// Counts nothing and shows nothing, for a phase whose progress is
+    /// counted by the caller.
+    pub fn hidden() -> Self {
+        Progress { label: String::new(), unit: "", total: None, done: 0, check_at: usize::MAX, drawn_at: Instant::now() }
+    }
+
+    /// Counts `steps` more steps.
+    #[inline]
+    pub fn add(&mut self, steps: usize) {
+        self.done += steps;
+        if self.done >= self.check_at {
+            self.tick();
+        }
+    }
+
+    #[cold]
+    fn tick(&mut self) {
+        self.check_at = self.done.saturating_add(PROGRESS_STEPS);
+        if self.drawn_at.elapsed() >= PROGRESS_INTERVAL {
+            self.draw();
+            self.drawn_at = Instant::now();
+        }
+    }
+
+    fn draw(&self) {
+        let count = |count: usize| match self.unit {
+            "MB" => format!("{:.1}", count as f64 / 1e6),
+            _ => count.to_string(),
+        };
+        let line = match self.total {
+            _ if self.unit.is_empty() => self.label.clone(),
+            Some(total) if total > 0 => format!(
+                "{}: {} of {} {} ({}%)",
+                self.label, count(self.done), count(total), self.unit, self.done.min(total) * 100 / total,
+            ),
+            _ => format!("{}: {} {}", self.label, count(self.done), self.unit),
+        };
+        eprint!("\r{}\x1b[K", line);
+        PROGRESS_DRAWN.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if !self.label.is_empty() {
+            erase_progress();
+        }
+    }
+}
+
+/// Erases the progress line, if one is drawn, so something else can be
+/// printed in its place.
+fn erase_progress() {
+    if PROGRESS_DRAWN.swap(false, Ordering::Relaxed) {
+        eprint!("\r\x1b[K");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_only_adds_escape_codes_when_enabled() {
+        assert_eq!(paint(Style::Red, "a -> b", false), "a -> b");
+        assert_eq!(paint(Style::Red, "a -> b", true), "\x1b[31ma -> b\x1b[0m");
+        assert_eq!(paint(Style::Green, "ok", true), "\x1b[32mok\x1b[0m");
+        assert_eq!(paint(Style::Yellow, "warning", true), "\x1b[33mwarning\x1b[0m");
+    }
+
+    #[test]
+    fn the_color_choice_decides_for_both_streams() {
+        init(ColorChoice::Always);
+        assert!(stdout_colored() && stderr_colored());
+        init(ColorChoice::Never);
+        assert!(!stdout_colored() && !stderr_colored());
+
+        // NO_COLOR turns off the colors a terminal would get, but not the
+        // ones asked for.
+        env::set_var("NO_COLOR", "1");
+        init(ColorChoice::Auto);
+        assert!(!stdout_colored() && !stderr_colored());
+        init(ColorChoice::Always);
+        assert!(stdout_colored() && stderr_colored());
+        env::remove_var("NO_COLOR");
+        init(ColorChoice::Never);
+    }
+}
@@ -0,0 +1,229 @@
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::Value;
+use toml::Spanned;
+use crate::graph::{Item, Node};
+use crate::error::DepsError;
+
+/// What a rule does with the edges it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Matching edges are fine, whatever later rules say
+    Allow,
+    /// Matching edges are violations
+    Deny,
+    /// Every node matching `from` needs an edge to a node matching `to`
+    Require,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    kind: Kind,
+    from: Spanned<String>,
+    to: Spanned<String>,
+    /// Patterns the DOT clusters of the two ends have to match as well.
+    from_cluster: Option<Spanned<String>>,
+    to_cluster: Option<Spanned<String>>,
+    /// Read the patterns as regular expressions rather than globs.
+    #[serde(default)]
+    regex: bool,
+}
+
+/// A rule of a rules file, numbered from 1 in the order it was written.
+pub struct Rule {
+    number: usize,
+    kind: Kind,
+    from_pattern: String,
+    to_pattern: String,
+    from: Pattern,
+    to: Pattern,
+}
+
+/// What one end of a rule matches: a node name, and its cluster when the
+/// rule names one.
+struct Pattern {
+    name: Regex,
+    cluster: Option<Regex>,
+}
+
+impl Pattern {
+    fn is_match(&self, node: &Node) -> bool {
+        let cluster_matches = match (&self.cluster, &node.cluster) {
+            (None, _) => true,
+            (Some(pattern), Some(cluster)) => pattern.is_match(cluster),
+            (Some(_), None) => false,
+        };
+        cluster_matches && self.name.is_match(&node.name)
+    }
+}
+
+impl Rule {
+    fn describe(&self) -> String {
+        let kind = match self.kind {
+            Kind::Allow => "allow",
+            Kind::Deny => "deny",
+            Kind::Require => "require",
+        };
+        format!("rule #{}: {} {} -> {}", self.number, kind, self.from_pattern, self.to_pattern)
+    }
+}
+
+/// Writes one end of a rule as `pattern`, or `cluster:pattern` when it
+/// names a cluster.
+fn describe_end(name: &Spanned<String>, cluster: &Option<Spanned<String>>) -> String {
+    match cluster {
+        Some(cluster) => format!("{}:{}", cluster.get_ref(), name.get_ref()),
+        None => name.get_ref().clone(),
+    }
+}
+
+/// Reads the `[[rule]]` tables of a TOML rules file. Patterns are globs
+/// where `*` matches any run of characters and `?` any one character,
+/// unless the rule sets `regex = true`. A rule can also require the ends of
+/// an edge to be in clusters matching `from_cluster` and `to_cluster`.
+pub fn load(path: &str, contents: &str) -> Result<Vec<Rule>, DepsError> {
+    let error_at = |offset: usize, message: &str| {
+        let before = &contents[..offset.min(contents.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        DepsError::at(path, line, column, message, contents)
+    };
+    let file: RulesFile = toml::from_str(contents).map_err(|err| {
+        let offset = err.span().map_or(0, |span| span.start);
+        error_at(offset, err.message())
+    })?;
+
+    let pattern = |spanned: &Spanned<String>, regex: bool| {
+        let pattern = spanned.get_ref();
+        let source = if regex { pattern.clone() } else { glob_to_regex(pattern) };
+        Regex::new(&source).map_err(|err| {
+            error_at(spanned.span().start, &format!("invalid pattern: {}", err.to_string().lines().last().unwrap_or("").trim_start_matches("error: ")))
+        })
+    };
+    let end = |name: &Spanned<String>, cluster: &Option<Spanned<String>>, regex: bool| -> Result<_, DepsError> {
+        Ok(Pattern {
+            name: pattern(name, regex)?,
+            cluster: cluster.as_ref().map(|cluster| pattern(cluster, regex)).transpose()?,
+        })
+    };
+    file.rule.iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            Ok(Rule {
+                number: index + 1,
+                kind: spec.kind,
+                from_pattern: describe_end(&spec.from, &spec.from_cluster),
+                to_pattern: describe_end(&spec.to, &spec.to_cluster),
+                from: end(&spec.from, &spec.from_cluster, spec.regex)?,
+                to: end(&spec.to, &spec.to_cluster, spec.regex)?,
+            })
+        })
+        .collect()
+}
+
+/// Lists every way the graph breaks the rules. Each edge is judged by the
+/// first allow or deny rule that matches it, and each require rule is
+/// checked on its own.
+pub fn check(items: &[Item<Node>], rules: &[Rule]) -> Vec<String> {
+    let mut violations = Vec::new();
+    for item in items {
+        for &dep in &item.deps {
+            let (from, to) = (&item.data, &items[dep].data);
+            let decided = rules.iter().find(|rule| {
+                rule.kind != Kind::Require && rule.from.is_match(from) && rule.to.is_match(to)
+            });
+            if let Some(rule) = decided.filter(|rule| rule.kind == Kind::Deny) {
+                violations.push(format!("{} -> {} (violates {})", from.name, to.name, rule.describe()));
+            }
+        }
+    }
+
+    for rule in rules.iter().filter(|rule| rule.kind == Kind::Require) {
+        for item in items {
+            // The node that is required is not required to depend on itself.
+            if !rule.from.is_match(item) || rule.to.is_match(item) {
+                continue;
+            }
+            if !item.deps.iter().any(|&dep| rule.to.is_match(&items[dep])) {
+                violations.push(format!(
+                    "{} has no dependency matching {} (violates {})",
+                    item.name, rule.to_pattern, rule.describe(),
+                ));
+            }
+        }
+    }
+    violations
+}
+
+/// The layer of each node, as read from a YAML mapping of glob patterns to
+/// layer numbers:
+///
+/// ```yaml
+/// ui/*: 3
+/// api/*: 2
+/// db/*: 1
+/// ```
+///
+/// A node belongs to the layer of the first pattern that matches its name.
+pub struct Layers {
+    patterns: Vec<(Regex, i64)>,
+}
+
+impl Layers {
+    /// Reads a YAML mapping of names or glob patterns to layer numbers.
+    pub fn load(path: &str, contents: &str) -> Result<Self, DepsError> {
+        let value: Value = serde_yaml::from_str(contents).map_err(|err| match err.location() {
+            Some(location) => DepsError::at(path, location.line(), location.column(), &err.to_string(), contents),
+            None => DepsError::Invalid { path: path.to_string(), message: err.to_string() },
+        })?;
+        let invalid = |message: String| DepsError::Invalid { path: path.to_string(), message };
+
+        let map = match value {
+            Value::Null => Default::default(),
+            Value::Mapping(map) => map,
+            _ => return Err(invalid("expected a mapping of names to layer numbers".to_string())),
+        };
+        let mut patterns = Vec::new();
+        for (key, layer) in &map {
+            let pattern = match key {
+                Value::String(pattern) => pattern.clone(),
+                Value::Number(number) => number.to_string(),
+                _ => return Err(invalid("every key must be a name or pattern".to_string())),
+            };
+            let layer = layer.as_i64()
+                .ok_or_else(|| invalid(format!("the layer of \"{}\" is not a whole number", pattern)))?;
+            let regex = Regex::new(&glob_to_regex(&pattern))
+                .map_err(|err| invalid(format!("invalid pattern \"{}\": {}", pattern, err)))?;
+            patterns.push((regex, layer));
+        }
+        Ok(Layers { patterns })
+    }
+
+    /// The layer of the node named `name`, if a pattern matches it.
+    pub fn layer_of(&self, name: &str) -> Option<i64> {
+        self.patterns.iter().find(|(regex, _)| regex.is_match(name)).map(|&(_, layer)| layer)
+    }
+}
+
+/// Anchors a glob as a regular expression.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
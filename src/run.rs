@@ -0,0 +1,197 @@
+//! Runs a shell command for every item of a graph, dependencies first and
+//! as many at a time as asked for, the way the `run` command does.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{self, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use crate::analysis::dependents;
+use crate::graph::{Item, Node};
+use crate::report::{self, Style};
+
+/// What became of a node in `run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    /// Not reached, because an earlier failure stopped the run
+    NotRun,
+    Succeeded,
+    Failed,
+    /// Not run because something it depends on failed or was skipped
+    Skipped,
+}
+
+/// Runs `exec` for every item, at most `jobs` at a time, starting an item
+/// once all its dependencies have succeeded. After a failure nothing new is
+/// started unless `keep_going`, and the items that were never started are
+/// left as `NotRun`.
+pub fn run_jobs(items: &[Item<Node>], exec: &str, jobs: usize, keep_going: bool) -> Vec<RunStatus> {
+    let dependents = dependents(items);
+    let mut in_degree: Vec<_> = items.iter().map(|item| item.deps.len()).collect();
+    let mut ready: VecDeque<_> = (0..items.len()).filter(|&index| in_degree[index] == 0).collect();
+    let mut status = vec![RunStatus::NotRun; items.len()];
+    let command_of = |index: usize| exec.replace("{}", &shell_quote(&items[index].name));
+
+    let (sender, receiver) = mpsc::channel();
+    let mut running = 0;
+    let mut stopped = false;
+    loop {
+        while running < jobs && !stopped {
+            let Some(index) = ready.pop_front() else { break };
+            let command = command_of(index);
+            report::info(format_args!("{} {}", report::highlight(Style::Green, "Running"), command));
+            // A single job can write straight to the terminal.
+            let prefix = (jobs > 1).then(|| items[index].to_string());
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let _ = sender.send((index, run_shell(&command, prefix.as_deref())));
+            });
+            running += 1;
+        }
+        if running == 0 {
+            break;
+        }
+
+        let (index, result) = receiver.recv().expect("every job sends its result");
+        running -= 1;
+        match result {
+            Ok(exit) if exit.success() => {
+                status[index] = RunStatus::Succeeded;
+                for &dependent in &dependents[index] {
+                    in_degree[dependent] -= 1;
+                    if in_degree[dependent] == 0 {
+                        ready.push_back(dependent);
+                    }
+                }
+                continue;
+            },
+            Ok(exit) => report::error(format_args!("{} failed with {}", *items[index], exit)),
+            Err(err) => report::error(format_args!("Could not run {}: {}", command_of(index), err)),
+        }
+        status[index] = RunStatus::Failed;
+        stopped = !keep_going;
+    }
+    status
+}
+
+/// Marks the items left as `NotRun` that depend on one that failed or was
+/// skipped as `Skipped`, going through them in `order`, dependencies first.
+pub fn skip_blocked<T>(items: &[Item<T>], order: &[usize], status: &mut [RunStatus]) {
+    // Dependencies come first, so their status is already known.
+    for &index in order {
+        let blocked = items[index].deps.iter()
+            .any(|&dep| matches!(status[dep], RunStatus::Failed | RunStatus::Skipped));
+        if status[index] == RunStatus::NotRun && blocked {
+            status[index] = RunStatus::Skipped;
+        }
+    }
+}
+
+/// Runs `command` with the shell of the platform. Without a `prefix` it
+/// writes to our stdout and stderr directly, otherwise every line it
+/// prints is written as `[prefix] line` so the output of parallel jobs can
+/// be told apart.
+fn run_shell(command: &str, prefix: Option<&str>) -> io::Result<process::ExitStatus> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    let Some(prefix) = prefix else {
+        return shell.status();
+    };
+
+    let mut child = shell.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    thread::scope(|scope| {
+        scope.spawn(|| prefix_lines(stderr, prefix, true));
+        prefix_lines(stdout, prefix, false);
+    });
+    child.wait()
+}
+
+/// Copies the lines of `stream` to stdout, or stderr when `to_stderr`, as
+/// `[prefix] line`.
+fn prefix_lines(stream: impl Read, prefix: &str, to_stderr: bool) {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).is_ok_and(|read| read > 0) {
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        if to_stderr {
+            eprintln!("[{}] {}", prefix, text);
+        } else {
+            println!("[{}] {}", prefix, text);
+        }
+        line.clear();
+    }
+}
+
+/// Quotes `text` for a POSIX shell, unless it is made only of characters
+/// the shell takes literally.
+pub fn shell_quote(text: &str) -> String {
+    let plain = !text.is_empty()
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ':' | ',' | '+' | '=' | '@'));
+    if plain {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::sort_order;
+    use crate::graph::GraphBuilder;
+
+    /// app depends on lib, which depends on core like tool does.
+    fn items() -> Vec<Item<Node>> {
+        let mut graph = GraphBuilder::new(false);
+        for (from, to) in [("app", "lib"), ("lib", "core"), ("tool", "core")] {
+            graph.add_edge_by_name(from, to).unwrap();
+        }
+        graph.items
+    }
+
+    /// Runs `exec` over `items` one job at a time and marks what it blocked.
+    fn statuses(items: &[Item<Node>], exec: &str, keep_going: bool) -> Vec<RunStatus> {
+        let mut status = run_jobs(items, exec, 1, keep_going);
+        skip_blocked(items, &sort_order(items, false).0, &mut status);
+        status
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn every_item_runs_once_its_dependencies_succeeded() {
+        let items = items();
+        assert_eq!(statuses(&items, "true {}", false), [RunStatus::Succeeded; 4]);
+        assert_eq!(run_jobs(&items, "true {}", 3, false), [RunStatus::Succeeded; 4]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_failure_skips_its_dependents_and_stops_the_rest_unless_keeping_going() {
+        use RunStatus::*;
+        let items = items();
+        // core runs first, then lib, which fails before tool is started.
+        assert_eq!(statuses(&items, "test {} != lib", false), [Skipped, Failed, Succeeded, NotRun]);
+        assert_eq!(statuses(&items, "test {} != lib", true), [Skipped, Failed, Succeeded, Succeeded]);
+        assert_eq!(statuses(&items, "test {} != core", true), [Skipped, Skipped, Failed, Skipped]);
+    }
+
+    #[test]
+    fn names_are_only_quoted_when_the_shell_would_change_them() {
+        assert_eq!(shell_quote("lib/core-1.0"), "lib/core-1.0");
+        assert_eq!(shell_quote("my lib"), "'my lib'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
@@ -0,0 +1,33 @@
+//! What the unit tests of several modules share: random graphs that come
+//! out the same on every run, so a failure can be reproduced from its seed.
+
+use crate::graph::{GraphBuilder, Item};
+
+/// A small linear congruential generator.
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random(seed)
+    }
+
+    /// A number from 0 up to, but not including, `below`.
+    pub fn below(&mut self, below: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % below
+    }
+}
+
+/// A graph of `len` items named by their index, with up to `3 * len`
+/// random edges between them, self-loops and cycles included.
+pub fn random_graph(seed: u64, len: usize) -> Vec<Item<String>> {
+    let mut random = Random::new(seed);
+    let mut graph = GraphBuilder::new(false);
+    for index in 0..len {
+        graph.add_node(&index.to_string());
+    }
+    for _ in 0..random.below(3 * len + 1) {
+        graph.add_edge(random.below(len), random.below(len)).expect("self-loops are allowed");
+    }
+    graph.build()
+}
@@ -0,0 +1,166 @@
+//! The breakdown of a run that `--timings` prints: how long each stage of
+//! the work took and how big the graph got. A stage is timed by holding a
+//! `Phase` for as long as it runs. Phases nest, and the time spent in an
+//! inner one is only counted for it, so the stages add up to the run.
+//!
+//! ```
+//! use std::thread;
+//! use std::time::Duration;
+//! use depsys::timings::{self, Phase};
+//!
+//! timings::enable();
+//! {
+//!     let _analysis = Phase::start("analysis");
+//!     thread::sleep(Duration::from_millis(20));
+//!     let _output = Phase::start("output");
+//!     thread::sleep(Duration::from_millis(20));
+//! }
+//! timings::note_size(3, 2, 16);
+//! timings::note_size(2, 5, 8);
+//!
+//! let breakdown = timings::take();
+//! let time = |name| breakdown.phases.iter().find(|&&(phase, _)| phase == name).unwrap().1;
+//! assert!(time("analysis") >= Duration::from_millis(20));
+//! assert!(time("output") >= Duration::from_millis(20));
+//! assert_eq!(time("read"), Duration::ZERO);
+//! // The output is not counted for the analysis it ran in as well.
+//! assert!(breakdown.total >= time("analysis") + time("output"));
+//! assert_eq!(breakdown.peak, timings::Size { nodes: 3, edges: 5, index_entries: 16 });
+//! ```
+
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// The stages every breakdown lists, in the order they are printed. Other
+/// phases follow in the order they first started.
+const STAGES: [&str; 5] = ["read", "parse", "graph", "analysis", "output"];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TIMINGS: Mutex<Timings> = Mutex::new(Timings::new());
+
+/// How big the graph got, each count the largest it was noted at.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Size {
+    pub nodes: usize,
+    pub edges: usize,
+    /// The slots allocated in the tables from names and edges to indices,
+    /// and in the packed edges of a `Csr`.
+    pub index_entries: usize,
+}
+
+/// What a run spent in each phase, how long it took in all and how big the
+/// graph got.
+#[derive(Debug, Clone)]
+pub struct Breakdown {
+    pub phases: Vec<(&'static str, Duration)>,
+    pub total: Duration,
+    pub peak: Size,
+}
+
+struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+    /// The innermost phase running and since when it has been counted.
+    current: Option<(&'static str, Instant)>,
+    started: Option<Instant>,
+    peak: Size,
+}
+
+impl Timings {
+    const fn new() -> Self {
+        Timings { phases: Vec::new(), current: None, started: None, peak: Size { nodes: 0, edges: 0, index_entries: 0 } }
+    }
+
+    /// Counts the time since the last switch for the phase that was
+    /// running, and goes on counting for `phase`. Returns the phase that
+    /// was running.
+    fn switch(&mut self, phase: Option<&'static str>) -> Option<&'static str> {
+        let now = Instant::now();
+        let previous = self.current.map(|(name, since)| {
+            match self.phases.iter_mut().find(|(other, _)| *other == name) {
+                Some((_, time)) => *time += now - since,
+                None => self.phases.push((name, now - since)),
+            }
+            name
+        });
+        self.current = phase.map(|name| (name, now));
+        previous
+    }
+}
+
+fn timings() -> std::sync::MutexGuard<'static, Timings> {
+    TIMINGS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Starts timing the phases of a run, forgetting any run before.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    let mut timings = timings();
+    *timings = Timings::new();
+    timings.phases = STAGES.iter().map(|&stage| (stage, Duration::ZERO)).collect();
+    timings.started = Some(Instant::now());
+}
+
+/// Whether the phases are timed, to skip working out what they would note.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Notes how big the graph is, keeping the largest of each count.
+pub fn note_size(nodes: usize, edges: usize, index_entries: usize) {
+    if !enabled() {
+        return;
+    }
+    let peak = &mut timings().peak;
+    peak.nodes = peak.nodes.max(nodes);
+    peak.edges = peak.edges.max(edges);
+    peak.index_entries = peak.index_entries.max(index_entries);
+}
+
+/// The breakdown of the run so far, ending the phase that is running.
+pub fn take() -> Breakdown {
+    let mut timings = timings();
+    timings.switch(None);
+    let total = timings.started.map_or(Duration::ZERO, |started| started.elapsed());
+    Breakdown { phases: timings.phases.clone(), total, peak: timings.peak }
+}
+
+/// Times a phase of the work until it is dropped, pausing the phase it
+/// started in.
+#[must_use = "the phase ends when it is dropped"]
+pub struct Phase {
+    /// The phase to go back to, when timing.
+    outer: Option<Option<&'static str>>,
+}
+
+impl Phase {
+    pub fn start(name: &'static str) -> Self {
+        if !enabled() {
+            return Phase { outer: None };
+        }
+        Phase { outer: Some(timings().switch(Some(name))) }
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        if let Some(outer) = self.outer {
+            timings().switch(outer);
+        }
+    }
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Timings:")?;
+        for &(name, time) in &self.phases {
+            writeln!(f, "  {:<10} {:>10.3}s", name, time.as_secs_f64())?;
+        }
+        writeln!(f, "  {:<10} {:>10.3}s", "total", self.total.as_secs_f64())?;
+        write!(
+            f, "Peak: {} nodes, {} edges, {} index entries",
+            self.peak.nodes, self.peak.edges, self.peak.index_entries,
+        )
+    }
+}
@@ -0,0 +1,514 @@
+//! Walks over the items of a graph as iterators, so a caller can stop
+//! early or stream through a huge graph without collecting every index
+//! first. Each walk has a reverse variant following the edges the other
+//! way. The edges are packed into a `Csr` the first time a walk needs
+//! them. Walks never visit an item twice, so they end on cyclic graphs too.
+//! `write_tree` draws a `TreeWalk` the way the `tree` command prints it.
+//!
+//! ```
+//! use depsys::graph::GraphBuilder;
+//! use depsys::traverse::Traverse;
+//!
+//! let mut graph = GraphBuilder::new(false);
+//! graph.add_edge_by_name("app", "lib")?;
+//! graph.add_edge_by_name("app", "log")?;
+//! graph.add_edge_by_name("lib", "app")?;
+//! let items = graph.build();
+//! assert_eq!(items.bfs_from(0).collect::<Vec<_>>(), [0, 1, 2]);
+//! assert_eq!(items.reverse_dfs_from(2).collect::<Vec<_>>(), [2, 0, 1]);
+//! # Ok::<(), depsys::error::DepsError>(())
+//! ```
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt;
+use std::io::Write;
+use clap::ValueEnum;
+use crate::csr::Csr;
+use crate::error::DepsError;
+use crate::graph::Item;
+use crate::report;
+
+/// Iterators over the items of a graph, given as a slice of them.
+pub trait Traverse<T> {
+    /// The items reachable from `start` depth first, `start` first and
+    /// every item before the dependencies it reaches first.
+    fn dfs_from(&self, start: usize) -> Dfs<'_, T>;
+
+    /// The items reachable from `start` breadth first, nearest first.
+    fn bfs_from(&self, start: usize) -> Bfs<'_, T>;
+
+    /// The items depending on `start` directly or not, depth first.
+    fn reverse_dfs_from(&self, start: usize) -> Dfs<'_, T>;
+
+    /// The items depending on `start` directly or not, nearest first.
+    fn reverse_bfs_from(&self, start: usize) -> Bfs<'_, T>;
+
+    /// Every item after its dependencies, those that are ready at the same
+    /// time in input order. Items on or behind a cycle are left out, see
+    /// `Topological::unplaced`.
+    fn topological_order(&self) -> Topological<'_, T>
+    where
+        T: Ord;
+
+    /// Every item after the items that depend on it.
+    fn reverse_topological_order(&self) -> Topological<'_, T>
+    where
+        T: Ord;
+}
+
+impl<T> Traverse<T> for [Item<T>] {
+    fn dfs_from(&self, start: usize) -> Dfs<'_, T> {
+        Dfs(TreeWalk::new(Edges::forward(self), start, None))
+    }
+
+    fn bfs_from(&self, start: usize) -> Bfs<'_, T> {
+        Bfs::new(Edges::forward(self), start)
+    }
+
+    fn reverse_dfs_from(&self, start: usize) -> Dfs<'_, T> {
+        Dfs(TreeWalk::new(Edges::reverse(self), start, None))
+    }
+
+    fn reverse_bfs_from(&self, start: usize) -> Bfs<'_, T> {
+        Bfs::new(Edges::reverse(self), start)
+    }
+
+    fn topological_order(&self) -> Topological<'_, T>
+    where
+        T: Ord,
+    {
+        Topological::new(self, Ready::InOrder(VecDeque::new()), false)
+    }
+
+    fn reverse_topological_order(&self) -> Topological<'_, T>
+    where
+        T: Ord,
+    {
+        Topological::new(self, Ready::InOrder(VecDeque::new()), true)
+    }
+}
+
+/// The edges a walk follows out of every item: its dependencies, or the
+/// items depending on it, which are only packed once asked for unless they
+/// were packed already.
+pub struct Edges<'a, T> {
+    items: &'a [Item<T>],
+    reverse: bool,
+    graph: OnceCell<Cow<'a, Csr>>,
+}
+
+impl<'a, T> Edges<'a, T> {
+    /// The dependencies of the items.
+    pub fn forward(items: &'a [Item<T>]) -> Self {
+        Edges { items, reverse: false, graph: OnceCell::new() }
+    }
+
+    /// The items depending on each item.
+    pub fn reverse(items: &'a [Item<T>]) -> Self {
+        Edges { items, reverse: true, graph: OnceCell::new() }
+    }
+
+    /// The edges of `graph`, packed already from `items` one way or the
+    /// other.
+    pub fn packed(items: &'a [Item<T>], graph: &'a Csr) -> Self {
+        Edges { items, reverse: false, graph: OnceCell::from(Cow::Borrowed(graph)) }
+    }
+
+    /// The items the edges out of `index` go to.
+    pub fn from(&self, index: usize) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator + '_ {
+        let graph = self.graph.get_or_init(|| {
+            Cow::Owned(if self.reverse { Csr::reverse(self.items) } else { Csr::forward(self.items) })
+        });
+        graph.targets(index)
+    }
+
+    /// How many items there are.
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+/// How an item is come upon by a `TreeWalk`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    /// For the first time, after which the walk goes on into its edges.
+    First,
+    /// Again, by another way than the first.
+    Again,
+    /// From one of the items the walk went through to get to it.
+    Cycle,
+}
+
+/// One step of a `TreeWalk`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    /// The item come upon.
+    pub index: usize,
+    /// How many edges away from the start the walk came upon it.
+    pub depth: usize,
+    /// Whether it is the last item the edges out of the previous one go to.
+    pub last: bool,
+    pub visit: Visit,
+}
+
+/// Follows every edge out of the items reachable from a start depth first,
+/// as `cargo tree` draws them: every time an edge is followed it yields
+/// the item it goes to, but only goes on from an item the first time.
+pub struct TreeWalk<'a, T> {
+    edges: Edges<'a, T>,
+    /// How many edges deep to go at most.
+    depth: Option<usize>,
+    /// Each entry is an item, its depth and whether it is the last item of
+    /// the edges it was reached by.
+    stack: Vec<(usize, usize, bool)>,
+    shown: Vec<bool>,
+    /// The items walked through to the current one, starting with the
+    /// start, and whether each item is one of them.
+    ancestors: Vec<usize>,
+    on_path: Vec<bool>,
+    cut: bool,
+}
+
+impl<'a, T> TreeWalk<'a, T> {
+    /// Walks from `start` along `edges`, going at most `depth` of them
+    /// deep.
+    pub fn new(edges: Edges<'a, T>, start: usize, depth: Option<usize>) -> Self {
+        let len = edges.len();
+        TreeWalk {
+            edges,
+            depth,
+            stack: vec![(start, 0, true)],
+            shown: vec![false; len],
+            ancestors: Vec::new(),
+            on_path: vec![false; len],
+            cut: false,
+        }
+    }
+
+    /// Whether the walk has come upon an item with edges out of it that it
+    /// didn't follow, being as deep as it may go.
+    pub fn cut(&self) -> bool {
+        self.cut
+    }
+}
+
+impl<T> Iterator for TreeWalk<'_, T> {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        let (index, depth, last) = self.stack.pop()?;
+        for ancestor in self.ancestors.drain(depth..) {
+            self.on_path[ancestor] = false;
+        }
+        let visit = if self.on_path[index] {
+            Visit::Cycle
+        } else if std::mem::replace(&mut self.shown[index], true) {
+            Visit::Again
+        } else {
+            Visit::First
+        };
+        if visit == Visit::First && self.depth.is_some_and(|limit| depth >= limit) {
+            self.cut |= self.edges.from(index).next().is_some();
+        } else if visit == Visit::First {
+            self.ancestors.push(index);
+            self.on_path[index] = true;
+            let next = self.edges.from(index);
+            let count = next.len();
+            for (i, other) in next.enumerate().rev() {
+                self.stack.push((other, depth + 1, i + 1 == count));
+            }
+        }
+        Some(Step { index, depth, last, visit })
+    }
+}
+
+/// The characters `tree` draws branches with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Charset {
+    /// Box-drawing characters
+    Utf8,
+    /// Plain ASCII, for logs that mangle anything else
+    Ascii,
+}
+
+/// Draws the tree of the items reachable from `root` along `edges` like
+/// `cargo tree` does, going at most `depth` edges deep, or `TREE_DEPTH`
+/// with a warning when the tree goes deeper and no depth was given. Items
+/// that were already shown are marked `(*)` instead of repeating them, and
+/// items that come back round to one of their own ancestors are marked
+/// `(cycle)`.
+pub fn write_tree<T: fmt::Display>(
+    out: &mut impl Write,
+    items: &[Item<T>],
+    edges: Edges<'_, T>,
+    root: usize,
+    depth: Option<usize>,
+    charset: Charset,
+) -> Result<(), DepsError> {
+    let (middle, last_branch, down) = match charset {
+        Charset::Utf8 => ("├── ", "└── ", "│   "),
+        Charset::Ascii => ("|-- ", "`-- ", "|   "),
+    };
+    let mut walk = TreeWalk::new(edges, root, Some(depth.unwrap_or(TREE_DEPTH)));
+    // What the item last shown at every depth adds to the prefix of the
+    // lines of its children. Prefixes are put together line by line, as
+    // keeping one for every depth would take memory in the square of it.
+    let mut downs: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    for Step { index, depth, last, visit } in walk.by_ref() {
+        line.clear();
+        if depth > 0 {
+            downs[..depth].iter().for_each(|down| line.push_str(down));
+            line.push_str(if last { last_branch } else { middle });
+        }
+        let marker = match visit {
+            Visit::First => "",
+            Visit::Again => " (*)",
+            Visit::Cycle => " (cycle)",
+        };
+        writeln!(out, "{}{}{}", line, *items[index], marker)?;
+        if visit == Visit::First {
+            downs.truncate(depth);
+            downs.push(match depth {
+                0 => "",
+                _ if last => "    ",
+                _ => down,
+            });
+        }
+    }
+    if depth.is_none() && walk.cut() {
+        report::warning(format_args!("Only drew the first {} levels of the tree, give --depth to draw more", TREE_DEPTH));
+    }
+    Ok(())
+}
+
+/// How many edges deep `tree` and `deps --tree` go when no depth is given.
+/// Every level is indented further, so the tree of a long chain would be
+/// as wide as it is long.
+pub const TREE_DEPTH: usize = 100;
+
+/// The items reachable from a start depth first, each once.
+pub struct Dfs<'a, T>(TreeWalk<'a, T>);
+
+impl<T> Iterator for Dfs<'_, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.0.by_ref().find(|step| step.visit == Visit::First).map(|step| step.index)
+    }
+}
+
+/// The items reachable from a start breadth first, each once.
+pub struct Bfs<'a, T> {
+    edges: Edges<'a, T>,
+    depth: Option<usize>,
+    queue: VecDeque<(usize, usize)>,
+    seen: Vec<bool>,
+}
+
+impl<'a, T> Bfs<'a, T> {
+    fn new(edges: Edges<'a, T>, start: usize) -> Self {
+        let mut seen = vec![false; edges.len()];
+        seen[start] = true;
+        Bfs { edges, depth: None, queue: VecDeque::from([(start, 0)]), seen }
+    }
+
+    /// Only goes `depth` edges away from the start, or everywhere with
+    /// `None`.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.depth = depth;
+        self
+    }
+}
+
+impl<T> Iterator for Bfs<'_, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let (index, distance) = self.queue.pop_front()?;
+        if self.depth.is_none_or(|depth| distance < depth) {
+            for other in self.edges.from(index) {
+                if !std::mem::replace(&mut self.seen[other], true) {
+                    self.queue.push_back((other, distance + 1));
+                }
+            }
+        }
+        Some(index)
+    }
+}
+
+/// The items that are ready to be sorted, taken in the order they became
+/// ready, smallest first when stable, or highest ranked first.
+pub(crate) enum Ready<'a, T> {
+    InOrder(VecDeque<usize>),
+    Smallest(BinaryHeap<Reverse<(&'a T, usize)>>),
+    Highest(Vec<usize>, BinaryHeap<(usize, Reverse<(&'a T, usize)>)>),
+}
+
+impl<'a, T: Ord> Ready<'a, T> {
+    fn push(&mut self, items: &'a [Item<T>], index: usize) {
+        match self {
+            Ready::InOrder(queue) => queue.push_back(index),
+            Ready::Smallest(heap) => heap.push(Reverse((&items[index].data, index))),
+            Ready::Highest(ranks, heap) => heap.push((ranks[index], Reverse((&items[index].data, index)))),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Ready::InOrder(queue) => queue.len(),
+            Ready::Smallest(heap) => heap.len(),
+            Ready::Highest(_, heap) => heap.len(),
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        match self {
+            Ready::InOrder(queue) => queue.pop_front(),
+            Ready::Smallest(heap) => heap.pop().map(|Reverse((_, index))| index),
+            Ready::Highest(_, heap) => heap.pop().map(|(_, Reverse((_, index)))| index),
+        }
+    }
+}
+
+/// The items in topological order, each next one taken among those whose
+/// dependencies, or dependents in reverse, have all been taken.
+pub struct Topological<'a, T> {
+    items: &'a [Item<T>],
+    /// The edges to the items that taking an item brings closer to ready.
+    edges: Edges<'a, T>,
+    queue: Ready<'a, T>,
+    /// How many of the edges into every item are left to take.
+    waiting: Vec<usize>,
+    levels: Vec<usize>,
+    widest: usize,
+}
+
+impl<'a, T: Ord> Topological<'a, T> {
+    pub(crate) fn new(items: &'a [Item<T>], mut queue: Ready<'a, T>, reverse: bool) -> Self {
+        let (edges, waiting) = if reverse {
+            let mut waiting = vec![0; items.len()];
+            for &dep in items.iter().flat_map(|item| &item.deps) {
+                waiting[dep] += 1;
+            }
+            (Edges::forward(items), waiting)
+        } else {
+            (Edges::reverse(items), items.iter().map(|item| item.deps.len()).collect())
+        };
+        for (index, &count) in waiting.iter().enumerate() {
+            if count == 0 {
+                queue.push(items, index);
+            }
+        }
+        let widest = queue.len();
+        Topological { items, edges, queue, waiting, levels: vec![0; items.len()], widest }
+    }
+
+    /// The level of every item taken so far by its index, the wave of the
+    /// sort in which it became ready.
+    pub fn levels(&self) -> &[usize] {
+        &self.levels
+    }
+
+    /// The most items that were ready at once so far, the width of the
+    /// graph once every item is taken.
+    pub fn widest(&self) -> usize {
+        self.widest
+    }
+
+    /// The items that are still waiting, which once the iterator is done
+    /// are the items on a cycle or depending on one.
+    pub fn unplaced(&self) -> Vec<usize> {
+        (0..self.waiting.len()).filter(|&index| self.waiting[index] > 0).collect()
+    }
+}
+
+impl<T: Ord> Iterator for Topological<'_, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let index = self.queue.pop()?;
+        for other in self.edges.from(index) {
+            self.waiting[other] -= 1;
+            self.levels[other] = self.levels[other].max(self.levels[index] + 1);
+            if self.waiting[other] == 0 {
+                self.queue.push(self.items, other);
+            }
+        }
+        self.widest = self.widest.max(self.queue.len());
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_graph;
+
+    #[test]
+    fn every_walk_yields_valid_indices_once() {
+        for seed in 0..50 {
+            let len = 1 + seed as usize % 20;
+            let items = random_graph(seed, len);
+            let valid_and_unique = |indices: &[usize]| {
+                let mut seen = vec![false; len];
+                indices.iter().all(|&index| index < len && !std::mem::replace(&mut seen[index], true))
+            };
+            for start in 0..len {
+                for walk in [
+                    items.dfs_from(start).collect::<Vec<_>>(),
+                    items.bfs_from(start).collect(),
+                    items.reverse_dfs_from(start).collect(),
+                    items.reverse_bfs_from(start).collect(),
+                ] {
+                    assert_eq!(walk[0], start);
+                    assert!(valid_and_unique(&walk), "seed {}: {:?}", seed, walk);
+                }
+            }
+            for mut order in [items.topological_order(), items.reverse_topological_order()] {
+                let mut indices: Vec<_> = order.by_ref().collect();
+                assert!(valid_and_unique(&indices), "seed {}: {:?}", seed, indices);
+                indices.extend(order.unplaced());
+                assert!(indices.len() == len && valid_and_unique(&indices), "seed {}: {:?}", seed, indices);
+            }
+        }
+    }
+
+    fn tree(items: &[Item<String>], root: usize, depth: Option<usize>, charset: Charset) -> String {
+        let mut out = Vec::new();
+        write_tree(&mut out, items, Edges::forward(items), root, depth, charset).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn trees_carry_the_branches_of_every_open_level() {
+        let mut graph = crate::graph::GraphBuilder::new(false);
+        for (from, to) in [("a", "b"), ("a", "c"), ("b", "d"), ("b", "e"), ("d", "f"), ("c", "g")] {
+            graph.add_edge_by_name(from, to).unwrap();
+        }
+        let items = graph.build();
+        assert_eq!(tree(&items, 0, None, Charset::Utf8), concat!(
+            "a\n",
+            "├── b\n",
+            "│   ├── d\n",
+            "│   │   └── f\n",
+            "│   └── e\n",
+            "└── c\n",
+            "    └── g\n",
+        ));
+        assert_eq!(tree(&items, 0, Some(1), Charset::Ascii), "a\n|-- b\n`-- c\n");
+        assert_eq!(tree(&items, 6, None, Charset::Utf8), "g\n");
+    }
+
+    #[test]
+    fn trees_stop_at_the_default_depth_without_one() {
+        let chain: Vec<_> = (0..150).map(|index| Item::new(index.to_string(), (index + 1..150.min(index + 2)).collect())).collect();
+        let drawn = tree(&chain, 0, None, Charset::Ascii);
+        assert_eq!(drawn.lines().count(), TREE_DEPTH + 1);
+        assert!(drawn.lines().last().unwrap().ends_with("`-- 100"));
+        assert_eq!(tree(&chain, 0, Some(120), Charset::Ascii).lines().count(), 121);
+    }
+}
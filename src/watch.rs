@@ -0,0 +1,126 @@
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use depsys::report::{self, Style};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The longest we sleep at once, so Ctrl-C is noticed quickly.
+const TICK: Duration = Duration::from_millis(100);
+
+/// What is known about an input when it is looked at: when it was last
+/// modified and how long it is, or nothing while it can't be read, as
+/// happens halfway through an editor saving it.
+type Stamp = Vec<Option<(SystemTime, u64)>>;
+
+/// Runs `run` once, and again every time one of `paths` changes, until
+/// Ctrl-C is pressed. The screen is cleared before each run when stdout is
+/// a terminal. Returns the exit code of the last run.
+pub fn watch(paths: &[String], interval: Duration, mut run: impl FnMut() -> ExitCode) -> ExitCode {
+    catch_interrupts();
+    let mut stamp = stamp(paths);
+    loop {
+        if io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+            let _ = io::stdout().flush();
+        }
+        let header = format!("[{}] {}", clock(), paths.join(" "));
+        report::info(report::highlight(Style::Green, header));
+        let code = run();
+
+        // Wait for a change, then for the files to stop changing, so a
+        // file truncated and then written is read once it is whole.
+        loop {
+            if !sleep(interval) {
+                return code;
+            }
+            let next = self::stamp(paths);
+            if next != stamp {
+                stamp = next;
+                break;
+            }
+        }
+        loop {
+            if !sleep(interval) {
+                return code;
+            }
+            let next = self::stamp(paths);
+            if next == stamp {
+                break;
+            }
+            stamp = next;
+        }
+    }
+}
+
+fn stamp(paths: &[String]) -> Stamp {
+    paths.iter()
+        .map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+/// Sleeps for `duration`, returning false if Ctrl-C was pressed meanwhile.
+fn sleep(duration: Duration) -> bool {
+    let mut left = duration;
+    while !left.is_zero() {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return false;
+        }
+        let step = left.min(TICK);
+        thread::sleep(step);
+        left -= step;
+    }
+    !INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// The time of day as `HH:MM:SS`, for the header of each run.
+fn clock() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let seconds = time_of_day(seconds);
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Turns Ctrl-C into a request to stop watching, so the exit code of the
+/// last run can be returned.
+#[cfg(unix)]
+fn catch_interrupts() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+    // SAFETY: the handler only stores to an atomic, which is safe to do
+    // from a signal handler.
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Elsewhere Ctrl-C ends the process as usual.
+#[cfg(not(unix))]
+fn catch_interrupts() {}
+
+/// The seconds since local midnight.
+#[cfg(unix)]
+fn time_of_day(seconds: u64) -> u64 {
+    let time = seconds as libc::time_t;
+    // SAFETY: `tm` is plain data that `localtime_r` fills in, and it is
+    // only read when the call succeeded.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return seconds % 86_400;
+        }
+        (tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec) as u64
+    }
+}
+
+/// The seconds since midnight UTC, where the local time zone isn't known.
+#[cfg(not(unix))]
+fn time_of_day(seconds: u64) -> u64 {
+    seconds % 86_400
+}
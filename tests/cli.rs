@@ -0,0 +1,989 @@
+//! Runs the binary the way scripts and CI use it, pinning down its exit
+//! codes and what goes to stdout and stderr.
+
+#![cfg(feature = "serde")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use assert_cmd::Command;
+use predicates::prelude::*;
+use serde_json::{Value, json};
+use tempfile::TempDir;
+
+/// The binary, run in `dir` so no dep-sys.toml above it is read.
+fn dep_sys(dir: &TempDir) -> Command {
+    let mut command = Command::cargo_bin("dep-sys-rs").unwrap();
+    command.current_dir(dir.path()).env_remove("RUST_LOG").env_remove("NO_COLOR");
+    command
+}
+
+/// Writes `contents` to `name` in `dir`.
+fn file(dir: &TempDir, name: &str, contents: &str) -> PathBuf {
+    let path = dir.path().join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn a_clean_graph_exits_with_0() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    dep_sys(&dir).args(["check", path(&graph)]).assert()
+        .code(0)
+        .stdout("The graph has no circular dependencies\n")
+        .stderr("");
+}
+
+#[test]
+fn a_cycle_exits_with_1() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c; c -> a }");
+    dep_sys(&dir).args(["check", path(&graph)]).assert()
+        .code(1)
+        .stdout("")
+        .stderr("Circular dependency detected: a -> b -> c -> a\n");
+    dep_sys(&dir).args(["sort", path(&graph)]).assert()
+        .code(1)
+        .stdout("")
+        .stderr(predicate::str::contains("ERROR: Circular dependency detected: a -> b -> c -> a"));
+}
+
+#[test]
+fn the_cycle_printed_is_made_of_edges_of_the_input() {
+    let dir = TempDir::new().unwrap();
+    for len in [3, 5] {
+        // Entered from a root, with a shortcut across it and a tail off it.
+        let mut edges = vec![("root".to_string(), "n1".to_string())];
+        edges.extend((0..len).map(|index| (format!("n{}", index), format!("n{}", (index + 1) % len))));
+        edges.push(("n0".to_string(), format!("n{}", len / 2)));
+        edges.push((format!("n{}", len - 1), "tail".to_string()));
+        let statements: Vec<_> = edges.iter().map(|(from, to)| format!("{} -> {}", from, to)).collect();
+        let graph = file(&dir, "graph.dot", &format!("digraph {{ {} }}", statements.join("; ")));
+
+        for command in ["check", "sort"] {
+            let output = dep_sys(&dir).args([command, path(&graph)]).assert().code(1).get_output().stderr.clone();
+            let message = String::from_utf8(output).unwrap();
+            let chain = message.trim_end().rsplit(": ").next().unwrap();
+            let names: Vec<_> = chain.split(" -> ").collect();
+            assert!(names.len() >= 3 && names.first() == names.last(), "{}", message);
+            for pair in names.windows(2) {
+                assert!(edges.contains(&(pair[0].to_string(), pair[1].to_string())), "{} -> {} is not an edge", pair[0], pair[1]);
+            }
+        }
+    }
+}
+
+#[test]
+fn usage_and_io_errors_exit_with_2() {
+    let dir = TempDir::new().unwrap();
+    dep_sys(&dir).args(["check", "missing.dot"]).assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::starts_with("ERROR: Could not read missing.dot:").and(predicate::str::contains("panicked").not()));
+    dep_sys(&dir).args(["check", "--no-such-option", "graph.dot"]).assert()
+        .code(2)
+        .stderr(predicate::str::contains("unexpected argument '--no-such-option'"));
+    let graph = file(&dir, "graph.dot", "digraph { a -> b }");
+    dep_sys(&dir).args(["deps", path(&graph), "nowhere"]).assert()
+        .code(2)
+        .stderr(predicate::str::contains("nowhere"));
+}
+
+#[test]
+fn input_that_cannot_be_parsed_exits_with_3() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "broken.dot", "digraph { a -> }");
+    dep_sys(&dir).args(["check", path(&graph)]).assert()
+        .code(3)
+        .stdout("")
+        .stderr(predicate::str::starts_with("ERROR: Could not parse").and(predicate::str::contains("panicked").not()));
+    let graph = file(&dir, "undirected.dot", "graph { a -- b }");
+    dep_sys(&dir).args(["check", path(&graph)]).assert()
+        .code(3)
+        .stderr(predicate::str::contains("--treat-undirected-as"));
+}
+
+#[test]
+fn a_failing_run_fails() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    dep_sys(&dir).args(["run", path(&graph), "--exec", "test {} != b"]).assert()
+        .code(1)
+        .stderr(predicate::str::contains("ERROR: b failed"));
+    dep_sys(&dir).args(["run", path(&graph), "--exec", "true"]).assert().code(0);
+}
+
+#[test]
+fn a_graph_can_be_piped_in() {
+    let dir = TempDir::new().unwrap();
+    dep_sys(&dir).args(["sort", "-"]).write_stdin("digraph { a -> b; b -> c }").assert()
+        .code(0)
+        .stdout("c\nb\na\n");
+    dep_sys(&dir).args(["check", "-"]).write_stdin("digraph { a -> b; b -> a }").assert()
+        .code(1)
+        .stderr("Circular dependency detected: a -> b -> a\n");
+}
+
+#[test]
+fn a_condensed_graph_sorts() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c; c -> a; c -> d; d -> e; e -> d; x -> a; a -> d }");
+    let condensed = dep_sys(&dir).args(["condense", path(&graph)]).assert().code(0).get_output().stdout.clone();
+    dep_sys(&dir).args(["sort", "-"]).write_stdin(condensed).assert()
+        .code(0)
+        .stdout("d+e\na+b+c\nx\n")
+        .stderr("");
+}
+
+#[test]
+fn applied_breaks_pass_check() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c; c -> a; b -> d; d -> a; d -> d; x -> y; y -> z; z -> x; z -> y; c -> x }");
+    dep_sys(&dir).args(["suggest-breaks", path(&graph)]).assert()
+        .code(0)
+        .stdout("a -> b (on 2 cycles)\ny -> z (on 2 cycles)\nd -> d (on 1 cycles)\n");
+
+    let broken = dir.path().join("broken.dot");
+    dep_sys(&dir).args(["suggest-breaks", "--apply", "-o", path(&broken), path(&graph)]).assert().code(0);
+    let applied = fs::read_to_string(&broken).unwrap();
+    assert!(applied.starts_with("// removed to break cycles: a -> b\n"), "{}", applied);
+    dep_sys(&dir).args(["check", path(&broken)]).assert()
+        .code(0)
+        .stdout("The graph has no circular dependencies\n");
+}
+
+#[test]
+fn empty_standard_input_is_an_error() {
+    let dir = TempDir::new().unwrap();
+    for stdin in ["", " \n\t\n"] {
+        dep_sys(&dir).args(["check", "-"]).write_stdin(stdin).assert()
+            .code(2)
+            .stdout("")
+            .stderr("ERROR: standard input is empty, expected a graph\n");
+    }
+}
+
+#[test]
+fn parse_errors_point_at_the_line_and_column() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "broken.dot", "digraph {\n  a -> b;\n\tb -> ; }\n");
+    dep_sys(&dir).args(["check", path(&graph)]).assert()
+        .code(3)
+        .stderr(format!(
+            "ERROR: Could not parse {0}: expected vertex\n --> {0}:3:7\n  |\n3 |     b -> ; }}\n  |          ^\n",
+            path(&graph),
+        ));
+}
+
+#[test]
+fn long_lines_are_cut_around_the_error() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "long.dot", &format!("digraph {{ a -> b; {} -> ; }}", "x".repeat(500)));
+    let output = dep_sys(&dir).args(["check", path(&graph)]).assert().code(3).get_output().stderr.clone();
+    let stderr = String::from_utf8(output).unwrap();
+    assert!(stderr.contains(&format!("{}:1:", path(&graph))), "{}", stderr);
+    let snippet = stderr.lines().find(|line| line.starts_with("1 | ")).unwrap();
+    assert!(snippet.contains('…') && snippet.ends_with("xxx -> ; }"), "{}", snippet);
+    assert!(snippet.chars().count() < 120, "{}", snippet);
+    // The caret is under the `;` that has no vertex before it.
+    let caret = stderr.lines().last().unwrap();
+    assert_eq!(caret.chars().count(), snippet.find(" -> ;").map(|at| snippet[..at].chars().count()).unwrap() + 5);
+}
+
+#[test]
+fn quiet_checks_only_answer_with_the_exit_code() {
+    let dir = TempDir::new().unwrap();
+    let clean = file(&dir, "clean.dot", "digraph { a -> b }");
+    let cyclic = file(&dir, "cyclic.dot", "digraph { a -> b; b -> a; c -> c }");
+    let broken = file(&dir, "broken.dot", "digraph { a -> ");
+    for (input, code) in [(path(&clean), 0), (path(&cyclic), 1), ("missing.dot", 2), (path(&broken), 3)] {
+        dep_sys(&dir).args(["check", "--quiet", input]).assert().code(code).stdout("").stderr("");
+    }
+}
+
+/// A build script that logs every node it is run for to `built` and fails
+/// for the node in `FAIL`, with the graph it runs over:
+/// `app` needs `api` and `web`, which both need `core`, and `docs` is on
+/// its own.
+fn build(dir: &TempDir) -> PathBuf {
+    file(dir, "build.sh", "echo \"$1\" >> built\n[ \"$1\" != \"$FAIL\" ]\n");
+    file(dir, "graph.dot", "digraph { app -> api; app -> web; api -> core; web -> core; docs }")
+}
+
+/// The nodes the build script was run for, in order.
+fn built(dir: &TempDir) -> Vec<String> {
+    let built = fs::read_to_string(dir.path().join("built")).unwrap_or_default();
+    built.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn a_run_stops_at_the_first_failure() {
+    let dir = TempDir::new().unwrap();
+    let graph = build(&dir);
+    dep_sys(&dir).args(["run", path(&graph), "--exec", "sh build.sh {}"]).env("FAIL", "api").assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "2 succeeded, 1 failed, 1 skipped, 1 not run\nFailed: api\nSkipped after a failed dependency: app\nNot run: web\n",
+        ));
+    assert_eq!(built(&dir), ["core", "docs", "api"]);
+}
+
+#[test]
+fn keep_going_skips_only_what_depends_on_a_failure() {
+    for (fail, succeeded, skipped, ran) in [
+        ("api", 3, "app", vec!["core", "docs", "api", "web"]),
+        ("core", 1, "api, web, app", vec!["core", "docs"]),
+        ("docs", 4, "", vec!["core", "docs", "api", "web", "app"]),
+    ] {
+        for jobs in ["1", "3"] {
+            let dir = TempDir::new().unwrap();
+            let graph = build(&dir);
+            let assert = dep_sys(&dir)
+                .args(["run", path(&graph), "--exec", "sh build.sh {}", "--keep-going", "-j", jobs])
+                .env("FAIL", fail)
+                .assert()
+                .code(1)
+                .stderr(predicate::str::contains(format!(
+                    "{} succeeded, 1 failed, {} skipped, 0 not run\nFailed: {}\n",
+                    succeeded, skipped.split(", ").filter(|name| !name.is_empty()).count(), fail,
+                )));
+            if !skipped.is_empty() {
+                assert.stderr(predicate::str::contains(format!("Skipped after a failed dependency: {}\n", skipped)));
+            }
+            let mut built = built(&dir);
+            let mut ran = ran.clone();
+            if jobs != "1" {
+                built.sort();
+                ran.sort();
+            }
+            assert_eq!(built, ran, "{} failing on {} jobs", fail, jobs);
+        }
+    }
+}
+
+#[test]
+fn a_dry_run_prints_the_commands_in_order() {
+    let dir = TempDir::new().unwrap();
+    let graph = build(&dir);
+    dep_sys(&dir).args(["run", path(&graph), "--exec", "sh build.sh {}", "--dry-run"]).assert()
+        .code(0)
+        .stdout("sh build.sh core\nsh build.sh docs\nsh build.sh api\nsh build.sh web\nsh build.sh app\n");
+    assert!(built(&dir).is_empty());
+}
+
+#[test]
+fn duplicate_edges_are_warned_about_by_name() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; c -> d; a -> b; c -> d; b -> c }");
+    dep_sys(&dir).args(["sort", path(&graph), "--warn-duplicates"]).assert()
+        .code(0)
+        .stdout("d\nc\nb\na\n")
+        .stderr("WARNING: Duplicate edge a -> b\nWARNING: Duplicate edge c -> d\n");
+    dep_sys(&dir).args(["sort", path(&graph)]).assert().stdout("d\nc\nb\na\n").stderr("");
+}
+
+/// Runs the binary and parses its stdout as a single JSON document.
+fn json_output(command: &mut Command, code: i32) -> Value {
+    let output = command.assert().code(code).get_output().stdout.clone();
+    serde_json::from_slice(&output).unwrap_or_else(|err| panic!("{}: {}", err, String::from_utf8_lossy(&output)))
+}
+
+#[test]
+fn check_json_reports_the_cycles() {
+    let dir = TempDir::new().unwrap();
+    let cyclic = file(&dir, "cyclic.dot", "digraph { a -> b; b -> c; c -> a; d -> d; c -> e }");
+    let report = json_output(dep_sys(&dir).args(["check", "--json", path(&cyclic)]), 1);
+    assert_eq!(report, json!({
+        "cycles": [{ "nodes": ["d"] }, { "nodes": ["a", "b", "c"] }],
+        "node_count": 5,
+        "edge_count": 5,
+    }));
+
+    let clean = file(&dir, "clean.dot", "digraph { a -> b; b -> c }");
+    dep_sys(&dir).args(["check", "--json", path(&clean)]).assert()
+        .stderr("The graph has no circular dependencies\n");
+    let report = json_output(dep_sys(&dir).args(["check", "--json", path(&clean)]), 0);
+    assert_eq!(report, json!({ "cycles": [], "node_count": 3, "edge_count": 2 }));
+}
+
+#[test]
+fn stats_pin_the_shape_of_a_known_graph() {
+    let dir = TempDir::new().unwrap();
+    let dag = file(&dir, "dag.dot", "digraph { app -> api; app -> ui; api -> core; ui -> core; api -> db; cli -> core; lone }");
+    dep_sys(&dir).args(["stats", path(&dag)]).assert()
+        .code(0)
+        .stdout(concat!(
+            "Nodes: 7\n",
+            "Edges: 6\n",
+            "Roots: 3\n",
+            "Leaves: 3\n",
+            "Isolated: 1\n",
+            "Clusters: 0\n",
+            "Fan-out: max 2 (app, api), average 0.86\n",
+            "Fan-in: max 3 (core), average 0.86\n",
+            "Longest chain: 2\n",
+            "Components with cycles: 0\n",
+            "DAG: yes\n",
+        ));
+    let report = json_output(dep_sys(&dir).args(["stats", "--json", path(&dag)]), 0);
+    assert_eq!(report, json!({
+        "nodes": 7,
+        "edges": 6,
+        "roots": 3,
+        "leaves": 3,
+        "isolated": 1,
+        "clusters": 0,
+        "fan_out": { "max": 2, "top": ["app", "api"], "average": 6.0 / 7.0 },
+        "fan_in": { "max": 3, "top": ["core"], "average": 6.0 / 7.0 },
+        "longest_chain": 2,
+        "cyclic_components": 0,
+        "is_dag": true,
+    }));
+
+    let cyclic = file(&dir, "cyclic.dot", "digraph { a -> b; b -> a; b -> c; d -> d }");
+    let report = json_output(dep_sys(&dir).args(["stats", "--json", path(&cyclic)]), 0);
+    assert_eq!(report["longest_chain"], Value::Null);
+    assert_eq!(report["cyclic_components"], 1);
+    assert_eq!(report["is_dag"], false);
+}
+
+#[test]
+fn rewired_sorts_keep_the_order_through_excluded_nodes() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", r#"digraph {
+        app -> "vendor/http"; "vendor/http" -> core; app -> ui
+        ui -> "vendor/log"; "vendor/log" -> "vendor/fmt"; "vendor/fmt" -> util; core -> util
+    }"#);
+    let output = dep_sys(&dir).args(["sort", path(&graph), "--exclude", "^vendor/", "--rewire"]).assert()
+        .code(0)
+        .get_output().stdout.clone();
+    let order: Vec<_> = String::from_utf8(output).unwrap().lines().map(str::to_string).collect();
+    let position = |name: &str| order.iter().position(|other| other == name).unwrap_or_else(|| panic!("{} is not sorted", name));
+    assert_eq!(order.len(), 4);
+    // Everything the remaining nodes depend on, directly or through vendor/.
+    for (dependent, dependency) in [("app", "core"), ("app", "ui"), ("app", "util"), ("ui", "util"), ("core", "util")] {
+        assert!(position(dependency) < position(dependent), "{} is sorted after {}", dependency, dependent);
+    }
+
+    // Without --rewire the edges through vendor/ are dropped with it.
+    dep_sys(&dir).args(["sort", path(&graph), "--exclude", "^vendor/"]).assert().stdout("ui\nutil\napp\ncore\n");
+    dep_sys(&dir).args(["sort", path(&graph), "--only", "^vendor/"]).assert().stdout("vendor/http\nvendor/fmt\nvendor/log\n");
+}
+
+#[test]
+fn removing_the_gateway_splits_its_graph_60_40() {
+    let dir = TempDir::new().unwrap();
+    let gateway = Path::new(env!("CARGO_MANIFEST_DIR")).join("gateway.dot");
+    // Six clients on one side of the gateway and four backends on the other.
+    let report = json_output(dep_sys(&dir).args(["bottlenecks", "--json", path(&gateway)]), 0);
+    assert_eq!(report, json!({
+        "articulation_points": [{ "node": "gateway", "separated": 4 }, { "node": "web", "separated": 1 }],
+        "bridges": [{ "from": "cli", "to": "web", "both_ways": false, "separated": 1 }],
+    }));
+
+    // Self-loops and separate components change nothing.
+    let graph = file(&dir, "graph.dot", "digraph { a -> a; a -> b; b -> a; x -> y }");
+    dep_sys(&dir).args(["bottlenecks", path(&graph)]).assert()
+        .code(0)
+        .stdout("Articulation points:\nBridges:\n  a <-> b (cuts off 1)\n  x -> y (cuts off 1)\n");
+}
+
+#[test]
+fn sort_json_orders_every_edge() {
+    let dir = TempDir::new().unwrap();
+    let edges = [("app", "api"), ("app", "web"), ("api", "core"), ("web", "core"), ("web", "ui"), ("ui", "core")];
+    let statements: Vec<_> = edges.iter().map(|(from, to)| format!("{} -> {};", from, to)).collect();
+    let graph = file(&dir, "graph.dot", &format!("digraph {{ {} docs }}", statements.join(" ")));
+    let sorted = json_output(dep_sys(&dir).args(["sort", "--json", path(&graph)]), 0);
+
+    let order: Vec<_> = sorted["order"].as_array().unwrap().iter().map(|name| name.as_str().unwrap()).collect();
+    assert_eq!(order.len(), 6);
+    let position = |name| order.iter().position(|&other| other == name).unwrap();
+    for (from, to) in edges {
+        assert!(position(to) < position(from), "{} comes before its dependency {}", from, to);
+    }
+    // Each node is in the level after the last of its dependencies, and
+    // the levels together are the order.
+    let levels: Vec<Vec<_>> = sorted["levels"].as_array().unwrap().iter()
+        .map(|level| level.as_array().unwrap().iter().map(|name| name.as_str().unwrap()).collect())
+        .collect();
+    let level = |name| levels.iter().position(|level| level.contains(&name)).unwrap();
+    for &name in &order {
+        let deps = edges.iter().filter(|&&(from, _)| from == name).map(|&(_, to)| level(to) + 1);
+        assert_eq!(level(name), deps.max().unwrap_or(0), "{} is in the wrong level", name);
+    }
+    assert_eq!(levels.concat(), order);
+}
+
+#[test]
+fn reversed_sorts_are_mirror_images() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { app -> api; app -> web; api -> core; web -> core; docs }");
+    let stdout = |args: &[&str]| String::from_utf8(dep_sys(&dir).args(args).assert().code(0).get_output().stdout.clone()).unwrap();
+    let mut order: Vec<_> = stdout(&["sort", path(&graph)]).lines().map(str::to_string).collect();
+    order.reverse();
+    assert_eq!(stdout(&["sort", "--reverse", path(&graph)]).lines().collect::<Vec<_>>(), order);
+
+    let sorted = json_output(dep_sys(&dir).args(["sort", "--json", path(&graph)]), 0);
+    let reversed = json_output(dep_sys(&dir).args(["sort", "--json", "--reverse", path(&graph)]), 0);
+    let mirrored = |value: &Value| {
+        let mut items = value.as_array().unwrap().clone();
+        items.reverse();
+        Value::Array(items)
+    };
+    assert_eq!(reversed["order"], mirrored(&sorted["order"]));
+    // The levels are taken from the order, so they read backwards too.
+    let levels: Vec<_> = sorted["levels"].as_array().unwrap().iter().rev().map(mirrored).collect();
+    assert_eq!(reversed["levels"], Value::Array(levels));
+}
+
+#[test]
+fn sort_json_reports_errors_as_json() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> a }");
+    let error = json_output(dep_sys(&dir).args(["sort", "--json", path(&graph)]), 1);
+    assert_eq!(error, json!({ "error": "Circular dependency detected: a -> b -> a" }));
+}
+
+#[test]
+fn a_failed_sort_leaves_the_output_file_alone() {
+    let dir = TempDir::new().unwrap();
+    let out = file(&dir, "order.txt", "the last good order\n");
+    let cyclic = file(&dir, "cyclic.dot", "digraph { a -> b; b -> a }");
+    dep_sys(&dir).args(["sort", path(&cyclic), "-o", path(&out)]).assert().code(1).stdout("");
+    assert_eq!(fs::read_to_string(&out).unwrap(), "the last good order\n");
+    let mut files: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    files.sort();
+    assert_eq!(files, ["cyclic.dot", "order.txt"]);
+
+    let clean = file(&dir, "clean.dot", "digraph { a -> b }");
+    dep_sys(&dir).args(["sort", path(&clean), "-o", path(&out)]).assert().code(0).stdout("");
+    assert_eq!(fs::read_to_string(&out).unwrap(), "b\na\n");
+}
+
+#[test]
+fn the_output_may_not_be_the_input() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b }");
+    dep_sys(&dir).args(["sort", "graph.dot", "-o", path(&graph)]).assert()
+        .code(2)
+        .stderr(predicate::str::contains("Refusing to write the output over the input"));
+    assert_eq!(fs::read_to_string(&graph).unwrap(), "digraph { a -> b }");
+}
+
+#[test]
+fn colors_are_only_used_when_asked_for() {
+    let dir = TempDir::new().unwrap();
+    let cyclic = file(&dir, "cyclic.dot", "digraph { a -> b; b -> a }");
+    let clean = file(&dir, "clean.dot", "digraph { a -> b }");
+    let escape = predicate::str::contains("\x1b[");
+    for input in [&cyclic, &clean] {
+        for command in ["check", "sort"] {
+            dep_sys(&dir).args([command, path(input), "--color", "never"]).assert()
+                .stdout(escape.clone().not())
+                .stderr(escape.clone().not());
+            // Neither stream is a terminal here, as in a CI log.
+            dep_sys(&dir).args([command, path(input)]).assert()
+                .stdout(escape.clone().not())
+                .stderr(escape.clone().not());
+        }
+    }
+    dep_sys(&dir).args(["check", path(&cyclic), "--color", "always"]).assert()
+        .stderr("Circular dependency detected: \x1b[31ma\x1b[0m -> \x1b[31mb\x1b[0m -> \x1b[31ma\x1b[0m\n");
+    dep_sys(&dir).args(["sort", path(&cyclic), "--color", "always"]).assert()
+        .stderr("\x1b[31mERROR:\x1b[0m Circular dependency detected: a -> b -> a\n");
+    dep_sys(&dir).args(["check", path(&clean), "--color", "always"]).assert()
+        .stdout("\x1b[32mThe graph has no circular dependencies\x1b[0m\n");
+    dep_sys(&dir).args(["check", path(&clean), "--color", "always"]).env("NO_COLOR", "1").assert()
+        .stdout(escape.clone());
+}
+
+#[test]
+fn why_takes_the_nodes_after_the_inputs() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    dep_sys(&dir).args(["why", path(&graph), "--", "a", "c"]).assert()
+        .code(0)
+        .stdout("a -> b -> c\n");
+    dep_sys(&dir).args(["why", path(&graph), "--from", "a", "--to", "c"]).assert()
+        .code(0)
+        .stdout("a -> b -> c\n");
+    dep_sys(&dir).args(["why", path(&graph), "--from", "a"]).assert()
+        .code(2)
+        .stderr(predicate::str::contains("--to <NODE>"));
+    dep_sys(&dir).args(["why", path(&graph), "--from", "a", "--to", "c", "--", "a", "c"]).assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+    dep_sys(&dir).args(["why", path(&graph)]).assert()
+        .code(2);
+}
+
+#[test]
+fn depth_takes_the_node_after_the_inputs() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c; c -> d; x -> c }");
+    dep_sys(&dir).args(["depth", path(&graph)]).assert()
+        .code(0)
+        .stdout("a -> b -> c -> d (length 3)\n");
+    dep_sys(&dir).args(["depth", path(&graph), "--", "x"]).assert()
+        .code(0)
+        .stdout("x -> c -> d (length 2)\n");
+    dep_sys(&dir).args(["depth", path(&graph), "--from", "x"]).assert()
+        .code(0)
+        .stdout("x -> c -> d (length 2)\n");
+    dep_sys(&dir).args(["depth", path(&graph), "--max", "2"]).assert()
+        .code(1)
+        .stderr(predicate::str::contains("The longest chain has length 3, more than the maximum of 2"));
+    dep_sys(&dir).args(["depth", path(&graph), "--from", "x", "--", "a"]).assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn extract_takes_the_nodes_after_the_inputs() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c; x -> b; z -> a; lone }");
+    let extracted = dep_sys(&dir).args(["extract", path(&graph), "--", "a", "x"]).assert().code(0).get_output().stdout.clone();
+    dep_sys(&dir).args(["extract", path(&graph), "--from", "a", "--", "x"]).assert().stdout(extracted.clone());
+    dep_sys(&dir).args(["extract", path(&graph), "--from", "a", "--from", "x"]).assert().stdout(extracted.clone());
+    // What both nodes depend on is only kept once, and the result sorts.
+    dep_sys(&dir).args(["sort", "-"]).write_stdin(extracted).assert()
+        .code(0)
+        .stdout("c\nb\na\nx\n");
+
+    let out = dir.path().join("out.dot");
+    dep_sys(&dir).args(["extract", path(&graph), "-o", path(&out), "--", "a", "nope"]).assert()
+        .code(2)
+        .stderr("ERROR: There is no node named nope, did you mean lone?\n");
+    assert!(!out.exists());
+    dep_sys(&dir).args(["extract", path(&graph)]).assert().code(2);
+}
+
+#[test]
+fn trees_mark_repeated_subtrees_and_cycles() {
+    let dir = TempDir::new().unwrap();
+    // A diamond on core, and a cycle from util back up to api.
+    let graph = file(&dir, "graph.dot", "digraph { app -> api; app -> ui; api -> core; ui -> core; core -> util; util -> api }");
+    dep_sys(&dir).args(["tree", path(&graph), "app"]).assert()
+        .code(0)
+        .stdout(concat!(
+            "app\n",
+            "├── api\n",
+            "│   └── core\n",
+            "│       └── util\n",
+            "│           └── api (cycle)\n",
+            "└── ui\n",
+            "    └── core (*)\n",
+        ));
+    dep_sys(&dir).args(["tree", "--charset", "ascii", path(&graph), "app"]).assert()
+        .stdout("app\n|-- api\n|   `-- core\n|       `-- util\n|           `-- api (cycle)\n`-- ui\n    `-- core (*)\n");
+    dep_sys(&dir).args(["tree", "--reverse", "--depth", "2", path(&graph), "core"]).assert()
+        .stdout("core\n├── api\n│   ├── app\n│   └── util\n└── ui\n    └── app (*)\n");
+}
+
+#[test]
+fn soft_edges_can_be_left_out_of_the_cycle_check() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c; c -> a [style=dashed]; c -> d [optional=true] }");
+    dep_sys(&dir).args(["check", path(&graph)]).assert()
+        .code(1)
+        .stderr("Circular dependency detected: a -> b -> c -> a\n");
+    dep_sys(&dir).args(["check", "--ignore-edge-attr", "style=dashed", path(&graph)]).assert()
+        .code(0)
+        .stdout("The graph has no circular dependencies\n");
+    dep_sys(&dir).args(["export", "--to", "mermaid", "--ignore-edge-attr", "style=dashed", "--include-ignored", path(&graph)]).assert()
+        .code(0)
+        .stdout(predicate::str::contains("-.->"));
+}
+
+#[test]
+fn schedules_match_hand_computed_ones() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { app -> lib; app -> ui; lib -> core; lib [cost=3]; ui [cost=2]; core [cost=2] }");
+    // One worker takes lib before ui, as lib leads to the costlier chain.
+    dep_sys(&dir).args(["schedule", path(&graph)]).assert()
+        .code(0)
+        .stdout(concat!(
+            "Makespan: 8\n",
+            "Critical path: 6, 2 less than the makespan (33.3%)\n",
+            "Worker 1: busy 8 (100.0%)\n",
+            "Timeline:\n",
+            "  0-2 worker 1: core\n",
+            "  2-5 worker 1: lib\n",
+            "  5-7 worker 1: ui\n",
+            "  7-8 worker 1: app\n",
+        ));
+    let report = json_output(dep_sys(&dir).args(["schedule", "--workers", "2", "--json", path(&graph)]), 0);
+    assert_eq!(report, json!({
+        "makespan": 6.0,
+        "critical_path": 6.0,
+        "gap": 0.0,
+        "workers": [
+            { "worker": 1, "busy": 6.0, "utilization": 1.0 },
+            { "worker": 2, "busy": 2.0, "utilization": 2.0 / 6.0 },
+        ],
+        "timeline": [
+            { "node": "core", "worker": 1, "start": 0.0, "end": 2.0 },
+            { "node": "ui", "worker": 2, "start": 0.0, "end": 2.0 },
+            { "node": "lib", "worker": 1, "start": 2.0, "end": 5.0 },
+            { "node": "app", "worker": 1, "start": 5.0, "end": 6.0 },
+        ],
+    }));
+    let cyclic = file(&dir, "cyclic.dot", "digraph { a -> b; b -> a }");
+    dep_sys(&dir).args(["schedule", path(&cyclic)]).assert().code(1);
+}
+
+#[test]
+fn the_repl_reports_bad_queries_and_goes_on() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    dep_sys(&dir).args(["repl", path(&graph)]).write_stdin("deps a\nfrobnicate\ndeps nowhere\nrdeps c\nquit\ndeps a\n").assert()
+        .code(0)
+        .stdout("b\nc\na\nb\n")
+        .stderr(predicate::str::contains("unrecognized subcommand 'frobnicate'")
+            .and(predicate::str::contains("ERROR: There is no node named nowhere")));
+}
+
+#[test]
+fn the_command_line_wins_over_the_config_file() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { c; b; a; vendor_x }");
+    file(&dir, "dep-sys.toml", concat!(
+        "[global]\n",
+        "stable = true\n",
+        "exclude = \"^vendor\"\n",
+        "bogus = 1\n",
+        "\n",
+        "[sort]\n",
+        "reverse = true\n",
+        "stable = false\n",
+    ));
+    let warning = format!("WARNING: {}:4: unknown key bogus in [global]\n", path(&dir.path().join("dep-sys.toml")));
+    // [sort] turns off the --stable of [global].
+    dep_sys(&dir).args(["sort", path(&graph)]).assert()
+        .code(0)
+        .stdout("a\nb\nc\n")
+        .stderr(warning.clone());
+    // The file is found from a directory below it, and the flags given win.
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    dep_sys(&dir).current_dir(dir.path().join("sub")).args(["sort", "--stable", path(&graph)]).assert()
+        .stdout("c\nb\na\n");
+    dep_sys(&dir).args(["sort", "--exclude", "^a", path(&graph)]).assert()
+        .stdout("vendor_x\nb\nc\n");
+    dep_sys(&dir).args(["--no-config", "sort", path(&graph)]).assert()
+        .stdout("c\nb\na\nvendor_x\n")
+        .stderr("");
+    let other = file(&dir, "other.toml", "[sort]\nstable = true\n");
+    dep_sys(&dir).args(["--config", path(&other), "sort", path(&graph)]).assert()
+        .stdout("a\nb\nc\nvendor_x\n")
+        .stderr("");
+}
+
+#[test]
+fn attributes_stay_with_their_nodes_and_edges() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { app [shape=box]; app -> lib [style=dashed]; lib -> core [weight=2]; tool -> core }");
+    dep_sys(&dir).args(["extract", "--from", "app", path(&graph)]).assert()
+        .code(0)
+        .stdout("digraph  {\n    app[shape=box]\n    lib\n    core\n    app -> lib [style=dashed]\n    lib -> core [weight=2]\n}\n");
+    let graph = file(&dir, "paths.dot", "digraph { \"app/ui\" -> \"lib/core\" [style=dashed]; \"lib/core\" -> \"lib/util\" [weight=2] }");
+    dep_sys(&dir).args(["collapse", "--by-prefix", "/", path(&graph)]).assert()
+        .code(0)
+        .stdout(predicate::str::contains("app -> lib [style=dashed]\n"));
+}
+
+#[test]
+fn progress_only_goes_to_stderr_and_is_erased() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    dep_sys(&dir).args(["--progress=always", "sort", path(&graph)]).assert()
+        .code(0)
+        .stdout("c\nb\na\n")
+        .stderr(predicate::str::starts_with("\rParsing").and(predicate::str::ends_with("\r\x1b[K")));
+    // A pipe is not a terminal, so --progress alone shows nothing here.
+    dep_sys(&dir).args(["--progress", "sort", path(&graph)]).assert()
+        .stdout("c\nb\na\n")
+        .stderr("");
+}
+
+#[test]
+fn check_add_takes_the_edge_or_only_more_edges() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    dep_sys(&dir).args(["check-add", path(&graph), "--", "c", "a"]).assert()
+        .code(1)
+        .stdout("")
+        .stderr("Adding c -> a creates a cycle: c -> a -> b -> c\n");
+    dep_sys(&dir).args(["check-add", path(&graph), "--from", "a", "--to", "c"]).assert()
+        .code(0)
+        .stdout("ok\n");
+    dep_sys(&dir).args(["check-add", path(&graph), "--edge", "c:d", "--edge", "d:a"]).assert()
+        .code(1)
+        .stderr(predicate::str::contains("Adding d -> a creates a cycle: d -> a -> b -> c -> d"));
+    dep_sys(&dir).args(["check-add", path(&graph), "--edge", "c:d", "--", "d", "a"]).assert()
+        .code(1)
+        .stderr(predicate::str::contains("Adding c -> d creates a cycle: c -> d -> a -> b -> c"));
+    dep_sys(&dir).args(["check-add", path(&graph)]).assert()
+        .code(2)
+        .stderr(predicate::str::contains("--edge <A:B>"));
+}
+
+#[test]
+fn timings_go_into_the_json_document() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    let mut report = json_output(dep_sys(&dir).args(["--timings", "check", "--json", path(&graph)]), 0);
+    let timings = report.as_object_mut().unwrap().remove("timings").unwrap();
+    assert_eq!(report, json!({ "cycles": [], "node_count": 3, "edge_count": 2 }));
+    let phases: Vec<_> = timings["seconds"].as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(phases, ["read", "parse", "graph", "analysis", "output"]);
+    assert!(timings["total_seconds"].as_f64().unwrap() > 0.0);
+    assert_eq!(timings["peak"]["nodes"], 3);
+    assert_eq!(timings["peak"]["edges"], 2);
+
+    let report = json_output(dep_sys(&dir).args(["--timings", "rdeps", "--json", path(&graph), "c"]), 0);
+    assert_eq!(report["result"], json!(["a", "b"]));
+    assert!(report["timings"].is_object());
+
+    dep_sys(&dir).args(["--timings", "check", path(&graph)]).assert()
+        .code(0)
+        .stdout("The graph has no circular dependencies\n")
+        .stderr(predicate::str::starts_with("Timings:\n  read "));
+}
+
+/// What the bash completion script offers for the words typed so far, the
+/// last one being completed.
+#[cfg(unix)]
+fn bash_completions(dir: &TempDir, words: &[&str]) -> Vec<String> {
+    let script = dep_sys(dir).args(["completions", "bash"]).assert().code(0).get_output().stdout.clone();
+    file(dir, "completions.bash", &String::from_utf8(script).unwrap());
+    let bin = assert_cmd::cargo::cargo_bin("dep-sys-rs");
+    let words: Vec<_> = words.iter().map(|word| format!("'{}'", word)).collect();
+    let driver = format!(
+        "source completions.bash
+        COMP_WORDS=({}); COMP_CWORD=$(( ${{#COMP_WORDS[@]}} - 1 ))
+        _dep_sys_rs_nodes dep-sys-rs \"${{COMP_WORDS[COMP_CWORD]}}\" \"${{COMP_WORDS[COMP_CWORD-1]}}\"
+        [ ${{#COMPREPLY[@]}} -eq 0 ] || printf '%s\\n' \"${{COMPREPLY[@]}}\"",
+        words.join(" "),
+    );
+    let path = format!("{}:{}", bin.parent().unwrap().display(), std::env::var("PATH").unwrap_or_default());
+    let output = std::process::Command::new("bash").arg("-c").arg(driver).current_dir(dir.path()).env("PATH", path).output().unwrap();
+    String::from_utf8(output.stdout).unwrap().lines().map(str::to_string).collect()
+}
+
+#[test]
+#[cfg(unix)]
+fn bash_completes_options_and_nodes_after_global_options() {
+    let dir = TempDir::new().unwrap();
+    file(&dir, "graph.dot", "digraph { alpha -> beta; beta -> gamma }");
+    assert_eq!(bash_completions(&dir, &["dep-sys-rs", "ch"]), ["check", "check-add"]);
+    assert_eq!(bash_completions(&dir, &["dep-sys-rs", "--progress", "check", "--js"]), ["--json"]);
+    assert_eq!(bash_completions(&dir, &["dep-sys-rs", "--progress", "deps", "graph.dot", "a"]), ["alpha"]);
+    assert_eq!(bash_completions(&dir, &["dep-sys-rs", "why", "graph.dot", "--from", "b"]), ["beta"]);
+    assert_eq!(bash_completions(&dir, &["dep-sys-rs", "check", "graph.dot", "a"]), Vec::<String>::new());
+}
+
+#[test]
+fn logging_leaves_stdout_alone() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c; a -> c }");
+    for args in [&["sort", path(&graph)][..], &["check", "--json", path(&graph)], &["stats", "--json", path(&graph)]] {
+        let quiet = dep_sys(&dir).args(args).assert().code(0).get_output().clone();
+        let logged = dep_sys(&dir).args(args).env("RUST_LOG", "debug").assert().code(0).get_output().clone();
+        assert_eq!(logged.stdout, quiet.stdout, "RUST_LOG=debug {:?}", args);
+        assert!(String::from_utf8_lossy(&logged.stderr).contains("DEBUG dep_sys_rs] Read "), "RUST_LOG=debug {:?}", args);
+        let verbose = dep_sys(&dir).arg("-vv").args(args).assert().code(0).get_output().clone();
+        assert_eq!(verbose.stdout, quiet.stdout, "-vv {:?}", args);
+        assert!(String::from_utf8_lossy(&verbose.stderr).contains("DEBUG depsys::graph] "), "-vv {:?}", args);
+    }
+}
+
+#[test]
+fn strict_checks_list_every_problem() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> a; a -> b; a -> b; \"my node\" -> \"b\"; b -> c }");
+    dep_sys(&dir).args(["check", "--strict", path(&graph)]).assert()
+        .code(1)
+        .stdout("self-loop: a -> a\nduplicate-edge: a -> b\n");
+    let graph = file(&dir, "quoted.dot", "digraph { \"my node\" -> \"b\"; b -> c }");
+    dep_sys(&dir).args(["sort", path(&graph)]).assert()
+        .code(0)
+        .stdout("c\nb\nmy node\n");
+}
+
+#[test]
+fn constrained_sorts_satisfy_the_graph_and_every_constraint() {
+    let dir = TempDir::new().unwrap();
+    let edges = [("app", "database"), ("app", "cache"), ("worker", "queue"), ("queue", "cache")];
+    let statements: Vec<_> = edges.iter().map(|(from, to)| format!("{} -> {}", from, to)).collect();
+    let graph = file(&dir, "graph.dot", &format!("digraph {{ {} }}", statements.join("; ")));
+    let constraints = file(&dir, "constraints.txt", "before:worker:app\n");
+    let output = dep_sys(&dir)
+        .args(["sort", path(&graph), "--constraint", "before:database:cache", "--constraints-file", path(&constraints)])
+        .assert().code(0).get_output().stdout.clone();
+    let order: Vec<_> = String::from_utf8(output).unwrap().lines().map(str::to_string).collect();
+    let position = |name: &str| order.iter().position(|other| other == name).unwrap();
+    assert_eq!(order.len(), 5);
+    for (from, to) in edges {
+        assert!(position(to) < position(from), "{} is not before {} in {:?}", to, from, order);
+    }
+    for (before, after) in [("database", "cache"), ("worker", "app")] {
+        assert!(position(before) < position(after), "{} is not before {} in {:?}", before, after, order);
+    }
+    dep_sys(&dir).args(["sort", path(&graph), "--constraint", "before:database:nowhere"]).assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains("nowhere"));
+}
+
+#[test]
+fn priorities_only_pick_among_ready_nodes() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { high [priority=10]; low [priority=1]; mid [priority=5.5]; plain; high -> low }");
+    // high has the highest priority but waits for low, which it depends on.
+    dep_sys(&dir).args(["sort", "--priority-attr", "priority", path(&graph)]).assert()
+        .code(0)
+        .stdout("mid\nlow\nhigh\nplain\n");
+    let graph = file(&dir, "bad.dot", "digraph { a [priority=soon] }");
+    dep_sys(&dir).args(["sort", "--priority-attr", "priority", path(&graph)]).assert()
+        .code(3)
+        .stderr("ERROR: Node a has priority=soon, expected a number\n");
+}
+
+#[test]
+fn sorts_only_search_again_for_cycles_closed_by_constraints() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    let searches = |constraint: Option<&str>| {
+        let mut command = dep_sys(&dir);
+        command.args(["-v", "sort", path(&graph)]).args(constraint.map(|constraint| ["--constraint", constraint]).into_iter().flatten());
+        let stderr = command.assert().get_output().stderr.clone();
+        String::from_utf8(stderr).unwrap().matches("cycle in").count()
+    };
+    assert_eq!(searches(None), 1);
+    // c already comes before b, but nothing put it before a yet.
+    assert_eq!(searches(Some("before:c:b")), 1);
+    assert_eq!(searches(Some("before:c:a")), 2);
+    dep_sys(&dir).args(["sort", path(&graph), "--constraint", "before:a:c"]).assert()
+        .code(1)
+        .stderr("ERROR: Circular dependency detected: a -> b -> c -> a, introduced by before:a:c\n");
+}
+
+#[test]
+fn the_number_of_threads_does_not_change_the_output() {
+    let dir = TempDir::new().unwrap();
+    // Forty components of two to six nodes, every third one with a cycle
+    // and some with a branch, so there is work for every thread.
+    let mut edges = Vec::new();
+    for component in 0..40 {
+        let len = 2 + component % 5;
+        for index in 0..len - 1 {
+            edges.push(format!("c{}_{} -> c{}_{}", component, index, component, index + 1));
+        }
+        if component % 3 == 0 {
+            edges.push(format!("c{}_{} -> c{}_0", component, len - 1, component));
+        }
+        if component % 4 == 1 {
+            edges.push(format!("c{}_0 -> c{}_extra", component, component));
+        }
+    }
+    let cyclic = file(&dir, "cyclic.dot", &format!("digraph {{ {} }}", edges.join("; ")));
+    edges.retain(|edge| !edge.ends_with("_0"));
+    let clean = file(&dir, "clean.dot", &format!("digraph {{ {} }}", edges.join("; ")));
+
+    for graph in [&cyclic, &clean] {
+        for args in [&["check"][..], &["check", "--json"], &["scc"], &["scc", "--all", "--json"], &["stats"], &["stats", "--json"]] {
+            let run = |threads: &str| {
+                let output = dep_sys(&dir).args(["--threads", threads]).args(args).arg(path(graph)).output().unwrap();
+                (output.status.code(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+            };
+            let one = run("1");
+            assert!(graph == &clean || !(one.1.is_empty() && one.2.is_empty()), "{:?} printed nothing", args);
+            assert_eq!(run("8"), one, "{:?} on {}", args, path(graph));
+        }
+    }
+}
+
+#[test]
+fn a_cache_is_used_until_its_input_changes() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b; b -> c }");
+    let cache = dir.path().join("graph.dot.depsys-cache");
+    let check = |verbose: bool| {
+        let mut command = dep_sys(&dir);
+        command.args(verbose.then_some("-v")).args(["check", "--cache", path(&graph)]);
+        let output = command.output().unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+    };
+    let cached = |stderr: &str| stderr.contains(&format!("from {}", path(&cache)));
+
+    let (code, stdout, stderr) = check(true);
+    assert_eq!((code, stdout.as_str()), (Some(0), "The graph has no circular dependencies\n"));
+    assert!(!cached(&stderr) && cache.exists(), "{}", stderr);
+    let (code, stdout, stderr) = check(true);
+    assert_eq!((code, stdout.as_str()), (Some(0), "The graph has no circular dependencies\n"));
+    assert!(cached(&stderr), "{}", stderr);
+
+    // Once the input is edited the cache is stale and written again.
+    fs::write(&graph, "digraph { a -> b; b -> c; c -> a }").unwrap();
+    let (code, _, stderr) = check(true);
+    assert_eq!(code, Some(1));
+    assert!(!cached(&stderr) && stderr.contains("Circular dependency detected: a -> b -> c -> a"), "{}", stderr);
+    assert!(cached(&check(true).2));
+
+    // A damaged cache is quietly loaded from the input instead, and replaced.
+    let mut damaged = fs::read(&cache).unwrap();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 1;
+    fs::write(&cache, &damaged).unwrap();
+    assert_eq!(check(false), (Some(1), String::new(), "Circular dependency detected: a -> b -> c -> a\n".to_string()));
+    assert_ne!(fs::read(&cache).unwrap(), damaged);
+    assert!(cached(&check(true).2));
+}
+
+#[test]
+fn no_cache_neither_reads_nor_writes_one() {
+    let dir = TempDir::new().unwrap();
+    let graph = file(&dir, "graph.dot", "digraph { a -> b }");
+    dep_sys(&dir).args(["check", "--cache", "--no-cache", path(&graph)]).assert().code(0);
+    dep_sys(&dir).args(["check", path(&graph)]).assert().code(0);
+    assert!(!dir.path().join("graph.dot.depsys-cache").exists());
+
+    // Nor is a cache already there read, or written again once stale.
+    dep_sys(&dir).args(["check", "--cache", path(&graph)]).assert().code(0);
+    let cache = fs::read(dir.path().join("graph.dot.depsys-cache")).unwrap();
+    dep_sys(&dir).args(["-v", "check", "--cache", "--no-cache", path(&graph)]).assert()
+        .code(0)
+        .stderr(predicate::str::contains("depsys-cache").not());
+    fs::write(&graph, "digraph { a -> b; b -> a }").unwrap();
+    dep_sys(&dir).args(["check", "--cache", "--no-cache", path(&graph)]).assert().code(1);
+    assert_eq!(fs::read(dir.path().join("graph.dot.depsys-cache")).unwrap(), cache);
+}
+
+#[test]
+fn every_command_is_described_in_the_help() {
+    let dir = TempDir::new().unwrap();
+    let help = dep_sys(&dir).arg("--help").assert().code(0).get_output().stdout.clone();
+    let help = String::from_utf8(help).unwrap();
+    let commands: Vec<_> = help.lines().skip_while(|line| *line != "Commands:").skip(1).take_while(|line| !line.is_empty()).collect();
+    assert!(commands.len() > 20, "{}", help);
+    for line in commands {
+        assert!(line.split_whitespace().count() > 1, "{:?} has no summary", line.trim());
+    }
+}
+
+#[test]
+fn critical_paths_list_the_nodes_without_slack() {
+    let dir = TempDir::new().unwrap();
+    // tool -> core is as heavy as the path through app, ui is not.
+    let graph = file(&dir, "graph.dot", "digraph { app -> lib; app -> ui; lib -> core; tool -> core; lib [cost=3]; ui [cost=2]; core [cost=2]; tool [cost=4] }");
+    dep_sys(&dir).args(["critical-path", path(&graph)]).assert()
+        .code(0)
+        .stdout("app -> lib -> core (total 6)\nSlack-free: app, lib, core, tool\n");
+    let empty = file(&dir, "empty.dot", "digraph { }");
+    dep_sys(&dir).args(["critical-path", path(&empty)]).assert().code(0).stdout("");
+}
@@ -0,0 +1,99 @@
+//! Counts what loading and sorting a graph of 100,000 nodes allocates.
+//! Each stage is run under an allocator that counts the allocations made
+//! and the most bytes held at once above what was held when it started,
+//! so the numbers are the same from run to run and machine to machine.
+//! The test is left out of the default run, as the counts are only
+//! meaningful in a release build:
+//!
+//! ```text
+//! cargo test --release --test memory -- --ignored --nocapture
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use depsys::analysis::sort_items;
+use depsys::formats::edgelist;
+use depsys::graph::Loader;
+
+/// The system allocator, counting as it goes.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static HELD: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let held = HELD.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(held, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        HELD.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// What a stage allocated.
+struct Usage {
+    allocations: usize,
+    peak: usize,
+}
+
+/// Runs `stage`, returning what it gave back and what it allocated.
+fn measure<R>(stage: impl FnOnce() -> R) -> (R, Usage) {
+    let held = HELD.load(Ordering::Relaxed);
+    PEAK.store(held, Ordering::Relaxed);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let result = stage();
+    let usage = Usage {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        peak: PEAK.load(Ordering::Relaxed) - held,
+    };
+    (result, usage)
+}
+
+/// An edge list of `len` nodes where each depends on the next and on one
+/// node further along, so there is no cycle.
+fn edge_list(len: usize) -> String {
+    let mut text = String::new();
+    for index in 0..len - 1 {
+        text.push_str(&format!("n{} n{}\n", index, index + 1));
+        let further = index + 2 + index * 7919 % 97;
+        if further < len {
+            text.push_str(&format!("n{} n{}\n", index, further));
+        }
+    }
+    text
+}
+
+#[test]
+#[ignore = "counts allocations, run it with --release"]
+fn sorting_a_hundred_thousand_nodes_allocates_no_copy_of_them() {
+    const LEN: usize = 100_000;
+    let text = edge_list(LEN);
+
+    let (loader, load) = measure(|| {
+        let mut loader = Loader::new(false);
+        edgelist::load(&mut loader, "graph.txt", &text).unwrap();
+        loader
+    });
+    let items = &loader.items;
+    let ((order, _levels), sort) = measure(|| sort_items(items, false, None).unwrap());
+    // What the sort cost when it handed back a copy of every item.
+    let (_copies, copy) = measure(|| order.iter().map(|&index| items[index].clone()).collect::<Vec<_>>());
+
+    for (stage, usage) in [("load", &load), ("sort", &sort), ("copy in order", &copy)] {
+        println!("{:<14} {:>9} allocations {:>12} bytes at most", stage, usage.allocations, usage.peak);
+    }
+    assert_eq!(order.len(), LEN);
+    // The sort allocates its working vectors, not anything per node, and
+    // holds less than the copy would on its own.
+    assert!(sort.allocations < 100, "{} allocations", sort.allocations);
+    assert!(sort.peak < copy.peak, "{} bytes against {}", sort.peak, copy.peak);
+}